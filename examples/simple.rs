@@ -1,4 +1,4 @@
-use log::*;
+use ::log::*;
 
 extern crate ds;
 
@@ -20,15 +20,11 @@ fn main() {
     ds.restart_code();
 
     ds.set_tcp_consumer(|pkt| {
-        match pkt {
-            TcpPacket::Stdout(s) => {
-                // timestamp: f32,
-                // message: String,
-                // seqnum: u16,
-                println!(">> {}", s.message);
-            }
-
-            _ => {}
+        if let TcpPacket::Stdout(s) = pkt {
+            // timestamp: f32,
+            // message: String,
+            // seqnum: u16,
+            println!(">> {}", s.message);
         }
     });
 
@@ -0,0 +1,108 @@
+//! HTTP and WebSocket driver station
+//!
+//! `ds-server --team 1234` connects to a robot and serves the API described in the `server` module, so that
+//! dashboards in a browser or in other languages can control it. It is built with the `server` feature.
+
+#[path = "../shared/connect.rs"]
+mod connect;
+
+use crate::connect::ConnectArgs;
+use ds::server::{DsServer, MIN_TOKEN_LEN};
+use ds::Result;
+
+use failure::format_err;
+use rand::distributions::Alphanumeric;
+use rand::Rng;
+use std::net::SocketAddr;
+
+const DEFAULT_LISTEN: &str = "127.0.0.1:5805";
+
+/// Environment variable holding the token, which unlike options isn't visible to other users of the machine
+const TOKEN_VAR: &str = "DS_SERVER_TOKEN";
+
+fn usage() -> String {
+    format!(
+        "Usage: ds-server (--team <number> | --target <host>) [options]\n\n{}    \
+         --listen <address>    Address to serve on. Defaults to {}\n\n\
+         Requests that control the robot need the token in ${}, of at least {} characters, or a random one \
+         printed at startup.\n",
+        connect::OPTIONS, DEFAULT_LISTEN, TOKEN_VAR, MIN_TOKEN_LEN
+    )
+}
+
+/// Takes `--listen` out of the options, leaving the options of `ds connect`
+fn parse(cli: impl Iterator<Item = String>) -> Result<(SocketAddr, ConnectArgs)> {
+    let mut listen = DEFAULT_LISTEN.to_string();
+    let mut rest = Vec::new();
+    let mut cli = cli.into_iter();
+    while let Some(arg) = cli.next() {
+        if arg == "--listen" {
+            listen = cli
+                .next()
+                .ok_or_else(|| format_err!("Missing value for --listen"))?;
+        } else {
+            rest.push(arg);
+        }
+    }
+
+    let listen = listen
+        .parse()
+        .map_err(|_| format_err!("Invalid address '{}'", listen))?;
+    Ok((listen, connect::parse_connect(rest.into_iter())?))
+}
+
+async fn run(listen: SocketAddr, args: ConnectArgs, token: String) -> Result<()> {
+    let ds = args.builder().build_async()?;
+    let server = DsServer::new(ds, token).await?;
+    server
+        .serve(listen, async {
+            let _ = tokio::signal::ctrl_c().await;
+        })
+        .await
+}
+
+fn main() {
+    env_logger::init();
+
+    let mut cli = std::env::args().skip(1).peekable();
+    match cli.peek().map(String::as_str) {
+        Some("help") | Some("--help") | Some("-h") | None => {
+            print!("{}", usage());
+            return;
+        }
+        _ => {}
+    }
+
+    let (listen, args) = match parse(cli) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            eprint!("{}\n\n{}", e, usage());
+            std::process::exit(2);
+        }
+    };
+
+    let token = std::env::var(TOKEN_VAR).unwrap_or_else(|_| {
+        let token: String = rand::thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(32)
+            .collect();
+        eprintln!("Control token: {}", token);
+        token
+    });
+    // Checked before connecting, as an empty variable would otherwise let anyone control the robot
+    if token.chars().count() < MIN_TOKEN_LEN {
+        eprintln!(
+            "${} must be at least {} characters long",
+            TOKEN_VAR, MIN_TOKEN_LEN
+        );
+        std::process::exit(2);
+    }
+
+    let result = tokio::runtime::Runtime::new()
+        .map_err(|e| format_err!("Failed to start runtime: {}", e))
+        .and_then(|mut runtime| runtime.block_on(run(listen, args, token)));
+    if let Err(e) = result {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,129 @@
+use ds::input::Key;
+
+use std::time::{Duration, Instant};
+
+/// How close together the keys of the enable chord must be pressed
+///
+/// Terminals report key presses but not releases, so holding the keys together can't be detected directly.
+const CHORD_WINDOW: Duration = Duration::from_millis(500);
+
+/// Rows scrolled by the page up and page down keys
+const PAGE: isize = 10;
+
+/// Something the user asked for with a key press
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Action {
+    Enable,
+    NextMode,
+    RestartCode,
+    /// Scrolls the console by a number of rows, positive values scroll back in time
+    Scroll(isize),
+    ClearConsole,
+    Quit,
+}
+
+/// Shortcuts listed at the bottom of the screen, leaving out `e` to fit in 80 columns
+pub const HELP: &str =
+    "Enter disable  Space E-stop  []\\ enable  m mode  r restart  \u{2191}\u{2193} scroll  q quit";
+
+/// Maps key presses to actions, enabling like the NI driver station
+///
+/// Enabling needs `[`, `]`, and `\` pressed together, which is hard to do by accident. Enter and the space bar are
+/// handled by [`SafetyKeys`](../ds/input/struct.SafetyKeys.html), and the keys of the virtual joystick by
+/// [`VirtualJoystick`](../ds/input/struct.VirtualJoystick.html), before keys get here. None of the keys here clash
+/// with those of [`VirtualJoystick::wasd`](../ds/input/struct.VirtualJoystick.html#method.wasd).
+pub struct Keymap {
+    /// When each key of the enable chord was last pressed
+    chord: [Option<Instant>; 3],
+}
+
+impl Keymap {
+    pub fn new() -> Keymap {
+        Keymap { chord: [None; 3] }
+    }
+
+    /// Returns the action for a key pressed at `now`, if there is one
+    pub fn action(&mut self, key: Key, now: Instant) -> Option<Action> {
+        let action = match key {
+            Key::Char(c @ '[') | Key::Char(c @ ']') | Key::Char(c @ '\\') => {
+                return self.press_chord(c, now)
+            }
+            Key::Char('m') => Action::NextMode,
+            Key::Char('r') => Action::RestartCode,
+            Key::Char('e') => Action::ClearConsole,
+            Key::Char('q') | Key::Interrupt => Action::Quit,
+            Key::Up => Action::Scroll(1),
+            Key::Down => Action::Scroll(-1),
+            Key::PageUp => Action::Scroll(PAGE),
+            Key::PageDown => Action::Scroll(-PAGE),
+            _ => return None,
+        };
+        Some(action)
+    }
+
+    fn press_chord(&mut self, key: char, now: Instant) -> Option<Action> {
+        let index = match key {
+            '[' => 0,
+            ']' => 1,
+            _ => 2,
+        };
+        self.chord[index] = Some(now);
+
+        let complete = self.chord.iter().all(|pressed| {
+            pressed.is_some_and(|pressed| now.duration_since(pressed) <= CHORD_WINDOW)
+        });
+        if complete {
+            self.chord = [None; 3];
+            Some(Action::Enable)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn enables_only_with_chord() {
+        let mut keymap = Keymap::new();
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+
+        assert_eq!(keymap.action(Key::Char('['), at(0)), None);
+        assert_eq!(keymap.action(Key::Char(']'), at(100)), None);
+        assert_eq!(
+            keymap.action(Key::Char('\\'), at(200)),
+            Some(Action::Enable)
+        );
+
+        // The chord starts over once it has enabled, and stale presses don't count
+        assert_eq!(keymap.action(Key::Char('\\'), at(300)), None);
+        assert_eq!(keymap.action(Key::Char('['), at(1000)), None);
+        assert_eq!(keymap.action(Key::Char(']'), at(1100)), None);
+        assert_eq!(
+            keymap.action(Key::Char('\\'), at(1200)),
+            Some(Action::Enable)
+        );
+    }
+
+    #[test]
+    fn maps_keys() {
+        let mut keymap = Keymap::new();
+        let now = Instant::now();
+        assert_eq!(keymap.action(Key::PageUp, now), Some(Action::Scroll(10)));
+        assert_eq!(keymap.action(Key::Interrupt, now), Some(Action::Quit));
+        assert_eq!(keymap.action(Key::Char('x'), now), None);
+    }
+
+    #[test]
+    fn keys_dont_clash_with_joystick() {
+        use ds::input::{KeyEvent, VirtualJoystick};
+
+        let mut joystick = VirtualJoystick::wasd();
+        for c in "[]\\mreq".chars() {
+            assert!(!joystick.handle(&KeyEvent::press(Key::Char(c)), Instant::now()));
+        }
+    }
+}
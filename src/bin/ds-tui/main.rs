@@ -0,0 +1,159 @@
+//! Terminal UI driver station
+//!
+//! `ds-tui --team 1234` connects to a robot and shows its state, battery, console output, joysticks, and connection
+//! diagnostics in panes, with the keyboard shortcuts of the NI driver station. It only needs a terminal, so it works
+//! on machines without a desktop and over SSH. It is built with the `tui` feature, and needs a unix terminal.
+//!
+//! The keyboard also drives a virtual Xbox controller on port 0, with the keys of
+//! [`VirtualJoystick::wasd`](../ds/input/struct.VirtualJoystick.html#method.wasd).
+
+#[path = "../shared/connect.rs"]
+mod connect;
+mod keys;
+mod term;
+mod ui;
+
+use crate::connect::ConnectArgs;
+use crate::keys::{Action, Keymap};
+use crate::term::RawTerminal;
+use crate::ui::{Snapshot, Ui};
+use ds::input::{read_terminal_keys, VirtualJoystick};
+use ds::{JoystickValue, Mode, Result, RobotConsole};
+
+use std::io;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How often the screen is redrawn when no keys are pressed
+const REDRAW_PERIOD: Duration = Duration::from_millis(100);
+
+/// Number of console entries kept for scrolling back
+const CONSOLE_CAPACITY: usize = 1000;
+
+/// How long a joystick key stays held after the terminal last reported it, longer than the usual key repeat delay
+const KEY_HOLD: Duration = Duration::from_millis(600);
+
+fn usage() -> String {
+    format!(
+        "Usage: ds-tui (--team <number> | --target <host>) [options]\n\n{}",
+        connect::OPTIONS
+    )
+}
+
+fn next_mode(mode: Mode) -> Mode {
+    match mode {
+        Mode::Autonomous => Mode::Teleoperated,
+        Mode::Teleoperated => Mode::Test,
+        Mode::Test => Mode::Autonomous,
+    }
+}
+
+fn run(args: ConnectArgs) -> Result<()> {
+    let mut ds = args.builder().build()?;
+
+    let console = Arc::new(Mutex::new(RobotConsole::new(CONSOLE_CAPACITY)));
+    let consumer = console.clone();
+    ds.set_tcp_consumer(move |packet| consumer.lock().unwrap().push(&packet));
+
+    // The values sent to the robot are kept here to be shown on screen
+    let keyboard = Arc::new(Mutex::new(VirtualJoystick::wasd().release_after(KEY_HOLD)));
+    let joysticks: Arc<Mutex<Vec<Vec<JoystickValue>>>> = Arc::new(Mutex::new(Vec::new()));
+    ds.set_joystick_descriptors(vec![keyboard.lock().unwrap().descriptor(0)]);
+    let (supplier, sent) = (keyboard.clone(), joysticks.clone());
+    ds.set_joystick_supplier(move || {
+        let mut keyboard = supplier.lock().unwrap();
+        keyboard.update(Instant::now());
+        let values = vec![keyboard.values()];
+        *sent.lock().unwrap() = values.clone();
+        values
+    });
+
+    let mut terminal = RawTerminal::enter()?;
+
+    // Keys are read on their own thread, where Enter and the space bar disable and E-stop the robot without waiting
+    // for the UI, and joystick keys go straight to the joystick. The channel disconnects when stdin is closed
+    let (key_tx, keys) = mpsc::channel();
+    let safety = ds.safety_keys();
+    thread::spawn(move || {
+        safety.run(read_terminal_keys(io::stdin()), |event| {
+            if !keyboard.lock().unwrap().handle(&event, Instant::now()) {
+                let _ = key_tx.send(event.key);
+            }
+        })
+    });
+
+    let mut keymap = Keymap::new();
+    let mut ui = Ui::new();
+
+    'draw: loop {
+        let status = ds.latest_status();
+        let telemetry = ds.telemetry();
+        let (width, height) = terminal.size();
+        let lines = {
+            let console = console.lock().unwrap();
+            let joysticks = joysticks.lock().unwrap();
+            let snapshot = Snapshot {
+                team_number: ds.team_number(),
+                status: &status,
+                telemetry: &telemetry,
+                console: &console,
+                joysticks: &joysticks,
+            };
+            ui.render(&snapshot, width, height)
+        };
+        terminal.draw(&lines)?;
+
+        // Handle the first key as soon as it arrives, then any others that came with it
+        let first = match keys.recv_timeout(REDRAW_PERIOD) {
+            Ok(key) => key,
+            Err(RecvTimeoutError::Timeout) => continue,
+            Err(RecvTimeoutError::Disconnected) => break,
+        };
+        for key in std::iter::once(first).chain(keys.try_iter()) {
+            match keymap.action(key, Instant::now()) {
+                Some(Action::Enable) => ds.enable(),
+                Some(Action::NextMode) => {
+                    let mode = next_mode(ds.mode());
+                    ds.set_mode(mode);
+                }
+                Some(Action::RestartCode) => ds.restart_code(),
+                Some(Action::Scroll(rows)) => ui.scroll(rows),
+                Some(Action::ClearConsole) => console.lock().unwrap().clear(),
+                Some(Action::Quit) => break 'draw,
+                None => {}
+            }
+        }
+    }
+
+    // Restore the terminal before shutting down, which disables the robot
+    drop(terminal);
+    ds.shutdown(Duration::from_secs(1))
+}
+
+fn main() {
+    env_logger::init();
+
+    let mut cli = std::env::args().skip(1).peekable();
+    match cli.peek().map(String::as_str) {
+        Some("help") | Some("--help") | Some("-h") | None => {
+            print!("{}", usage());
+            return;
+        }
+        _ => {}
+    }
+
+    let args = match connect::parse_connect(cli) {
+        Ok(args) => args,
+        Err(e) => {
+            eprint!("{}\n\n{}", e, usage());
+            std::process::exit(2);
+        }
+    };
+
+    if let Err(e) = run(args) {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,68 @@
+use std::io::{self, Write};
+use std::mem::MaybeUninit;
+
+/// The terminal, switched to raw mode and the alternate screen until this is dropped
+pub struct RawTerminal {
+    original: libc::termios,
+}
+
+impl RawTerminal {
+    /// Switches the terminal to raw mode, so that keys are read as soon as they are pressed, and hides the cursor
+    pub fn enter() -> io::Result<RawTerminal> {
+        let original = unsafe {
+            let mut original = MaybeUninit::uninit();
+            if libc::tcgetattr(libc::STDIN_FILENO, original.as_mut_ptr()) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            original.assume_init()
+        };
+
+        let mut raw = original;
+        unsafe {
+            libc::cfmakeraw(&mut raw);
+            if libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+
+        let terminal = RawTerminal { original };
+        let mut out = io::stdout();
+        write!(out, "\x1b[?1049h\x1b[?25l\x1b[2J")?;
+        out.flush()?;
+        Ok(terminal)
+    }
+
+    /// Returns the size of the terminal in columns and rows, or 80x24 if it can't be queried
+    pub fn size(&self) -> (usize, usize) {
+        let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+        let ok = unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) } == 0;
+        if ok && size.ws_col > 0 && size.ws_row > 0 {
+            (size.ws_col as usize, size.ws_row as usize)
+        } else {
+            (80, 24)
+        }
+    }
+
+    /// Draws a frame, one line per row of the terminal
+    pub fn draw(&mut self, lines: &[String]) -> io::Result<()> {
+        let mut frame = String::new();
+        for (row, line) in lines.iter().enumerate() {
+            frame.push_str(&format!("\x1b[{};1H{}", row + 1, line));
+        }
+
+        let mut out = io::stdout();
+        out.write_all(frame.as_bytes())?;
+        out.flush()
+    }
+}
+
+impl Drop for RawTerminal {
+    fn drop(&mut self) {
+        let mut out = io::stdout();
+        let _ = write!(out, "\x1b[?25h\x1b[?1049l");
+        let _ = out.flush();
+        unsafe {
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.original);
+        }
+    }
+}
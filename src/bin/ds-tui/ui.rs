@@ -0,0 +1,369 @@
+use crate::keys::HELP;
+use ds::log::DsLogRecord;
+use ds::{ConsoleEntry, ConsoleLevel, DsMode, JoystickValue, Mode, RobotConsole, RobotStatus};
+
+/// Width of the column holding the robot, diagnostics, and joystick panes
+const SIDE_WIDTH: usize = 36;
+
+/// Everything shown on screen
+pub struct Snapshot<'a> {
+    pub team_number: u32,
+    pub status: &'a RobotStatus,
+    pub telemetry: &'a DsLogRecord,
+    pub console: &'a RobotConsole,
+    pub joysticks: &'a [Vec<JoystickValue>],
+}
+
+/// A grid of characters that panes are drawn into
+struct Canvas {
+    width: usize,
+    rows: Vec<Vec<char>>,
+}
+
+impl Canvas {
+    fn new(width: usize, height: usize) -> Canvas {
+        Canvas {
+            width,
+            rows: vec![vec![' '; width]; height],
+        }
+    }
+
+    /// Writes `text` starting at `row` and `col`, cut off after `max` characters or at the edge of the canvas
+    fn text(&mut self, row: usize, col: usize, text: &str, max: usize) {
+        let row = match self.rows.get_mut(row) {
+            Some(row) => row,
+            None => return,
+        };
+        let end = (col + max).min(self.width);
+        for (cell, c) in row.iter_mut().take(end).skip(col).zip(text.chars()) {
+            *cell = if c.is_control() { ' ' } else { c };
+        }
+    }
+
+    /// Draws the border of a pane with its title
+    fn pane(&mut self, row: usize, col: usize, height: usize, width: usize, title: &str) {
+        if height < 2 || width < 2 {
+            return;
+        }
+        let inner = width - 2;
+        let border = "\u{2500}".repeat(inner);
+        self.text(row, col, &format!("\u{250c}{}\u{2510}", border), width);
+        self.text(
+            row,
+            col + 2,
+            &format!(" {} ", title),
+            inner.saturating_sub(2),
+        );
+        for r in row + 1..row + height - 1 {
+            self.text(r, col, "\u{2502}", 1);
+            self.text(r, col + width - 1, "\u{2502}", 1);
+        }
+        self.text(
+            row + height - 1,
+            col,
+            &format!("\u{2514}{}\u{2518}", border),
+            width,
+        );
+    }
+
+    /// Fills the inside of a pane with `lines`, one per row
+    fn fill(&mut self, row: usize, col: usize, height: usize, width: usize, lines: &[String]) {
+        for (i, line) in lines.iter().take(height.saturating_sub(2)).enumerate() {
+            self.text(row + 1 + i, col + 2, line, width.saturating_sub(4));
+        }
+    }
+
+    fn into_lines(self) -> Vec<String> {
+        self.rows
+            .into_iter()
+            .map(|row| row.into_iter().collect())
+            .collect()
+    }
+}
+
+/// Draws frames of the terminal UI, keeping track of how far the console has been scrolled back
+pub struct Ui {
+    /// Rows the console is scrolled back from the latest output
+    scroll: usize,
+}
+
+impl Ui {
+    pub fn new() -> Ui {
+        Ui { scroll: 0 }
+    }
+
+    /// Scrolls the console by `rows`, positive values scrolling back in time
+    pub fn scroll(&mut self, rows: isize) {
+        self.scroll = if rows < 0 {
+            self.scroll.saturating_sub(rows.unsigned_abs())
+        } else {
+            self.scroll + rows as usize
+        };
+    }
+
+    /// Renders a frame of `width` by `height` characters, as one string per row
+    pub fn render(&mut self, snapshot: &Snapshot, width: usize, height: usize) -> Vec<String> {
+        let mut canvas = Canvas::new(width, height);
+        canvas.text(
+            0,
+            0,
+            &format!(" ds-tui | Team {}", snapshot.team_number),
+            width,
+        );
+        canvas.text(height.saturating_sub(1), 0, &format!(" {}", HELP), width);
+
+        let body = height.saturating_sub(2);
+        let side = SIDE_WIDTH.min(width / 2);
+        let robot = robot_lines(snapshot.status, snapshot.telemetry);
+        let diagnostics = diagnostic_lines(snapshot.status, snapshot.telemetry);
+        let robot_height = (robot.len() + 2).min(body);
+        let diagnostics_height = (diagnostics.len() + 2).min(body - robot_height);
+        let joysticks_height = body - robot_height - diagnostics_height;
+
+        let mut row = 1;
+        canvas.pane(row, 0, robot_height, side, "Robot");
+        canvas.fill(row, 0, robot_height, side, &robot);
+        row += robot_height;
+        canvas.pane(row, 0, diagnostics_height, side, "Diagnostics");
+        canvas.fill(row, 0, diagnostics_height, side, &diagnostics);
+        row += diagnostics_height;
+        canvas.pane(row, 0, joysticks_height, side, "Joysticks");
+        canvas.fill(
+            row,
+            0,
+            joysticks_height,
+            side,
+            &joystick_lines(snapshot.joysticks),
+        );
+
+        let console = console_lines(snapshot.console);
+        let visible = body.saturating_sub(2);
+        self.scroll = self.scroll.min(console.len().saturating_sub(visible));
+        let end = console.len() - self.scroll;
+        let start = end.saturating_sub(visible);
+        let title = if self.scroll > 0 {
+            format!("Console, {} rows back", self.scroll)
+        } else {
+            "Console".to_string()
+        };
+        canvas.pane(1, side, body, width - side, &title);
+        canvas.fill(1, side, body, width - side, &console[start..end]);
+
+        canvas.into_lines()
+    }
+}
+
+fn robot_lines(status: &RobotStatus, telemetry: &DsLogRecord) -> Vec<String> {
+    let communications = if status.connected { "OK" } else { "None" };
+    let code = if status.trace.is_code_started() {
+        "Running"
+    } else {
+        "None"
+    };
+    let mode = match status.mode {
+        Mode::Autonomous => "Autonomous",
+        Mode::Teleoperated => "Teleoperated",
+        Mode::Test => "Test",
+    };
+    let state = if status.estopped {
+        "E-STOPPED"
+    } else if status.enabled {
+        "Enabled"
+    } else {
+        "Disabled"
+    };
+    let alert = if telemetry.brownout {
+        "Brownout".to_string()
+    } else if let Some(discrepancy) = status.discrepancy {
+        format!("{:?}", discrepancy.kind)
+    } else {
+        "None".to_string()
+    };
+
+    vec![
+        format!("Communications  {}", communications),
+        format!("Robot code      {}", code),
+        format!("Mode            {}", mode),
+        format!("State           {}", state),
+        format!("Battery         {:.2} V", status.battery_voltage),
+        format!("Alert           {}", alert),
+    ]
+}
+
+fn diagnostic_lines(status: &RobotStatus, telemetry: &DsLogRecord) -> Vec<String> {
+    let target = match status.ds_mode {
+        DsMode::Normal => "roboRIO",
+        DsMode::Simulation => "Simulator",
+    };
+
+    vec![
+        format!("Target          {}", target),
+        format!(
+            "Trip time       {:.1} ms",
+            telemetry.trip_time.as_secs_f32() * 1000.0
+        ),
+        format!("Packet loss     {:.0}%", telemetry.packet_loss * 100.0),
+        format!("CPU             {:.0}%", telemetry.cpu * 100.0),
+        format!("CAN             {:.0}%", telemetry.can_utilization * 100.0),
+    ]
+}
+
+fn joystick_lines(joysticks: &[Vec<JoystickValue>]) -> Vec<String> {
+    if joysticks.is_empty() {
+        return vec!["No joysticks".to_string()];
+    }
+
+    let mut lines = Vec::new();
+    for (port, joystick) in joysticks.iter().enumerate() {
+        let mut axes = Vec::new();
+        let mut buttons = Vec::new();
+        let mut povs = Vec::new();
+        for value in joystick {
+            match *value {
+                JoystickValue::Axis { value, .. } => axes.push(format!("{:+.2}", value)),
+                JoystickValue::Button { id, pressed: true } => buttons.push(id.to_string()),
+                JoystickValue::Button { .. } => {}
+                JoystickValue::POV { angle, .. } => povs.push(angle.to_string()),
+            }
+        }
+
+        lines.push(format!("{}: axes {}", port, axes.join(" ")));
+        lines.push(format!("   buttons {}", buttons.join(" ")));
+        if !povs.is_empty() {
+            lines.push(format!("   pov {}", povs.join(" ")));
+        }
+    }
+    lines
+}
+
+/// Lays out the console as rows of text, oldest first
+fn console_lines(console: &RobotConsole) -> Vec<String> {
+    let mut lines = Vec::new();
+    for entry in console.entries() {
+        match entry {
+            ConsoleEntry::Message(message) => {
+                let text = message.text.replace('\t', "    ");
+                match (message.level, message.code) {
+                    (ConsoleLevel::Print, _) | (_, None) => {
+                        lines.extend(text.lines().map(String::from))
+                    }
+                    (level, Some(code)) => {
+                        let level = if level == ConsoleLevel::Error {
+                            "ERROR"
+                        } else {
+                            "WARNING"
+                        };
+                        lines.push(format!("{} {}: {}", level, code, text));
+                        if !message.location.is_empty() {
+                            lines.push(format!("    at {}", message.location));
+                        }
+                    }
+                }
+            }
+            ConsoleEntry::Gap { missed } => lines.push(format!("... {} messages missed", missed)),
+            ConsoleEntry::Restart => lines.push("--- Robot code restarted ---".to_string()),
+        }
+    }
+    lines
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ds::{Stdout, TcpPacket, Trace};
+
+    fn status() -> RobotStatus {
+        RobotStatus {
+            connected: true,
+            enabled: false,
+            estopped: true,
+            mode: Mode::Teleoperated,
+            ds_mode: DsMode::Normal,
+            trace: Trace::IS_ROBORIO | Trace::DISABLED,
+            battery_voltage: 12.5,
+            discrepancy: None,
+        }
+    }
+
+    fn console(lines: u16) -> RobotConsole {
+        let mut console = RobotConsole::new(100);
+        for i in 0..lines {
+            console.push(&TcpPacket::Stdout(Stdout {
+                timestamp: i as f32,
+                message: format!("line {}", i),
+                seqnum: i,
+                raw: Vec::new(),
+            }));
+        }
+        console
+    }
+
+    #[test]
+    fn renders_panes() {
+        let status = status();
+        let telemetry = DsLogRecord::default();
+        let console = console(3);
+        let joysticks = vec![vec![
+            JoystickValue::Axis { id: 0, value: 0.5 },
+            JoystickValue::Button {
+                id: 2,
+                pressed: true,
+            },
+        ]];
+        let snapshot = Snapshot {
+            team_number: 1234,
+            status: &status,
+            telemetry: &telemetry,
+            console: &console,
+            joysticks: &joysticks,
+        };
+
+        let lines = Ui::new().render(&snapshot, 100, 30);
+        assert_eq!(lines.len(), 30);
+        assert!(lines.iter().all(|line| line.chars().count() == 100));
+        let screen = lines.join("\n");
+        for text in &[
+            "Team 1234",
+            "E-STOPPED",
+            "12.50 V",
+            "Packet loss     0%",
+            "0: axes +0.50",
+            "buttons 2",
+            "line 2",
+        ] {
+            assert!(screen.contains(text), "missing {}", text);
+        }
+
+        // Tiny terminals are cut off rather than panicking
+        assert_eq!(Ui::new().render(&snapshot, 10, 3).len(), 3);
+    }
+
+    #[test]
+    fn scrolls_console() {
+        let status = status();
+        let telemetry = DsLogRecord::default();
+        let console = console(40);
+        let snapshot = Snapshot {
+            team_number: 1234,
+            status: &status,
+            telemetry: &telemetry,
+            console: &console,
+            joysticks: &[],
+        };
+
+        let mut ui = Ui::new();
+        let screen = ui.render(&snapshot, 80, 24).join("\n");
+        assert!(screen.contains("line 39") && !screen.contains("line 19"));
+
+        ui.scroll(10);
+        let screen = ui.render(&snapshot, 80, 24).join("\n");
+        assert!(screen.contains("line 29") && !screen.contains("line 39"));
+
+        // Scrolling stops at the oldest line
+        ui.scroll(100);
+        let screen = ui.render(&snapshot, 80, 24).join("\n");
+        assert!(screen.contains("line 0") && screen.contains("rows back"));
+        ui.scroll(-1000);
+        assert!(ui.render(&snapshot, 80, 24).join("\n").contains("line 39"));
+    }
+}
@@ -0,0 +1,63 @@
+use crate::connect::{self, ConnectArgs};
+use ds::Result;
+
+use failure::bail;
+
+/// Returns the help printed by `ds help`
+pub fn usage() -> String {
+    format!(
+        "Usage: ds connect (--team <number> | --target <host>) [options]\n\n{}",
+        connect::OPTIONS
+    )
+}
+
+/// The subcommand requested on the command line
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Connect(ConnectArgs),
+    Help,
+}
+
+/// Parses the arguments following the binary name
+pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Command> {
+    let mut args = args.into_iter();
+    match args.next().as_deref() {
+        Some("connect") => connect::parse_connect(args).map(Command::Connect),
+        Some("help") | Some("--help") | Some("-h") | None => Ok(Command::Help),
+        Some(other) => bail!("Unknown command '{}'", other),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ds::{Alliance, Mode};
+
+    fn args(s: &str) -> Vec<String> {
+        s.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn parses_command() {
+        let command = parse(args("connect --team 1234 --alliance blue2")).unwrap();
+        assert_eq!(
+            command,
+            Command::Connect(ConnectArgs {
+                team: Some(1234),
+                target: None,
+                alliance: Alliance::new_blue(2),
+                mode: Mode::Teleoperated,
+                log_dir: None,
+                capture: None,
+                sim_detection: true,
+            })
+        );
+        assert_eq!(parse(args("")).unwrap(), Command::Help);
+    }
+
+    #[test]
+    fn rejects_bad_commands() {
+        assert!(parse(args("connect")).is_err());
+        assert!(parse(args("disconnect")).is_err());
+    }
+}
@@ -0,0 +1,87 @@
+use crate::connect::{parse_alliance, parse_mode};
+use ds::{Alliance, Mode, Result};
+
+use failure::bail;
+
+pub const HELP: &str = "\
+Commands:
+    enable, e            Enable the robot
+    disable, d, <enter>  Disable the robot
+    estop                Emergency stop the robot until its code is restarted
+    mode <mode>          Switch to auto, teleop, or test
+    alliance <station>   Switch alliance station, e.g. red1 or blue3
+    gsm <message>        Set the game specific message
+    restart-code         Restart robot code
+    reboot               Reboot the roboRIO
+    help                 Show this message
+    quit                 Disable the robot and exit";
+
+/// A command typed at the interactive prompt
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Enable,
+    Disable,
+    Estop,
+    Mode(Mode),
+    Alliance(Alliance),
+    GameData(String),
+    RestartCode,
+    Reboot,
+    Help,
+    Quit,
+}
+
+impl Command {
+    /// Parses a line typed at the prompt. A blank line disables the robot, so that Enter always works as a stop button
+    pub fn parse(line: &str) -> Result<Command> {
+        let line = line.trim();
+        let (name, rest) = match line.find(char::is_whitespace) {
+            Some(i) => (&line[..i], line[i..].trim()),
+            None => (line, ""),
+        };
+
+        let command = match name.to_lowercase().as_str() {
+            "" | "d" | "disable" => Command::Disable,
+            "e" | "enable" => Command::Enable,
+            "estop" => Command::Estop,
+            "mode" => Command::Mode(parse_mode(rest)?),
+            "alliance" => Command::Alliance(parse_alliance(rest)?),
+            "gsm" => Command::GameData(rest.to_string()),
+            "restart-code" => Command::RestartCode,
+            "reboot" => Command::Reboot,
+            "help" | "?" => Command::Help,
+            "quit" | "exit" | "q" => Command::Quit,
+            _ => bail!("Unknown command '{}', type help for a list", name),
+        };
+        Ok(command)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parses_commands() {
+        assert_eq!(Command::parse("").unwrap(), Command::Disable);
+        assert_eq!(Command::parse("  E ").unwrap(), Command::Enable);
+        assert_eq!(
+            Command::parse("mode test").unwrap(),
+            Command::Mode(Mode::Test)
+        );
+        assert_eq!(
+            Command::parse("alliance blue1").unwrap(),
+            Command::Alliance(Alliance::new_blue(1))
+        );
+        assert_eq!(
+            Command::parse("gsm  LRL").unwrap(),
+            Command::GameData("LRL".to_string())
+        );
+        assert_eq!(
+            Command::parse("restart-code").unwrap(),
+            Command::RestartCode
+        );
+        assert!(Command::parse("mode").is_err());
+        assert!(Command::parse("fly").is_err());
+    }
+}
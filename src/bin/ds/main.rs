@@ -0,0 +1,192 @@
+//! Command line driver station
+//!
+//! `ds connect --team 1234` connects to a robot, then reads commands from stdin while showing a status line and the
+//! robot's console output. Everything is plain text on stdin and stdout, so it works over SSH on a headless machine.
+
+mod args;
+mod command;
+#[path = "../shared/connect.rs"]
+mod connect;
+
+use crate::args::Command as CliCommand;
+use crate::command::{Command, HELP};
+use crate::connect::ConnectArgs;
+use ds::{Mode, Result, RobotStatus, TcpPacket};
+
+use std::io::{self, BufRead, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// Writes console output above a status line that is redrawn in place
+struct Terminal {
+    status: String,
+}
+
+impl Terminal {
+    /// Prints a full line, keeping the status line below it
+    fn println(&mut self, line: &str) {
+        let mut out = io::stdout();
+        let _ = write!(out, "\r\x1b[2K{}\n{}", line, self.status);
+        let _ = out.flush();
+    }
+
+    fn set_status(&mut self, status: String) {
+        if status != self.status {
+            self.status = status;
+            let mut out = io::stdout();
+            let _ = write!(out, "\r\x1b[2K{}", self.status);
+            let _ = out.flush();
+        }
+    }
+}
+
+fn format_status(status: &RobotStatus) -> String {
+    if !status.connected {
+        return "No robot communication".to_string();
+    }
+
+    let mode = match status.mode {
+        Mode::Autonomous => "Auto",
+        Mode::Teleoperated => "Teleop",
+        Mode::Test => "Test",
+    };
+    let state = if status.estopped {
+        "ESTOPPED"
+    } else if status.enabled {
+        "Enabled"
+    } else {
+        "Disabled"
+    };
+    let code = if status.trace.is_code_started() {
+        "Robot code"
+    } else {
+        "No robot code"
+    };
+
+    let mut line = format!(
+        "{:>6.2}V | {} {} | {}",
+        status.battery_voltage, mode, state, code
+    );
+    if let Some(discrepancy) = status.discrepancy {
+        line.push_str(&format!(" | {:?}", discrepancy.kind));
+    }
+    line
+}
+
+fn connect(args: ConnectArgs) -> Result<()> {
+    let mut ds = args.builder().build()?;
+    let terminal = Arc::new(Mutex::new(Terminal {
+        status: String::new(),
+    }));
+
+    let console = terminal.clone();
+    ds.set_tcp_consumer(move |packet| {
+        let mut terminal = console.lock().unwrap();
+        match packet {
+            TcpPacket::Stdout(stdout) => {
+                for line in stdout.message.lines() {
+                    terminal.println(line);
+                }
+            }
+            TcpPacket::ErrorMessage(error) => {
+                let level = if error.is_error { "ERROR" } else { "WARNING" };
+                terminal.println(&format!(
+                    "{} {}: {} {}",
+                    level, error.code, error.details, error.location
+                ));
+            }
+            TcpPacket::Dummy => {}
+        }
+    });
+
+    let ds = Arc::new(Mutex::new(ds));
+    let running = Arc::new(AtomicBool::new(true));
+    let status_ds = ds.clone();
+    let status_terminal = terminal.clone();
+    let status_running = running.clone();
+    let status_thread = thread::spawn(move || {
+        while status_running.load(Ordering::Relaxed) {
+            let status = status_ds.lock().unwrap().latest_status();
+            status_terminal
+                .lock()
+                .unwrap()
+                .set_status(format_status(&status));
+            thread::sleep(Duration::from_millis(100));
+        }
+    });
+
+    terminal
+        .lock()
+        .unwrap()
+        .println("Type help for a list of commands");
+    let stdin = io::stdin();
+    for line in stdin.lock().lines() {
+        let line = line?;
+        let command = match Command::parse(&line) {
+            Ok(command) => command,
+            Err(e) => {
+                terminal.lock().unwrap().println(&e.to_string());
+                continue;
+            }
+        };
+
+        let mut ds = ds.lock().unwrap();
+        match command {
+            Command::Enable => ds.enable(),
+            Command::Disable => ds.disable(),
+            Command::Estop => ds.estop(),
+            Command::Mode(mode) => ds.set_mode(mode),
+            Command::Alliance(alliance) => ds.set_alliance(alliance),
+            Command::GameData(message) => {
+                if let Err(e) = ds.set_game_specific_message(&message) {
+                    terminal.lock().unwrap().println(&e.to_string());
+                }
+            }
+            Command::RestartCode => ds.restart_code(),
+            Command::Reboot => ds.restart_roborio(),
+            Command::Help => {
+                let mut terminal = terminal.lock().unwrap();
+                for line in HELP.lines() {
+                    terminal.println(line);
+                }
+            }
+            Command::Quit => break,
+        }
+    }
+
+    // Reached on quit or when stdin closes, shutting down disables the robot before exiting
+    terminal
+        .lock()
+        .unwrap()
+        .println("Disabling and disconnecting");
+    running.store(false, Ordering::Relaxed);
+    let _ = status_thread.join();
+    println!();
+    match Arc::try_unwrap(ds) {
+        Ok(ds) => ds.into_inner().unwrap().shutdown(Duration::from_secs(1)),
+        Err(_) => unreachable!("the status thread has exited"),
+    }
+}
+
+fn main() {
+    env_logger::init();
+
+    let result = match args::parse(std::env::args().skip(1)) {
+        Ok(CliCommand::Connect(args)) => connect(args),
+        Ok(CliCommand::Help) => {
+            print!("{}", args::usage());
+            Ok(())
+        }
+        Err(e) => {
+            eprint!("{}\n\n{}", e, args::usage());
+            std::process::exit(2);
+        }
+    };
+
+    if let Err(e) = result {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+}
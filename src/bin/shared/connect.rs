@@ -0,0 +1,161 @@
+//! Options shared by `ds connect`, `ds-tui` and `ds-server`
+
+use ds::{Alliance, DriverStation, DriverStationBuilder, Mode, Result};
+
+use failure::{bail, format_err};
+use std::path::PathBuf;
+
+/// Help for the options accepted by [`parse_connect`]
+pub const OPTIONS: &str = "\
+Options:
+    --team <number>       Team number, used to find the roboRIO at 10.TE.AM.2
+    --target <host>       Address of the roboRIO, overriding the team number
+    --alliance <station>  Alliance station, e.g. red1 or blue3. Defaults to red1
+    --mode <mode>         Mode to enable in: auto, teleop, or test. Defaults to teleop
+    --log-dir <dir>       Write .dslog and .dsevents logs to <dir>
+    --capture <file>      Record every packet to a pcap file
+    --no-sim              Don't switch to the WPILib simulator when it is running
+";
+
+/// Options given to `ds connect`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConnectArgs {
+    pub team: Option<u32>,
+    pub target: Option<String>,
+    pub alliance: Alliance,
+    pub mode: Mode,
+    pub log_dir: Option<PathBuf>,
+    pub capture: Option<PathBuf>,
+    pub sim_detection: bool,
+}
+
+impl ConnectArgs {
+    /// Returns a driver station builder configured with these options
+    pub fn builder(&self) -> DriverStationBuilder {
+        let mut builder = DriverStation::builder()
+            .alliance(self.alliance)
+            .mode(self.mode)
+            .simulation_detection(self.sim_detection);
+        if let Some(team) = self.team {
+            builder = builder.team(team);
+        }
+        if let Some(ref target) = self.target {
+            builder = builder.target(target.clone());
+        }
+        if let Some(ref dir) = self.log_dir {
+            builder = builder.log_dir(dir.clone());
+        }
+        if let Some(ref path) = self.capture {
+            builder = builder.capture(path.clone());
+        }
+        builder
+    }
+}
+
+/// Parses the options of `ds connect`, which are also the options of `ds-tui` and `ds-server`
+pub fn parse_connect(mut args: impl Iterator<Item = String>) -> Result<ConnectArgs> {
+    let mut connect = ConnectArgs {
+        team: None,
+        target: None,
+        alliance: Alliance::new_red(1),
+        mode: Mode::Teleoperated,
+        log_dir: None,
+        capture: None,
+        sim_detection: true,
+    };
+
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .ok_or_else(|| format_err!("Missing value for {}", arg))
+        };
+        match arg.as_str() {
+            "--team" => {
+                let team = value()?;
+                connect.team = Some(
+                    team.parse()
+                        .map_err(|_| format_err!("Invalid team number '{}'", team))?,
+                );
+            }
+            "--target" => connect.target = Some(value()?),
+            "--alliance" => connect.alliance = parse_alliance(&value()?)?,
+            "--mode" => connect.mode = parse_mode(&value()?)?,
+            "--log-dir" => connect.log_dir = Some(value()?.into()),
+            "--capture" => connect.capture = Some(value()?.into()),
+            "--no-sim" => connect.sim_detection = false,
+            _ => bail!("Unknown option '{}'", arg),
+        }
+    }
+
+    if connect.team.is_none() && connect.target.is_none() {
+        bail!("Either --team or --target is required");
+    }
+    Ok(connect)
+}
+
+/// Parses an alliance station such as `red2` or `blue3`
+pub fn parse_alliance(s: &str) -> Result<Alliance> {
+    let s = s.to_lowercase();
+    let (colour, position) = if let Some(position) = s.strip_prefix("red") {
+        (Alliance::new_red as fn(u8) -> Alliance, position)
+    } else if let Some(position) = s.strip_prefix("blue") {
+        (Alliance::new_blue as fn(u8) -> Alliance, position)
+    } else {
+        bail!(
+            "Invalid alliance station '{}', expected e.g. red1 or blue3",
+            s
+        );
+    };
+
+    match position.parse() {
+        Ok(position @ 1..=3) => Ok(colour(position)),
+        _ => bail!("Invalid alliance station '{}', positions are 1 to 3", s),
+    }
+}
+
+/// Parses a robot mode, accepting the usual abbreviations
+pub fn parse_mode(s: &str) -> Result<Mode> {
+    match s.to_lowercase().as_str() {
+        "auto" | "autonomous" => Ok(Mode::Autonomous),
+        "teleop" | "teleoperated" => Ok(Mode::Teleoperated),
+        "test" => Ok(Mode::Test),
+        _ => bail!("Invalid mode '{}', expected auto, teleop, or test", s),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn args(s: &str) -> impl Iterator<Item = String> + '_ {
+        s.split_whitespace().map(String::from)
+    }
+
+    #[test]
+    fn parses_connect() {
+        let connect =
+            parse_connect(args("--team 1234 --alliance blue2 --mode auto --no-sim")).unwrap();
+        assert_eq!(
+            connect,
+            ConnectArgs {
+                team: Some(1234),
+                target: None,
+                alliance: Alliance::new_blue(2),
+                mode: Mode::Autonomous,
+                log_dir: None,
+                capture: None,
+                sim_detection: false,
+            }
+        );
+    }
+
+    #[test]
+    fn rejects_bad_arguments() {
+        assert!(parse_connect(args("")).is_err());
+        assert!(parse_connect(args("--team")).is_err());
+        assert!(parse_connect(args("--team abc")).is_err());
+        assert!(parse_connect(args("--team 1 --alliance red4")).is_err());
+        assert!(parse_connect(args("--team 1 --alliance green1")).is_err());
+        assert!(parse_connect(args("--team 1 --speed 11")).is_err());
+    }
+}
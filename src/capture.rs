@@ -0,0 +1,820 @@
+//! Recording driver station traffic to pcap files, and replaying it offline
+//!
+//! Captures are enabled with [`DriverStationBuilder::capture`](../struct.DriverStationBuilder.html#method.capture).
+//! Every frame sent or received is written with the time it was seen, wrapped in synthesized IPv4 and UDP or TCP
+//! headers so that the file can also be opened in Wireshark. Frames are recorded as they came off the socket, before
+//! they are decoded, so malformed packets are captured too.
+//!
+//! [`read_capture`](fn.read_capture.html) and [`replay`](fn.replay.html) turn a capture back into the packets the
+//! driver station saw, and [`replay_telemetry`](fn.replay_telemetry.html) into the `.dslog` records it logged.
+
+use crate::ds::state::RecvState;
+use crate::ds::Clock;
+use crate::log::{DsLogRecord, RECORD_PERIOD};
+use crate::proto::tcp::DsTcpCodec;
+use crate::proto::udp::outbound::types::Control;
+use crate::{Ports, Result, TcpPacket, UdpControlPacket, UdpResponsePacket};
+
+use byteorder::{BigEndian, ByteOrder, LittleEndian, WriteBytesExt};
+use bytes::{Bytes, BytesMut};
+use chrono::{DateTime, Duration as ChronoDuration, TimeZone, Utc};
+use failure::bail;
+use log::*;
+use std::io::{self, Read, Write};
+use std::net::Ipv4Addr;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::Instant;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_util::codec::{Decoder, Encoder};
+
+const PCAP_MAGIC: u32 = 0xa1b2_c3d4;
+const PCAP_MAGIC_NANOS: u32 = 0xa1b2_3c4d;
+const LINKTYPE_ETHERNET: u32 = 1;
+const LINKTYPE_RAW: u32 = 101;
+const SNAPLEN: u32 = 65535;
+
+const PROTO_TCP: u8 = 6;
+const PROTO_UDP: u8 = 17;
+
+/// Which way a captured frame was travelling
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Direction {
+    ToRobot,
+    FromRobot,
+}
+
+/// The protocol a captured frame was sent over
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Transport {
+    Udp,
+    Tcp,
+}
+
+/// A single frame read back from a capture, without any of its network headers
+#[derive(Debug, Clone, PartialEq)]
+pub struct CapturedFrame {
+    pub timestamp: DateTime<Utc>,
+    pub direction: Direction,
+    pub transport: Transport,
+    pub data: Vec<u8>,
+}
+
+/// Writes IPv4 packets to a pcap file with the raw IP link type
+pub struct PcapWriter<W: Write> {
+    inner: W,
+}
+
+impl<W: Write> PcapWriter<W> {
+    /// Writes the pcap file header to `inner`
+    pub fn new(mut inner: W) -> io::Result<PcapWriter<W>> {
+        inner.write_u32::<LittleEndian>(PCAP_MAGIC)?;
+        inner.write_u16::<LittleEndian>(2)?; // major version
+        inner.write_u16::<LittleEndian>(4)?; // minor version
+        inner.write_i32::<LittleEndian>(0)?; // timezone offset, always UTC
+        inner.write_u32::<LittleEndian>(0)?; // timestamp accuracy
+        inner.write_u32::<LittleEndian>(SNAPLEN)?;
+        inner.write_u32::<LittleEndian>(LINKTYPE_RAW)?;
+        Ok(PcapWriter { inner })
+    }
+
+    /// Writes a single IPv4 packet seen at `timestamp`, and flushes it
+    pub fn write_packet(&mut self, timestamp: DateTime<Utc>, packet: &[u8]) -> io::Result<()> {
+        let len = packet.len().min(SNAPLEN as usize);
+        self.inner
+            .write_u32::<LittleEndian>(timestamp.timestamp() as u32)?;
+        self.inner
+            .write_u32::<LittleEndian>(timestamp.timestamp_subsec_micros().min(999_999))?;
+        self.inner.write_u32::<LittleEndian>(len as u32)?;
+        self.inner.write_u32::<LittleEndian>(packet.len() as u32)?;
+        self.inner.write_all(&packet[..len])?;
+        self.inner.flush()
+    }
+
+    /// Returns the underlying writer
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+/// The addresses written into the synthesized headers of captured frames
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct Endpoints {
+    pub ds: Ipv4Addr,
+    pub robot: Ipv4Addr,
+    pub ports: Ports,
+}
+
+struct TapState {
+    writer: PcapWriter<Box<dyn Write + Send>>,
+    /// Next TCP sequence numbers, to and from the robot
+    tcp_seq: [u32; 2],
+}
+
+/// Shared handle that every network task records its traffic through
+#[derive(Clone)]
+pub(crate) struct Tap {
+    state: Arc<Mutex<TapState>>,
+    endpoints: Endpoints,
+    clock: Arc<dyn Clock>,
+}
+
+impl Tap {
+    pub fn new(
+        writer: Box<dyn Write + Send>,
+        endpoints: Endpoints,
+        clock: Arc<dyn Clock>,
+    ) -> io::Result<Tap> {
+        Ok(Tap {
+            state: Arc::new(Mutex::new(TapState {
+                writer: PcapWriter::new(writer)?,
+                tcp_seq: [0, 0],
+            })),
+            endpoints,
+            clock,
+        })
+    }
+
+    /// Writes a frame to the capture. `ds_port` is the port used on the driver station's side
+    pub fn record(&self, direction: Direction, transport: Transport, ds_port: u16, data: &[u8]) {
+        let timestamp = self.clock.now();
+        let mut state = self.state.lock().unwrap();
+        let robot_port = match transport {
+            Transport::Udp => self.endpoints.ports.udp_tx,
+            Transport::Tcp => self.endpoints.ports.tcp,
+        };
+        let ds = (self.endpoints.ds, ds_port);
+        let robot = (self.endpoints.robot, robot_port);
+        let (src, dst) = match direction {
+            Direction::ToRobot => (ds, robot),
+            Direction::FromRobot => (robot, ds),
+        };
+
+        let transport_header = match transport {
+            Transport::Udp => udp_header(src.1, dst.1, data.len()),
+            Transport::Tcp => {
+                let (ours, theirs) = match direction {
+                    Direction::ToRobot => (0, 1),
+                    Direction::FromRobot => (1, 0),
+                };
+                let header = tcp_header(src.1, dst.1, state.tcp_seq[ours], state.tcp_seq[theirs]);
+                state.tcp_seq[ours] = state.tcp_seq[ours].wrapping_add(data.len() as u32);
+                header
+            }
+        };
+
+        let protocol = match transport {
+            Transport::Udp => PROTO_UDP,
+            Transport::Tcp => PROTO_TCP,
+        };
+        let mut packet = ipv4_header(src.0, dst.0, protocol, transport_header.len() + data.len());
+        packet.extend(transport_header);
+        packet.extend_from_slice(data);
+
+        if let Err(e) = state.writer.write_packet(timestamp, &packet) {
+            warn!("Failed to write to capture: {}", e);
+        }
+    }
+}
+
+fn ipv4_header(src: Ipv4Addr, dst: Ipv4Addr, protocol: u8, payload_len: usize) -> Vec<u8> {
+    let mut buf = vec![0x45, 0]; // version 4, 20 byte header, no DSCP
+    buf.write_u16::<BigEndian>(20 + payload_len as u16).unwrap();
+    buf.extend_from_slice(&[0, 0, 0x40, 0]); // identification, don't fragment
+    buf.push(64); // ttl
+    buf.push(protocol);
+    buf.extend_from_slice(&[0, 0]); // checksum, filled in below
+    buf.extend_from_slice(&src.octets());
+    buf.extend_from_slice(&dst.octets());
+
+    let mut sum = 0u32;
+    for word in buf.chunks(2) {
+        sum += u32::from(BigEndian::read_u16(word));
+    }
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    BigEndian::write_u16(&mut buf[10..12], !(sum as u16));
+
+    buf
+}
+
+fn udp_header(src_port: u16, dst_port: u16, payload_len: usize) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(8);
+    buf.write_u16::<BigEndian>(src_port).unwrap();
+    buf.write_u16::<BigEndian>(dst_port).unwrap();
+    buf.write_u16::<BigEndian>(8 + payload_len as u16).unwrap();
+    buf.write_u16::<BigEndian>(0).unwrap(); // no checksum
+    buf
+}
+
+fn tcp_header(src_port: u16, dst_port: u16, seq: u32, ack: u32) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(20);
+    buf.write_u16::<BigEndian>(src_port).unwrap();
+    buf.write_u16::<BigEndian>(dst_port).unwrap();
+    buf.write_u32::<BigEndian>(seq).unwrap();
+    buf.write_u32::<BigEndian>(ack).unwrap();
+    buf.push(5 << 4); // 20 byte header
+    buf.push(0x18); // PSH, ACK
+    buf.write_u16::<BigEndian>(0xffff).unwrap(); // window
+    buf.write_u32::<BigEndian>(0).unwrap(); // checksum and urgent pointer
+    buf
+}
+
+/// Codec wrapper that records every UDP datagram passing through it to a capture
+///
+/// `UdpFramed` hands the codec one whole datagram at a time, so the datagram is recorded as it was received, before
+/// the inner codec gets a chance to reject it.
+pub(crate) struct Tapped<C> {
+    inner: C,
+    tap: Option<Tap>,
+    ds_port: u16,
+}
+
+impl<C> Tapped<C> {
+    pub fn new(inner: C, tap: Option<Tap>, ds_port: u16) -> Tapped<C> {
+        Tapped {
+            inner,
+            tap,
+            ds_port,
+        }
+    }
+}
+
+impl<C: Decoder> Decoder for Tapped<C> {
+    type Item = C::Item;
+    type Error = C::Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> std::result::Result<Option<C::Item>, C::Error> {
+        if let Some(ref tap) = self.tap {
+            if !src.is_empty() {
+                tap.record(Direction::FromRobot, Transport::Udp, self.ds_port, src);
+            }
+        }
+        self.inner.decode(src)
+    }
+}
+
+impl<C: Encoder> Encoder for Tapped<C> {
+    type Item = C::Item;
+    type Error = C::Error;
+
+    fn encode(&mut self, item: C::Item, dst: &mut BytesMut) -> std::result::Result<(), C::Error> {
+        let start = dst.len();
+        self.inner.encode(item, dst)?;
+        if let Some(ref tap) = self.tap {
+            tap.record(
+                Direction::ToRobot,
+                Transport::Udp,
+                self.ds_port,
+                &dst[start..],
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Stream wrapper that records every TCP read and write to a capture, before anything is decoded
+pub(crate) struct TappedStream<S> {
+    inner: S,
+    tap: Option<Tap>,
+    ds_port: u16,
+}
+
+impl<S> TappedStream<S> {
+    pub fn new(inner: S, tap: Option<Tap>, ds_port: u16) -> TappedStream<S> {
+        TappedStream {
+            inner,
+            tap,
+            ds_port,
+        }
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for TappedStream<S> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let n = futures::ready!(Pin::new(&mut self.inner).poll_read(cx, buf))?;
+        if let Some(ref tap) = self.tap {
+            if n > 0 {
+                tap.record(
+                    Direction::FromRobot,
+                    Transport::Tcp,
+                    self.ds_port,
+                    &buf[..n],
+                );
+            }
+        }
+        Poll::Ready(Ok(n))
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for TappedStream<S> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let n = futures::ready!(Pin::new(&mut self.inner).poll_write(cx, buf))?;
+        if let Some(ref tap) = self.tap {
+            if n > 0 {
+                tap.record(Direction::ToRobot, Transport::Tcp, self.ds_port, &buf[..n]);
+            }
+        }
+        Poll::Ready(Ok(n))
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+/// Reads every UDP and TCP frame exchanged with the roboRIO from a pcap file
+///
+/// Both captures written by this crate and Ethernet captures taken with other tools are supported. Frames are
+/// attributed to the robot or the driver station using `ports`, and anything else in the capture is skipped, as are
+/// records with an invalid timestamp. Records larger than the snapshot length of the file, or than the largest IP
+/// packet, are rejected as corrupt rather than read.
+pub fn read_capture(mut reader: impl Read, ports: Ports) -> Result<Vec<CapturedFrame>> {
+    let mut header = [0; 24];
+    reader.read_exact(&mut header)?;
+    let (big_endian, nanos) = match (
+        LittleEndian::read_u32(&header[0..4]),
+        BigEndian::read_u32(&header[0..4]),
+    ) {
+        (PCAP_MAGIC, _) => (false, false),
+        (PCAP_MAGIC_NANOS, _) => (false, true),
+        (_, PCAP_MAGIC) => (true, false),
+        (_, PCAP_MAGIC_NANOS) => (true, true),
+        _ => bail!("Not a pcap file"),
+    };
+    let read_u32 = |buf: &[u8]| {
+        if big_endian {
+            BigEndian::read_u32(buf)
+        } else {
+            LittleEndian::read_u32(buf)
+        }
+    };
+    let snaplen = read_u32(&header[16..20]).min(SNAPLEN) as usize;
+    let link_type = read_u32(&header[20..24]);
+    let link_header = match link_type {
+        LINKTYPE_RAW => 0,
+        LINKTYPE_ETHERNET => 14,
+        other => bail!("Unsupported pcap link type {}", other),
+    };
+
+    let mut frames = Vec::new();
+    let mut record = [0; 16];
+    loop {
+        match reader.read_exact(&mut record) {
+            Ok(()) => {}
+            Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+            Err(e) => return Err(e.into()),
+        }
+        let secs = read_u32(&record[0..4]);
+        let frac = read_u32(&record[4..8]);
+        let caplen = read_u32(&record[8..12]) as usize;
+        if caplen > snaplen {
+            bail!(
+                "Capture record of {} bytes is longer than the snapshot length of {}",
+                caplen,
+                snaplen
+            );
+        }
+        let mut packet = vec![0; caplen];
+        reader.read_exact(&mut packet)?;
+
+        let nanos = if nanos {
+            Some(frac)
+        } else {
+            frac.checked_mul(1000)
+        };
+        let timestamp = nanos.and_then(|nanos| Utc.timestamp_opt(i64::from(secs), nanos).single());
+        let frame = packet
+            .get(link_header..)
+            .and_then(|ip| strip_headers(ip, ports));
+        if let (Some(timestamp), Some((direction, transport, data))) = (timestamp, frame) {
+            frames.push(CapturedFrame {
+                timestamp,
+                direction,
+                transport,
+                data,
+            });
+        }
+    }
+
+    Ok(frames)
+}
+
+/// Removes the IPv4 and transport headers from `packet`, if it's part of a conversation with the roboRIO
+fn strip_headers(packet: &[u8], ports: Ports) -> Option<(Direction, Transport, Vec<u8>)> {
+    if packet.len() < 20 || packet[0] >> 4 != 4 {
+        return None;
+    }
+    let ip_header = usize::from(packet[0] & 0x0f) * 4;
+    let total_len = usize::from(BigEndian::read_u16(&packet[2..4])).min(packet.len());
+    let protocol = packet[9];
+    let segment = packet.get(ip_header..total_len)?;
+    if segment.len() < 8 {
+        return None;
+    }
+    let src_port = BigEndian::read_u16(&segment[0..2]);
+    let dst_port = BigEndian::read_u16(&segment[2..4]);
+
+    match protocol {
+        PROTO_UDP => {
+            let direction = if dst_port == ports.udp_tx {
+                Direction::ToRobot
+            } else if dst_port == ports.udp_rx {
+                Direction::FromRobot
+            } else {
+                return None;
+            };
+            Some((direction, Transport::Udp, segment[8..].to_vec()))
+        }
+        PROTO_TCP => {
+            let direction = if dst_port == ports.tcp {
+                Direction::ToRobot
+            } else if src_port == ports.tcp {
+                Direction::FromRobot
+            } else {
+                return None;
+            };
+            let data_offset = usize::from(*segment.get(12)? >> 4) * 4;
+            let data = segment.get(data_offset..)?;
+            if data.is_empty() {
+                return None;
+            }
+            Some((direction, Transport::Tcp, data.to_vec()))
+        }
+        _ => None,
+    }
+}
+
+/// A packet decoded from a capture
+#[derive(Debug)]
+pub enum ReplayedPacket {
+    /// A control packet sent by the driver station
+    Control(UdpControlPacket),
+    /// A status packet sent by the roboRIO
+    Status(UdpResponsePacket),
+    /// A message sent by the roboRIO over TCP
+    RobotTcp(TcpPacket),
+    /// A frame sent by the driver station over TCP, split into its id and contents
+    DsTcp { id: u8, data: Vec<u8> },
+    /// A frame that couldn't be decoded
+    Malformed(CapturedFrame),
+}
+
+/// Decodes captured frames into the packets that were exchanged, in the order they were captured
+///
+/// TCP streams are reassembled, so messages split across several frames are only returned once they are complete.
+pub fn replay(frames: Vec<CapturedFrame>) -> Vec<(DateTime<Utc>, ReplayedPacket)> {
+    let mut packets = Vec::new();
+    let mut robot_tcp = BytesMut::new();
+    let mut ds_tcp = BytesMut::new();
+
+    for frame in frames {
+        let timestamp = frame.timestamp;
+        match (frame.transport, frame.direction) {
+            (Transport::Udp, Direction::ToRobot) => {
+                let packet = match UdpControlPacket::decode(&mut &frame.data[..]) {
+                    Ok(packet) => ReplayedPacket::Control(packet),
+                    Err(_) => ReplayedPacket::Malformed(frame),
+                };
+                packets.push((timestamp, packet));
+            }
+            (Transport::Udp, Direction::FromRobot) => {
+                let packet = match UdpResponsePacket::decode(&mut Bytes::from(frame.data.clone())) {
+                    Ok((packet, _)) => ReplayedPacket::Status(packet),
+                    Err(_) => ReplayedPacket::Malformed(frame),
+                };
+                packets.push((timestamp, packet));
+            }
+            (Transport::Tcp, Direction::FromRobot) => {
+                robot_tcp.extend_from_slice(&frame.data);
+                loop {
+                    match DsTcpCodec.decode(&mut robot_tcp) {
+                        Ok(Some(packet)) => {
+                            packets.push((timestamp, ReplayedPacket::RobotTcp(packet)))
+                        }
+                        Ok(None) => break,
+                        Err(_) => {
+                            // The codec skips frames it can't decode by itself, so this means the framing is lost
+                            robot_tcp.clear();
+                            packets.push((timestamp, ReplayedPacket::Malformed(frame)));
+                            break;
+                        }
+                    }
+                }
+            }
+            (Transport::Tcp, Direction::ToRobot) => {
+                ds_tcp.extend_from_slice(&frame.data);
+                while ds_tcp.len() >= 2 {
+                    let len = usize::from(BigEndian::read_u16(&ds_tcp[..2]));
+                    if ds_tcp.len() < len + 2 {
+                        break;
+                    }
+                    let frame = ds_tcp.split_to(len + 2);
+                    if let Some((&id, data)) = frame[2..].split_first() {
+                        packets.push((
+                            timestamp,
+                            ReplayedPacket::DsTcp {
+                                id,
+                                data: data.to_vec(),
+                            },
+                        ));
+                    }
+                }
+            }
+        }
+    }
+
+    packets
+}
+
+/// Regenerates the `.dslog` records that the driver station logged while a capture was taken
+///
+/// The replayed packets are fed through the same state that the driver station keeps while it runs, and a record is
+/// taken every [`RECORD_PERIOD`](../log/constant.RECORD_PERIOD.html) from the first packet to the last, so the
+/// battery voltage, trace, and tags match the live log. Whether the watchdog expired isn't visible in the traffic, and
+/// is always false.
+pub fn replay_telemetry(
+    packets: &[(DateTime<Utc>, ReplayedPacket)],
+) -> Vec<(DateTime<Utc>, DsLogRecord)> {
+    let (start, end) = match (packets.first(), packets.last()) {
+        (Some(first), Some(last)) => (first.0, last.0),
+        _ => return Vec::new(),
+    };
+    // The link statistics work on instants, which are anchored at the start of the capture
+    let base = Instant::now();
+    let instant =
+        |timestamp: DateTime<Utc>| base + (timestamp - start).to_std().unwrap_or_default();
+    let period = ChronoDuration::from_std(RECORD_PERIOD).unwrap();
+
+    let mut recv = RecvState::new();
+    let mut control = None;
+    let mut records = Vec::new();
+    let mut next_record = start;
+    let take_record = |recv: &RecvState, control: Option<Control>, at: DateTime<Utc>| {
+        let record = recv.log_record(instant(at));
+        match control {
+            Some(control) => DsLogRecord {
+                ds_teleop: !control.intersects(Control::AUTO | Control::TEST),
+                ds_disabled: !control.contains(Control::ENABLED),
+                ..record
+            },
+            None => record,
+        }
+    };
+
+    for (timestamp, packet) in packets {
+        while next_record < *timestamp {
+            records.push((next_record, take_record(&recv, control, next_record)));
+            next_record += period;
+        }
+
+        match packet {
+            ReplayedPacket::Control(packet) => {
+                control = Some(packet.control);
+                recv.link_mut().sent(packet.seqnum, instant(*timestamp));
+            }
+            ReplayedPacket::Status(packet) => {
+                recv.set_trace(packet.trace);
+                recv.set_status(packet.status);
+                recv.set_battery_voltage(packet.battery);
+                recv.update_tags(&packet.tags);
+                recv.link_mut().received(packet.seqnum, instant(*timestamp));
+            }
+            _ => {}
+        }
+    }
+    while next_record <= end {
+        records.push((next_record, take_record(&recv, control, next_record)));
+        next_record += period;
+    }
+
+    records
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ds::SimulatedClock;
+    use crate::proto::tcp::outbound::{GameData, TcpTag};
+    use crate::proto::udp::inbound::types::{Status, Trace};
+    use crate::proto::udp::outbound::types::{Alliance, Control};
+    use chrono::NaiveDate;
+
+    #[derive(Clone)]
+    struct SharedBuf(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuf {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn replays_recorded_traffic() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        tokio::time::pause();
+        let buf = SharedBuf(Arc::new(Mutex::new(Vec::new())));
+        let start = Utc.from_utc_datetime(
+            &NaiveDate::from_ymd_opt(2020, 3, 7)
+                .unwrap()
+                .and_hms_opt(14, 31, 0)
+                .unwrap(),
+        );
+        let endpoints = Endpoints {
+            ds: Ipv4Addr::new(10, 0, 0, 5),
+            robot: Ipv4Addr::new(10, 0, 0, 2),
+            ports: Ports::default(),
+        };
+        let tap = Tap::new(
+            Box::new(buf.clone()),
+            endpoints,
+            Arc::new(SimulatedClock::new(start)),
+        )
+        .unwrap();
+
+        let control = UdpControlPacket {
+            seqnum: 7,
+            control: Control::ENABLED | Control::AUTO,
+            request: None,
+            alliance: Alliance::new_blue(2),
+            tags: Vec::new(),
+        };
+        tap.record(Direction::ToRobot, Transport::Udp, 55555, &control.encode());
+        let status = UdpResponsePacket {
+            seqnum: 7,
+            status: Status::ENABLED | Status::AUTO,
+            trace: Trace::ROBOT_CODE | Trace::AUTONOMOUS,
+            battery: 12.5,
+            need_date: false,
+            tags: Vec::new(),
+        };
+        let mut udp = Tapped::new(crate::proto::udp::DsUdpCodec, Some(tap.clone()), 1150);
+        let decoded = udp
+            .decode(&mut BytesMut::from(&status.encode()[..]))
+            .unwrap()
+            .unwrap();
+        assert_eq!(decoded, status);
+        // Datagrams that fail to decode are still captured
+        assert!(udp.decode(&mut BytesMut::from(&[0, 7][..])).is_err());
+
+        // A stdout message split across two reads, and some game data going the other way
+        let mut stdout = vec![0, 12, 0x0c, 0x3f, 0x80, 0, 0, 0, 1];
+        stdout.extend_from_slice(b"Hello");
+        let mut tcp = TappedStream::new(&stdout[..], Some(tap.clone()), 50000);
+        let mut read = vec![0; 6];
+        tcp.read_exact(&mut read).await.unwrap();
+        tcp.read_to_end(&mut read).await.unwrap();
+        assert_eq!(read, stdout);
+        let mut out = BytesMut::new();
+        DsTcpCodec
+            .encode(
+                TcpTag::GameData(GameData {
+                    gsm: "LRL".to_string(),
+                }),
+                &mut out,
+            )
+            .unwrap();
+        let mut tcp = TappedStream::new(Vec::new(), Some(tap.clone()), 50000);
+        tcp.write_all(&out).await.unwrap();
+
+        let bytes = buf.0.lock().unwrap().clone();
+        let frames = read_capture(&bytes[..], Ports::default()).unwrap();
+        assert_eq!(frames.len(), 6);
+        assert!(frames.iter().all(|f| f.timestamp == start));
+
+        let packets = replay(frames);
+        assert_eq!(packets.len(), 5);
+        match &packets[0].1 {
+            ReplayedPacket::Control(packet) => assert_eq!(packet, &control),
+            other => panic!("Expected a control packet, got {:?}", other),
+        }
+        match &packets[1].1 {
+            ReplayedPacket::Status(packet) => assert_eq!(packet, &status),
+            other => panic!("Expected a status packet, got {:?}", other),
+        }
+        match &packets[2].1 {
+            ReplayedPacket::Malformed(frame) => assert_eq!(frame.data, vec![0, 7]),
+            other => panic!("Expected a malformed frame, got {:?}", other),
+        }
+        match &packets[3].1 {
+            ReplayedPacket::RobotTcp(TcpPacket::Stdout(stdout)) => {
+                assert_eq!(stdout.message, "Hello");
+                assert_eq!(stdout.seqnum, 1);
+            }
+            other => panic!("Expected stdout, got {:?}", other),
+        }
+        match &packets[4].1 {
+            ReplayedPacket::DsTcp { id, data } => {
+                assert_eq!(*id, 0x0e);
+                assert_eq!(data, b"LRL");
+            }
+            other => panic!("Expected game data, got {:?}", other),
+        }
+
+        let records = replay_telemetry(&packets);
+        assert_eq!(records.len(), 1);
+        let (timestamp, record) = &records[0];
+        assert_eq!(*timestamp, start);
+        assert_eq!(record.battery_voltage, 12.5);
+        assert!(record.robot_auto);
+        assert!(!record.ds_disabled);
+        assert!(!record.ds_teleop);
+        assert_eq!(record.packet_loss, 0.0);
+    }
+
+    #[test]
+    fn rejects_oversized_records() {
+        let mut bytes = PcapWriter::new(Vec::new()).unwrap().into_inner();
+        bytes.extend_from_slice(&[0; 8]); // timestamp
+        bytes.write_u32::<LittleEndian>(u32::MAX).unwrap(); // captured length
+        bytes.write_u32::<LittleEndian>(u32::MAX).unwrap(); // original length
+        assert!(read_capture(&bytes[..], Ports::default()).is_err());
+
+        // A microsecond timestamp too large to convert to nanoseconds is skipped, not an overflow
+        let mut bytes = PcapWriter::new(Vec::new()).unwrap().into_inner();
+        bytes.write_u32::<LittleEndian>(0).unwrap();
+        bytes.write_u32::<LittleEndian>(u32::MAX).unwrap();
+        bytes.write_u32::<LittleEndian>(0).unwrap();
+        bytes.write_u32::<LittleEndian>(0).unwrap();
+        assert!(read_capture(&bytes[..], Ports::default())
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn captures_driver_station_session() {
+        use crate::testing::MockRoborio;
+        use futures::StreamExt;
+        use std::time::Duration;
+
+        let path = std::env::temp_dir().join(format!("ds-capture-{}.pcap", std::process::id()));
+        let mock = MockRoborio::bind_any().await.unwrap();
+        mock.send_stdout(0.5, "Robot program starting");
+        let ds = mock.connected_ds().capture(&path).build_async().unwrap();
+
+        let mut status = ds.status();
+        while !status.next().await.unwrap().connected {}
+        tokio::time::delay_for(Duration::from_millis(100)).await;
+        let live = ds.telemetry().await;
+        ds.shutdown(Duration::from_secs(1)).await.unwrap();
+
+        let frames = read_capture(std::fs::File::open(&path).unwrap(), mock.ports()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let packets = replay(frames);
+        assert!(packets
+            .iter()
+            .any(|(_, p)| matches!(p, ReplayedPacket::Control(_))));
+        assert!(packets
+            .iter()
+            .any(|(_, p)| matches!(p, ReplayedPacket::Status(_))));
+        assert!(packets.iter().any(|(_, p)| matches!(
+            p,
+            ReplayedPacket::RobotTcp(TcpPacket::Stdout(stdout)) if stdout.message == "Robot program starting"
+        )));
+        assert!(!packets
+            .iter()
+            .any(|(_, p)| matches!(p, ReplayedPacket::Malformed(_))));
+
+        let (_, last) = replay_telemetry(&packets).pop().unwrap();
+        assert_eq!(last.battery_voltage, live.battery_voltage);
+        assert_eq!(last.robot_teleop, live.robot_teleop);
+        assert_eq!(last.robot_disabled, live.robot_disabled);
+    }
+
+    #[test]
+    fn ip_checksum_is_valid() {
+        let header = ipv4_header(
+            Ipv4Addr::new(10, 0, 0, 5),
+            Ipv4Addr::new(10, 0, 0, 2),
+            PROTO_UDP,
+            14,
+        );
+        let mut sum = 0u32;
+        for word in header.chunks(2) {
+            sum += u32::from(BigEndian::read_u16(word));
+        }
+        while sum > 0xffff {
+            sum = (sum & 0xffff) + (sum >> 16);
+        }
+        assert_eq!(sum, 0xffff);
+    }
+}
@@ -1,202 +1,347 @@
-use failure::bail;
-
-use std::thread;
-
+mod async_ds;
+mod builder;
+mod clock;
 mod conn;
+mod console;
+mod event;
+mod practice;
+mod reconcile;
 pub(crate) mod state;
-
-use self::conn::*;
+mod watchdog;
+
+pub use self::async_ds::AsyncDriverStation;
+pub use self::builder::{DriverStationBuilder, Ports};
+pub use self::clock::{Clock, SimulatedClock, SystemClock};
+pub use self::console::{ConsoleEntry, ConsoleLevel, ConsoleMessage, RobotConsole};
+pub use self::event::{DsEvent, EventReceiver};
+pub use self::practice::{MatchPhase, PracticeMatch, PracticeTimings};
+pub use self::reconcile::{DiscrepancyKind, DiscrepancyReason, StateDiscrepancy};
 use self::state::*;
+pub use self::watchdog::Watchdog;
 
+use failure::format_err;
 use futures::executor::block_on;
+#[cfg(feature = "metrics")]
+use std::net::SocketAddr;
+use std::sync::mpsc::Receiver;
+#[cfg(feature = "metrics")]
 use std::sync::Arc;
-
-use futures_channel::mpsc::{unbounded, UnboundedSender};
-
-use crate::proto::tcp::outbound::{GameData, TcpTag};
+use std::thread::JoinHandle;
+use std::time::Duration;
+use tokio::runtime::Handle;
+
+use crate::input::{InputProfile, JoystickRecorder, SafetyKeys};
+use crate::log::DsLogRecord;
+use crate::metrics::Metrics;
+#[cfg(feature = "metrics")]
+use crate::metrics::MetricsServer;
+use crate::proto::tcp::outbound::{JoystickDesc, TcpTag};
 use crate::proto::udp::inbound::types::Trace;
 use crate::proto::udp::outbound::types::tags::UdpTag;
 use crate::proto::udp::outbound::types::*;
-use crate::util::ip_from_team_number;
 use crate::{Result, TcpPacket};
 
 /// Represents a connection to the roboRIO acting as a driver station
 ///
 /// This struct will contain relevant functions to update the state of the robot,
 /// and also manages the threads that manage network connections and joysticks
+///
+/// Every method blocks the calling thread, and so shouldn't be called from inside a tokio runtime.
+/// Async code should use [`AsyncDriverStation`](struct.AsyncDriverStation.html) instead, which this wraps.
 pub struct DriverStation {
-    thread_tx: UnboundedSender<Signal>,
-    team_number: u32,
-    state: Arc<DsState>,
+    inner: AsyncDriverStation,
+    /// Handle to the runtime the network tasks are running on
+    handle: Handle,
+    /// The thread running the runtime, if it is owned by this driver station, and a channel notified when it finishes
+    thread: Option<(JoinHandle<()>, Receiver<()>)>,
 }
 
 impl DriverStation {
+    /// Returns a builder that can be used to configure a new driver station
+    ///
+    /// Unlike [`new`](#method.new) and [`new_team`](#method.new_team), the builder reports failures to set up the
+    /// network sockets to the caller.
+    pub fn builder() -> DriverStationBuilder {
+        DriverStationBuilder::new()
+    }
+
     /// Creates a new driver station with the given team number and alliance
     ///
     /// This driver station will attempt to connect to a roboRIO at 10.TE.AM.2,
     /// if the roboRIO is at a different ip, use [new] and specify the ip directly.
+    ///
+    /// # Panics
+    /// Panics if the driver station could not be started. Use [`builder`](#method.builder) to handle the error instead.
     pub fn new_team(team_number: u32, alliance: Alliance) -> DriverStation {
-        Self::new(&ip_from_team_number(team_number), alliance, team_number)
+        Self::builder()
+            .team(team_number)
+            .alliance(alliance)
+            .build()
+            .expect("Failed to start driver station")
     }
 
     /// Creates a new driver station for the given alliance station and team number
     /// Connects to the roborio at `ip`. To infer the ip from team_number, use `new_team` instead.
+    ///
+    /// # Panics
+    /// Panics if the driver station could not be started. Use [`builder`](#method.builder) to handle the error instead.
     pub fn new(ip: &str, alliance: Alliance, team_number: u32) -> DriverStation {
-        // Channels to communicate to the threads that make up the application, used to break out of infinite loops when the struct is dropped
-        let (tx, rx) = unbounded::<Signal>();
-
-        // Global state of the driver station
-        let state = Arc::new(DsState::new(alliance));
-
-        // Thread containing UDP sockets communicating with the roboRIO
-        let udp_state = state.clone();
-        let udp_ip = ip.to_owned();
-
-        let sim_tx = tx.clone();
-        thread::spawn(move || {
-            use tokio::runtime::Runtime;
-            let mut rt = Runtime::new().unwrap();
-            rt.spawn(sim_conn(sim_tx));
-            rt.block_on(udp_conn(udp_state, udp_ip, rx))
-                .expect("Error with udp connection");
-        });
+        Self::builder()
+            .team(team_number)
+            .target(ip)
+            .alliance(alliance)
+            .build()
+            .expect("Failed to start driver station")
+    }
 
+    pub(crate) fn from_async(
+        inner: AsyncDriverStation,
+        handle: Handle,
+        thread: Option<(JoinHandle<()>, Receiver<()>)>,
+    ) -> DriverStation {
         DriverStation {
-            thread_tx: tx,
-            state,
-            team_number,
+            inner,
+            handle,
+            thread,
         }
     }
 
+    /// Returns the async driver station wrapped by this one
+    pub fn as_async(&mut self) -> &mut AsyncDriverStation {
+        &mut self.inner
+    }
+
     /// Provides a closure that will be called when constructing outbound packets to append joystick values
+    ///
+    /// The closure is called on a thread of its own. Packets wait 10ms for it, then resend the values it last returned
+    /// until it answers. If it hasn't answered for 500ms, packets are sent without joysticks.
     pub fn set_joystick_supplier(
         &mut self,
         supplier: impl Fn() -> Vec<Vec<JoystickValue>> + Send + Sync + 'static,
     ) {
-        block_on(self.state.send().lock()).set_joystick_supplier(supplier);
+        block_on(self.inner.set_joystick_supplier(supplier));
+    }
+
+    /// Processes the joystick values of `port` with `profile` before they are sent, or sends them unchanged if it is
+    /// None
+    ///
+    /// Profiles can be changed at any time, such as when a different driver takes the controls.
+    pub fn set_input_profile(&mut self, port: usize, profile: Option<InputProfile>) {
+        block_on(self.inner.set_input_profile(port, profile));
+    }
+
+    /// Records the joystick values sent the next time the robot is enabled, replacing any other recorder, or stops
+    /// recording if `recorder` is None
+    ///
+    /// See [`JoystickPlayback`](input/struct.JoystickPlayback.html) for playing the recording back.
+    pub fn set_joystick_recorder(&mut self, recorder: Option<JoystickRecorder>) {
+        block_on(self.inner.set_joystick_recorder(recorder));
     }
 
     /// Provides a closure that will be called when TCP packets are received from the roboRIO
     ///
     /// Example usage: Logging all stdout messages from robot code.
     pub fn set_tcp_consumer(&mut self, consumer: impl FnMut(TcpPacket) + Send + Sync + 'static) {
-        block_on(self.state.tcp().lock()).set_tcp_consumer(consumer);
+        block_on(self.inner.set_tcp_consumer(consumer));
+    }
+
+    /// Describes the joysticks on each port to robot code, replacing any descriptors given before
+    ///
+    /// The descriptors are sent now if the TCP connection is established, and again every time it is re-established.
+    pub fn set_joystick_descriptors(&mut self, descriptors: Vec<JoystickDesc>) {
+        block_on(self.inner.set_joystick_descriptors(descriptors));
     }
 
     /// Changes the alliance for the given `DriverStation`
     pub fn set_alliance(&mut self, alliance: Alliance) {
-        block_on(self.state.send().lock()).set_alliance(alliance);
+        block_on(self.inner.set_alliance(alliance));
     }
 
     /// Changes the given `mode` the robot will be in
     pub fn set_mode(&mut self, mode: Mode) {
-        block_on(self.state.send().lock()).set_mode(mode);
+        block_on(self.inner.set_mode(mode));
     }
 
     pub fn ds_mode(&self) -> DsMode {
-        *block_on(self.state.send().lock()).ds_mode()
+        block_on(self.inner.ds_mode())
     }
 
     /// Changes the team number of this driver station, as well as the ip the driver station will attempt to connect to.
     /// The ip of the new roboRIO target is 10.TE.AM.2
     pub fn set_team_number(&mut self, team_number: u32) {
-        self.team_number = team_number;
-        self.thread_tx
-            .unbounded_send(Signal::NewTarget(ip_from_team_number(team_number)))
-            .unwrap();
+        self.inner.set_team_number(team_number);
     }
 
     pub fn set_use_usb(&mut self, use_usb: bool) {
-        if use_usb {
-            self.thread_tx
-                .unbounded_send(Signal::NewTarget("172.22.11.2".to_string()))
-                .unwrap();
-        } else {
-            self.thread_tx
-                .unbounded_send(Signal::NewTarget(ip_from_team_number(self.team_number)))
-                .unwrap();
-        }
+        self.inner.set_use_usb(use_usb);
     }
 
     pub fn team_number(&self) -> u32 {
-        self.team_number
+        self.inner.team_number()
     }
 
     /// Sets the game specific message sent to the robot, and used during the autonomous period
     pub fn set_game_specific_message(&mut self, message: &str) -> Result<()> {
-        if message.len() != 3 {
-            bail!("Message should be 3 characters long");
-        }
-
-        let _ = block_on(self.state.tcp().lock()).queue_tcp(TcpTag::GameData(GameData {
-            gsm: message.to_string(),
-        }));
-        Ok(())
+        block_on(self.inner.set_game_specific_message(message))
     }
 
     /// Returns the current mode of the robot
     pub fn mode(&self) -> Mode {
-        *block_on(self.state.send().lock()).mode()
+        block_on(self.inner.mode())
     }
 
     /// Enables outputs on the robot
     pub fn enable(&mut self) {
-        block_on(self.state.send().lock()).enable();
+        block_on(self.inner.enable());
     }
 
     /// Instructs the roboRIO to restart robot code
     pub fn restart_code(&mut self) {
-        block_on(self.state.send().lock()).request(Request::RESTART_CODE);
+        block_on(self.inner.restart_code());
     }
 
     /// Instructs the roboRIO to reboot
     pub fn restart_roborio(&mut self) {
-        block_on(self.state.send().lock()).request(Request::REBOOT_ROBORIO);
+        block_on(self.inner.restart_roborio());
     }
 
     /// Returns whether the robot is currently enabled
     pub fn enabled(&self) -> bool {
-        block_on(self.state.send().lock()).enabled()
+        block_on(self.inner.enabled())
     }
 
     /// Returns the last received Trace from the robot
     pub fn trace(&self) -> Trace {
-        *block_on(self.state.recv().lock()).trace()
+        block_on(self.inner.trace())
     }
 
     /// Returns the last received battery voltage from the robot
     pub fn battery_voltage(&self) -> f32 {
-        block_on(self.state.recv().lock()).battery_voltage()
+        block_on(self.inner.battery_voltage())
+    }
+
+    /// Returns the most recent status snapshot of the driver station and robot
+    pub fn latest_status(&self) -> RobotStatus {
+        self.inner.latest_status()
+    }
+
+    /// Returns a snapshot of the link and robot telemetry, such as trip time, packet loss, and CPU and CAN usage
+    ///
+    /// The snapshot is the same record that is written to `.dslog` files every 20ms.
+    pub fn telemetry(&self) -> DsLogRecord {
+        block_on(self.inner.telemetry())
+    }
+
+    /// Returns a snapshot of the telemetry exported to Prometheus, including the number of brownouts and the memory
+    /// usage of the roboRIO
+    pub fn metrics(&self) -> Metrics {
+        block_on(self.inner.metrics())
     }
 
     /// Queues a UDP tag to be transmitted with the next outbound packet to the roboRIO
     pub fn queue_udp(&mut self, udp_tag: UdpTag) {
-        block_on(self.state.send().lock()).queue_udp(udp_tag);
+        block_on(self.inner.queue_udp(udp_tag));
     }
 
     /// Returns a Vec of the current contents of the UDP queue
     pub fn udp_queue(&self) -> Vec<UdpTag> {
-        block_on(self.state.send().lock()).pending_udp().clone()
+        block_on(self.inner.udp_queue())
     }
 
     /// Queues a TCP tag to be transmitted to the roboRIO
     pub fn queue_tcp(&mut self, tcp_tag: TcpTag) {
-        let _ = block_on(self.state.tcp().lock()).queue_tcp(tcp_tag);
+        block_on(self.inner.queue_tcp(tcp_tag));
     }
 
     /// Disables outputs on the robot and disallows enabling it until the code is restarted.
     pub fn estop(&mut self) {
-        block_on(self.state.send().lock()).estop();
+        block_on(self.inner.estop());
     }
 
     /// Returns whether the robot is currently E-stopped
     pub fn estopped(&self) -> bool {
-        block_on(self.state.send().lock()).estopped()
+        block_on(self.inner.estopped())
     }
 
     /// Disables outputs on the robot
     pub fn disable(&mut self) {
-        block_on(self.state.send().lock()).disable();
+        block_on(self.inner.disable());
+    }
+
+    /// Returns a receiver for events raised by the driver station after this is called
+    pub fn events(&self) -> EventReceiver {
+        EventReceiver::new(self.inner.state.subscribe_events())
+    }
+
+    /// Feeds the communication watchdog, if one is configured
+    pub fn feed(&self) {
+        self.inner.feed();
+    }
+
+    /// Returns a handle to the communication watchdog, if one is configured
+    ///
+    /// The handle can be moved into a joystick supplier so that it feeds the watchdog only when it has fresh input.
+    pub fn watchdog(&self) -> Option<Watchdog> {
+        self.inner.watchdog()
+    }
+
+    /// Returns the safety hotkeys for this driver station, which disable and E-stop the robot without waiting on locks
+    ///
+    /// Unlike the other methods, the returned [`SafetyKeys`](input/struct.SafetyKeys.html) never block.
+    pub fn safety_keys(&self) -> SafetyKeys {
+        self.inner.safety_keys()
+    }
+
+    /// Starts a practice match in the background, switching modes and enabling the robot for each period
+    ///
+    /// Dropping the returned handle aborts the match.
+    pub fn start_practice_match(&mut self, timings: PracticeTimings) -> PracticeMatch {
+        PracticeMatch::start(&self.handle, self.inner.state.clone(), timings)
+    }
+
+    /// Serves the [`metrics`](metrics/index.html) of this driver station at `/metrics` on `addr`, in the background
+    ///
+    /// Dropping the returned handle stops the endpoint.
+    #[cfg(feature = "metrics")]
+    pub fn serve_metrics(&mut self, addr: SocketAddr) -> Result<MetricsServer> {
+        MetricsServer::start(&self.handle, Arc::downgrade(&self.inner.state), addr)
+    }
+
+    /// Disables the robot and stops all the network tasks, waiting up to `timeout` for them to finish
+    ///
+    /// A final disabled control packet is sent to the roboRIO, the TCP connection is closed, and the network thread
+    /// is joined before this returns. Dropping the driver station does the same, with a timeout of one second.
+    ///
+    /// When the driver station runs on a runtime given to
+    /// [`runtime_handle`](struct.DriverStationBuilder.html#method.runtime_handle), this blocks on that runtime, and so
+    /// returns an error if called from inside a tokio runtime. The network tasks are still told to stop, but async code
+    /// should call [`AsyncDriverStation::shutdown`](struct.AsyncDriverStation.html#method.shutdown) to wait for them.
+    pub fn shutdown(mut self, timeout: Duration) -> Result<()> {
+        self.stop(timeout)
+    }
+
+    fn stop(&mut self, timeout: Duration) -> Result<()> {
+        self.inner.signal_shutdown();
+
+        match self.thread.take() {
+            Some((thread, done)) => {
+                done.recv_timeout(timeout)
+                    .map_err(|_| format_err!("Timed out waiting for the network thread to stop"))?;
+                thread
+                    .join()
+                    .map_err(|_| format_err!("Network thread panicked"))
+            }
+            None => {
+                // Blocking on the runtime from inside one panics
+                if Handle::try_current().is_ok() {
+                    return Err(format_err!(
+                        "Can't wait for the driver station to stop from inside a tokio runtime, use AsyncDriverStation"
+                    ));
+                }
+                let handle = self.handle.clone();
+                handle.block_on(self.inner.join(timeout))
+            }
+        }
     }
 }
 
@@ -206,7 +351,6 @@ pub enum JoystickValue {
     /// Represents an axis value to be sent to the roboRIO
     ///
     /// `value` should range from `-1.0..=1.0`, or `0.0..=1.0` if the axis is a trigger
-
     // If the source data is a u8, which it is with hidapi, then converting to
     // an f32 just so it can be converted back to a u8 for the packet makes no sense.
     Axis { id: u8, value: f32 },
@@ -218,8 +362,10 @@ pub enum JoystickValue {
 
 impl Drop for DriverStation {
     fn drop(&mut self) {
-        // When this struct is dropped the threads that we spawned should be stopped otherwise we're leaking
-        let _ = self.thread_tx.unbounded_send(Signal::Disconnect);
+        // Only wait on threads we own, blocking on a runtime provided by the user could panic if we're running on it
+        if self.thread.is_some() {
+            let _ = self.stop(Duration::from_secs(1));
+        }
     }
 }
 
@@ -229,3 +375,62 @@ pub(crate) enum Signal {
     NewTarget(String),
     NewMode(DsMode),
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::net::UdpSocket;
+
+    #[test]
+    fn shutdown_sends_disabled_packet() {
+        let robot = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let udp_rx = crate::testing::free_port().unwrap();
+        let mut ds = DriverStation::builder()
+            .target("127.0.0.1")
+            .ports(Ports {
+                udp_tx: robot.local_addr().unwrap().port(),
+                udp_rx,
+                ..Ports::default()
+            })
+            .simulation_detection(false)
+            .build()
+            .unwrap();
+
+        ds.enable();
+        std::thread::sleep(Duration::from_millis(100));
+        ds.shutdown(Duration::from_secs(1)).unwrap();
+
+        robot.set_nonblocking(true).unwrap();
+        let mut buf = [0; 1024];
+        let mut last = None;
+        while let Ok(n) = robot.recv(&mut buf) {
+            last = Some(Control::from_bits_truncate(buf[3]));
+            assert!(n >= 6);
+        }
+        let last = last.expect("No packets were sent");
+        assert!(!last.contains(Control::ENABLED));
+
+        // The port is free again once the driver station has shut down
+        UdpSocket::bind(("0.0.0.0", udp_rx)).unwrap();
+    }
+
+    #[test]
+    fn shutdown_inside_runtime_is_an_error() {
+        let mut rt = tokio::runtime::Runtime::new().unwrap();
+        let robot = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let ds = DriverStation::builder()
+            .target("127.0.0.1")
+            .ports(Ports {
+                udp_tx: robot.local_addr().unwrap().port(),
+                udp_rx: crate::testing::free_port().unwrap(),
+                ..Ports::default()
+            })
+            .simulation_detection(false)
+            .runtime_handle(rt.handle().clone())
+            .build()
+            .unwrap();
+
+        let res = rt.block_on(async move { ds.shutdown(Duration::from_secs(1)) });
+        assert!(res.is_err());
+    }
+}
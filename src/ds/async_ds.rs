@@ -0,0 +1,339 @@
+use super::clock;
+use super::state::{DsMode, DsState, RobotStatus};
+use super::{DsEvent, JoystickValue, PracticeMatch, PracticeTimings, Signal, Watchdog};
+
+use crate::input::{InputProfile, JoystickRecorder, SafetyKeys};
+use crate::log::DsLogRecord;
+use crate::metrics::Metrics;
+#[cfg(feature = "metrics")]
+use crate::metrics::MetricsServer;
+use crate::proto::tcp::outbound::{GameData, JoystickDesc, TcpTag};
+use crate::proto::udp::inbound::types::Trace;
+use crate::proto::udp::outbound::types::tags::UdpTag;
+use crate::proto::udp::outbound::types::*;
+use crate::util::ip_from_team_number;
+use crate::{Mode, Result, TcpPacket};
+
+use failure::{bail, format_err};
+use futures::future;
+use futures::stream::{Stream, StreamExt};
+use futures_channel::mpsc::UnboundedSender;
+#[cfg(feature = "metrics")]
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::runtime::Handle;
+use tokio::task::JoinHandle;
+
+/// A connection to the roboRIO whose network tasks run on the caller's tokio runtime
+///
+/// This exposes the same functionality as [`DriverStation`](struct.DriverStation.html), but never blocks the thread
+/// it is called from, making it safe to use from inside async code. Construct one with
+/// [`DriverStationBuilder::build_async`](struct.DriverStationBuilder.html#method.build_async).
+pub struct AsyncDriverStation {
+    pub(crate) thread_tx: UnboundedSender<Signal>,
+    pub(crate) team_number: u32,
+    pub(crate) state: Arc<DsState>,
+    /// The root network task, taken once the driver station has been shut down
+    pub(crate) task: Option<JoinHandle<()>>,
+    pub(crate) watchdog: Option<Watchdog>,
+}
+
+impl AsyncDriverStation {
+    /// Provides a closure that will be called when constructing outbound packets to append joystick values
+    ///
+    /// The closure is called on a thread of its own. Packets wait 10ms for it, then resend the values it last returned
+    /// until it answers. If it hasn't answered for 500ms, packets are sent without joysticks.
+    pub async fn set_joystick_supplier(
+        &mut self,
+        supplier: impl Fn() -> Vec<Vec<JoystickValue>> + Send + Sync + 'static,
+    ) {
+        self.state
+            .send()
+            .lock()
+            .await
+            .set_joystick_supplier(supplier);
+    }
+
+    /// Processes the joystick values of `port` with `profile` before they are sent, or sends them unchanged if it is
+    /// None
+    pub async fn set_input_profile(&mut self, port: usize, profile: Option<InputProfile>) {
+        self.state
+            .send()
+            .lock()
+            .await
+            .set_input_profile(port, profile);
+    }
+
+    /// Records the joystick values sent the next time the robot is enabled, replacing any other recorder, or stops
+    /// recording if `recorder` is None
+    pub async fn set_joystick_recorder(&mut self, recorder: Option<JoystickRecorder>) {
+        self.state
+            .send()
+            .lock()
+            .await
+            .set_joystick_recorder(recorder);
+    }
+
+    /// Provides a closure that will be called when TCP packets are received from the roboRIO
+    pub async fn set_tcp_consumer(
+        &mut self,
+        consumer: impl FnMut(TcpPacket) + Send + Sync + 'static,
+    ) {
+        self.state.tcp().lock().await.set_tcp_consumer(consumer);
+    }
+
+    /// Describes the joysticks on each port to robot code, replacing any descriptors given before
+    ///
+    /// The descriptors are sent now if the TCP connection is established, and again every time it is re-established.
+    pub async fn set_joystick_descriptors(&mut self, descriptors: Vec<JoystickDesc>) {
+        self.state
+            .tcp()
+            .lock()
+            .await
+            .set_joystick_descriptors(descriptors);
+    }
+
+    /// Changes the alliance for the given `AsyncDriverStation`
+    pub async fn set_alliance(&mut self, alliance: Alliance) {
+        self.state.send().lock().await.set_alliance(alliance);
+    }
+
+    /// Changes the given `mode` the robot will be in
+    pub async fn set_mode(&mut self, mode: Mode) {
+        self.state.send().lock().await.set_mode(mode);
+        self.state.update_status().await;
+    }
+
+    pub async fn ds_mode(&self) -> DsMode {
+        *self.state.send().lock().await.ds_mode()
+    }
+
+    /// Changes the team number of this driver station, as well as the ip the driver station will attempt to connect to.
+    /// The ip of the new roboRIO target is 10.TE.AM.2
+    pub fn set_team_number(&mut self, team_number: u32) {
+        self.team_number = team_number;
+        let _ = self
+            .thread_tx
+            .unbounded_send(Signal::NewTarget(ip_from_team_number(team_number)));
+    }
+
+    pub fn set_use_usb(&mut self, use_usb: bool) {
+        let target = if use_usb {
+            "172.22.11.2".to_string()
+        } else {
+            ip_from_team_number(self.team_number)
+        };
+        let _ = self.thread_tx.unbounded_send(Signal::NewTarget(target));
+    }
+
+    pub fn team_number(&self) -> u32 {
+        self.team_number
+    }
+
+    /// Sets the game specific message sent to the robot, and used during the autonomous period
+    pub async fn set_game_specific_message(&mut self, message: &str) -> Result<()> {
+        if message.len() != 3 {
+            bail!("Message should be 3 characters long");
+        }
+
+        let _ = self
+            .state
+            .tcp()
+            .lock()
+            .await
+            .queue_tcp(TcpTag::GameData(GameData {
+                gsm: message.to_string(),
+            }));
+        Ok(())
+    }
+
+    /// Returns the current mode of the robot
+    pub async fn mode(&self) -> Mode {
+        *self.state.send().lock().await.mode()
+    }
+
+    /// Enables outputs on the robot
+    pub async fn enable(&mut self) {
+        self.state.send().lock().await.enable();
+        self.state.update_status().await;
+    }
+
+    /// Instructs the roboRIO to restart robot code
+    pub async fn restart_code(&mut self) {
+        self.state
+            .send()
+            .lock()
+            .await
+            .request(Request::RESTART_CODE);
+    }
+
+    /// Instructs the roboRIO to reboot
+    pub async fn restart_roborio(&mut self) {
+        self.state
+            .send()
+            .lock()
+            .await
+            .request(Request::REBOOT_ROBORIO);
+    }
+
+    /// Returns whether the robot is currently enabled
+    pub async fn enabled(&self) -> bool {
+        self.state.send().lock().await.enabled()
+    }
+
+    /// Returns the last received Trace from the robot
+    pub async fn trace(&self) -> Trace {
+        *self.state.recv().lock().await.trace()
+    }
+
+    /// Returns the last received battery voltage from the robot
+    pub async fn battery_voltage(&self) -> f32 {
+        self.state.recv().lock().await.battery_voltage()
+    }
+
+    /// Queues a UDP tag to be transmitted with the next outbound packet to the roboRIO
+    pub async fn queue_udp(&mut self, udp_tag: UdpTag) {
+        self.state.send().lock().await.queue_udp(udp_tag);
+    }
+
+    /// Returns a Vec of the current contents of the UDP queue
+    pub async fn udp_queue(&self) -> Vec<UdpTag> {
+        self.state.send().lock().await.pending_udp().clone()
+    }
+
+    /// Queues a TCP tag to be transmitted to the roboRIO
+    pub async fn queue_tcp(&mut self, tcp_tag: TcpTag) {
+        let _ = self.state.tcp().lock().await.queue_tcp(tcp_tag);
+    }
+
+    /// Disables outputs on the robot and disallows enabling it until the code is restarted.
+    pub async fn estop(&mut self) {
+        self.state.send().lock().await.estop();
+        self.state.update_status().await;
+    }
+
+    /// Returns whether the robot is currently E-stopped
+    pub async fn estopped(&self) -> bool {
+        self.state.send().lock().await.estopped()
+    }
+
+    /// Disables outputs on the robot
+    pub async fn disable(&mut self) {
+        self.state.send().lock().await.disable();
+        self.state.update_status().await;
+    }
+
+    /// Returns the most recent status snapshot of the driver station and robot
+    pub fn latest_status(&self) -> RobotStatus {
+        *self.state.subscribe_status().borrow()
+    }
+
+    /// Returns a snapshot of the link and robot telemetry, such as trip time, packet loss, and CPU and CAN usage
+    ///
+    /// The snapshot is the same record that is written to `.dslog` files every 20ms.
+    pub async fn telemetry(&self) -> DsLogRecord {
+        self.state.log_record().await
+    }
+
+    /// Returns a snapshot of the telemetry exported to Prometheus, including the number of brownouts and the memory
+    /// usage of the roboRIO
+    pub async fn metrics(&self) -> Metrics {
+        Metrics::read(&self.state).await
+    }
+
+    /// Returns a stream of status snapshots
+    ///
+    /// The stream yields the current status immediately, and then a new snapshot every time it changes.
+    pub fn status(&self) -> impl Stream<Item = RobotStatus> {
+        self.state.subscribe_status()
+    }
+
+    /// Returns a stream of events raised by the driver station after this is called
+    pub fn events(&self) -> impl Stream<Item = DsEvent> {
+        self.state
+            .subscribe_events()
+            .filter_map(|event| future::ready(event.ok()))
+    }
+
+    /// Feeds the communication watchdog, if one is configured
+    pub fn feed(&self) {
+        if let Some(ref watchdog) = self.watchdog {
+            watchdog.feed();
+        }
+    }
+
+    /// Returns a handle to the communication watchdog, if one is configured
+    pub fn watchdog(&self) -> Option<Watchdog> {
+        self.watchdog.clone()
+    }
+
+    /// Returns the safety hotkeys for this driver station, which disable and E-stop the robot without waiting on locks
+    pub fn safety_keys(&self) -> SafetyKeys {
+        SafetyKeys::new(self.state.safety().clone())
+    }
+
+    /// Starts a practice match on the current runtime, switching modes and enabling the robot for each period
+    ///
+    /// Dropping the returned handle aborts the match.
+    pub fn start_practice_match(&mut self, timings: PracticeTimings) -> PracticeMatch {
+        PracticeMatch::start(&Handle::current(), self.state.clone(), timings)
+    }
+
+    /// Serves the [`metrics`](metrics/index.html) of this driver station at `/metrics` on `addr`, on the current
+    /// runtime
+    ///
+    /// Dropping the returned handle stops the endpoint.
+    #[cfg(feature = "metrics")]
+    pub fn serve_metrics(&mut self, addr: SocketAddr) -> Result<MetricsServer> {
+        MetricsServer::start(&Handle::current(), Arc::downgrade(&self.state), addr)
+    }
+
+    /// Disables the robot and stops all the network tasks, waiting up to `timeout` for them to finish
+    ///
+    /// A final disabled control packet is sent to the roboRIO, and the TCP connection is closed before this returns.
+    pub async fn shutdown(mut self, timeout: Duration) -> Result<()> {
+        self.signal_shutdown();
+        self.join(timeout).await
+    }
+
+    pub(crate) fn signal_shutdown(&self) {
+        let _ = self.thread_tx.unbounded_send(Signal::Disconnect);
+    }
+
+    /// Waits for the root network task to finish, if it is owned by this driver station
+    pub(crate) async fn join(&mut self, timeout: Duration) -> Result<()> {
+        if let Some(task) = self.task.take() {
+            clock::timeout(&**self.state.clock(), timeout, task)
+                .await
+                .map_err(|_| format_err!("Timed out waiting for the network tasks to stop"))??;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for AsyncDriverStation {
+    fn drop(&mut self) {
+        // When this struct is dropped the tasks that we spawned should be stopped otherwise we're leaking
+        self.signal_shutdown();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::testing::MockRoborio;
+    use futures::StreamExt;
+
+    #[tokio::test]
+    async fn status_stream_follows_commands() {
+        let mock = MockRoborio::bind_any().await.unwrap();
+        let mut ds = mock.connected_ds().build_async().unwrap();
+
+        let mut status = ds.status();
+        assert!(!status.next().await.unwrap().enabled);
+
+        ds.enable().await;
+        assert!(status.next().await.unwrap().enabled);
+        assert!(ds.latest_status().enabled);
+    }
+}
@@ -0,0 +1,352 @@
+use super::conn::{udp_conn, ConnConfig};
+use super::state::DsState;
+use super::{AsyncDriverStation, Clock, DriverStation, Signal, SystemClock, Watchdog};
+use crate::capture::{Endpoints, Tap};
+use crate::log::DsLogWriter;
+
+use crate::proto::udp::outbound::types::Alliance;
+use crate::util::{host_timezone, ip_from_team_number};
+use crate::{Mode, Result};
+
+use failure::{bail, format_err};
+use futures_channel::mpsc::unbounded;
+use log::*;
+use std::fs::File;
+use std::io::BufWriter;
+use std::net::{self, IpAddr, Ipv4Addr};
+use std::path::PathBuf;
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use tokio::net::UdpSocket;
+use tokio::runtime::{Handle, Runtime};
+
+/// The network ports used to communicate with the roboRIO
+///
+/// The defaults are the ports used by the NI driver station, these should only need to be changed
+/// when talking to something other than a real roboRIO, such as a mock or a simulator on a remote host.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Ports {
+    /// The UDP port on the roboRIO that control packets are sent to
+    pub udp_tx: u16,
+    /// The local UDP port that status packets from the roboRIO are received on
+    pub udp_rx: u16,
+    /// The TCP port on the roboRIO used for stdout, errors, and game data
+    pub tcp: u16,
+    /// The local UDP port that the WPILib simulator pings to announce that it is running
+    pub sim: u16,
+}
+
+impl Default for Ports {
+    fn default() -> Ports {
+        Ports {
+            udp_tx: 1110,
+            udp_rx: 1150,
+            tcp: 1740,
+            sim: 1135,
+        }
+    }
+}
+
+/// Builder used to configure and construct a [`DriverStation`](struct.DriverStation.html)
+///
+/// Either a team number or an explicit target must be provided before calling [`build`](#method.build).
+pub struct DriverStationBuilder {
+    team_number: Option<u32>,
+    target: Option<String>,
+    alliance: Alliance,
+    mode: Mode,
+    timezone: Option<String>,
+    ports: Ports,
+    handle: Option<Handle>,
+    sim_detection: bool,
+    watchdog: Option<Duration>,
+    clock: Arc<dyn Clock>,
+    capture: Option<PathBuf>,
+    log_dir: Option<PathBuf>,
+}
+
+impl DriverStationBuilder {
+    pub(crate) fn new() -> DriverStationBuilder {
+        DriverStationBuilder {
+            team_number: None,
+            target: None,
+            alliance: Alliance::new_red(1),
+            mode: Mode::Autonomous,
+            timezone: None,
+            ports: Ports::default(),
+            handle: None,
+            sim_detection: true,
+            watchdog: None,
+            clock: Arc::new(SystemClock),
+            capture: None,
+            log_dir: None,
+        }
+    }
+
+    /// Sets the team number of the driver station
+    ///
+    /// If no target is set explicitly, the driver station will connect to 10.TE.AM.2
+    pub fn team(mut self, team_number: u32) -> Self {
+        self.team_number = Some(team_number);
+        self
+    }
+
+    /// Sets the ip or hostname of the roboRIO, overriding the one inferred from the team number
+    pub fn target(mut self, target: impl Into<String>) -> Self {
+        self.target = Some(target.into());
+        self
+    }
+
+    /// Sets the alliance station of the driver station. Defaults to red 1
+    pub fn alliance(mut self, alliance: Alliance) -> Self {
+        self.alliance = alliance;
+        self
+    }
+
+    /// Sets the mode the robot will be in when it is first enabled. Defaults to autonomous
+    pub fn mode(mut self, mode: Mode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Sets the timezone that will be sent to the roboRIO alongside the date, e.g. `America/Toronto`
+    ///
+    /// Defaults to the timezone of the host, or UTC if that can't be determined.
+    pub fn timezone(mut self, timezone: impl Into<String>) -> Self {
+        self.timezone = Some(timezone.into());
+        self
+    }
+
+    /// Overrides the ports used to communicate with the roboRIO
+    pub fn ports(mut self, ports: Ports) -> Self {
+        self.ports = ports;
+        self
+    }
+
+    /// Runs the network tasks on an existing tokio runtime rather than starting a new one on a dedicated thread
+    pub fn runtime_handle(mut self, handle: Handle) -> Self {
+        self.handle = Some(handle);
+        self
+    }
+
+    /// Sets whether the driver station should switch to the WPILib simulator when one is detected. Defaults to true
+    pub fn simulation_detection(mut self, enabled: bool) -> Self {
+        self.sim_detection = enabled;
+        self
+    }
+
+    /// Enables the communication watchdog, which disables the robot if it isn't fed at least once every `window`
+    ///
+    /// The watchdog is fed by calling [`DriverStation::feed`](struct.DriverStation.html#method.feed), or through a
+    /// [`Watchdog`](struct.Watchdog.html) handle. Disabled by default.
+    pub fn watchdog(mut self, window: Duration) -> Self {
+        self.watchdog = Some(window);
+        self
+    }
+
+    /// Sets the source of time used by the driver station. Defaults to [`SystemClock`](struct.SystemClock.html)
+    ///
+    /// This is mostly useful in tests, see [`SimulatedClock`](struct.SimulatedClock.html).
+    pub fn clock(mut self, clock: impl Clock) -> Self {
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    /// Records every frame exchanged with the roboRIO to a pcap file at `path`, replacing the file if it exists
+    ///
+    /// See the [`capture`](capture/index.html) module for reading the file back.
+    pub fn capture(mut self, path: impl Into<PathBuf>) -> Self {
+        self.capture = Some(path.into());
+        self
+    }
+
+    /// Writes NI compatible `.dslog` and `.dsevents` logs to `dir`, named after the time the driver station was built
+    ///
+    /// See the [`log`](log/index.html) module for the format of the logs.
+    pub fn log_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.log_dir = Some(dir.into());
+        self
+    }
+
+    /// Binds the sockets used by the driver station and starts the network tasks
+    ///
+    /// Unless a runtime handle was provided, the tasks run on a new tokio runtime on a dedicated thread.
+    ///
+    /// Returns Err if no team or target was specified, if the target can't be resolved, or if any of the sockets can't be bound.
+    pub fn build(self) -> Result<DriverStation> {
+        match self.handle.clone() {
+            Some(handle) => {
+                let ds = self.start(&handle)?;
+                Ok(DriverStation::from_async(ds, handle, None))
+            }
+            None => {
+                let mut rt = Runtime::new()?;
+                let handle = rt.handle().clone();
+                let mut ds = self.start(&handle)?;
+                let task = ds.task.take().unwrap();
+
+                // The runtime is dropped on this thread once the root task is done, stopping anything left on it
+                let (done_tx, done_rx) = mpsc::channel();
+                let thread = thread::spawn(move || {
+                    let _ = rt.block_on(task);
+                    drop(rt);
+                    let _ = done_tx.send(());
+                });
+                Ok(DriverStation::from_async(
+                    ds,
+                    handle,
+                    Some((thread, done_rx)),
+                ))
+            }
+        }
+    }
+
+    /// Binds the sockets used by the driver station and starts the network tasks on the current tokio runtime
+    ///
+    /// If a runtime handle was provided, it is used instead of the current runtime.
+    ///
+    /// Returns Err if called outside of a tokio runtime without a handle, or for any of the reasons [`build`](#method.build) can fail.
+    pub fn build_async(self) -> Result<AsyncDriverStation> {
+        let handle = match self.handle.clone() {
+            Some(handle) => handle,
+            None => Handle::try_current().map_err(|_| {
+                format_err!("build_async must be called from inside a tokio runtime")
+            })?,
+        };
+
+        self.start(&handle)
+    }
+
+    /// Binds all the sockets and spawns the network tasks onto `handle`
+    fn start(self, handle: &Handle) -> Result<AsyncDriverStation> {
+        let target = match (&self.target, self.team_number) {
+            (Some(target), _) => target.clone(),
+            (None, Some(team)) => {
+                if team > 9999 {
+                    bail!("Team number {} is out of range", team);
+                }
+                ip_from_team_number(team)
+            }
+            (None, None) => bail!("A team number or target must be specified"),
+        };
+        let ports = self.ports;
+
+        // Bind everything up front so that failures are reported to the caller rather than a background task
+        let udp_rx = net::UdpSocket::bind(("0.0.0.0", ports.udp_rx))
+            .map_err(|e| format_err!("Failed to bind UDP port {}: {}", ports.udp_rx, e))?;
+        let udp_tx = net::UdpSocket::bind("0.0.0.0:0")?;
+        udp_tx
+            .connect((target.as_str(), ports.udp_tx))
+            .map_err(|e| format_err!("Failed to connect to {}: {}", target, e))?;
+        let endpoints = Endpoints {
+            ds: ipv4(udp_tx.local_addr()?.ip()),
+            robot: ipv4(udp_tx.peer_addr()?.ip()),
+            ports,
+        };
+        let sim = if self.sim_detection {
+            Some(
+                net::UdpSocket::bind(("127.0.0.1", ports.sim))
+                    .map_err(|e| format_err!("Failed to bind UDP port {}: {}", ports.sim, e))?,
+            )
+        } else {
+            None
+        };
+
+        let (udp_rx, udp_tx, sim) = handle.enter(|| -> Result<_> {
+            let udp_rx = UdpSocket::from_std(udp_rx)?;
+            let udp_tx = UdpSocket::from_std(udp_tx)?;
+            let sim = sim.map(UdpSocket::from_std).transpose()?;
+            Ok((udp_rx, udp_tx, sim))
+        })?;
+
+        let (tx, rx) = unbounded::<Signal>();
+
+        let clock = self.clock;
+        let watchdog = self
+            .watchdog
+            .map(|window| Watchdog::new(window, clock.clone()));
+        let state = Arc::new(DsState::new(self.alliance, clock));
+        {
+            let mut send = state.send().try_lock().unwrap();
+            send.set_mode(self.mode);
+            send.set_watchdog(watchdog.clone());
+        }
+
+        let tap = match self.capture {
+            Some(ref path) => {
+                let file = File::create(path).map_err(|e| {
+                    format_err!("Failed to create capture file {}: {}", path.display(), e)
+                })?;
+                Some(Tap::new(
+                    Box::new(BufWriter::new(file)),
+                    endpoints,
+                    state.clock().clone(),
+                )?)
+            }
+            None => None,
+        };
+
+        let log = match self.log_dir {
+            Some(ref dir) => Some(Arc::new(Mutex::new(DsLogWriter::create(
+                dir,
+                state.clock().now(),
+            )?))),
+            None => None,
+        };
+
+        let config = ConnConfig {
+            ports,
+            timezone: self.timezone.unwrap_or_else(host_timezone),
+            tap,
+            log,
+        };
+
+        let udp_state = state.clone();
+        let task = handle.spawn(async move {
+            if let Err(e) = udp_conn(udp_state, target, config, udp_rx, udp_tx, sim, rx).await {
+                error!("Error with udp connection: {}", e);
+            }
+        });
+
+        Ok(AsyncDriverStation {
+            thread_tx: tx,
+            state,
+            team_number: self.team_number.unwrap_or(0),
+            task: Some(task),
+            watchdog,
+        })
+    }
+}
+
+/// Captures only hold IPv4 addresses, so anything else is recorded as unspecified
+fn ipv4(ip: IpAddr) -> Ipv4Addr {
+    match ip {
+        IpAddr::V4(ip) => ip,
+        IpAddr::V6(ip) => ip.to_ipv4().unwrap_or(Ipv4Addr::UNSPECIFIED),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn build_requires_target() {
+        assert!(DriverStation::builder().build().is_err());
+    }
+
+    #[test]
+    fn build_reports_bind_failure() {
+        let taken = net::UdpSocket::bind("0.0.0.0:0").unwrap();
+        let res = DriverStation::builder()
+            .target("127.0.0.1")
+            .ports(Ports {
+                udp_rx: taken.local_addr().unwrap().port(),
+                ..Ports::default()
+            })
+            .simulation_detection(false)
+            .build();
+        assert!(res.is_err());
+    }
+}
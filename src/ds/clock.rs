@@ -0,0 +1,167 @@
+use chrono::{DateTime, Utc};
+use futures::future::{self, BoxFuture, Either, FutureExt};
+use futures::stream::{self, Stream, StreamExt};
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::time;
+
+/// Source of time for the driver station
+///
+/// Everything time dependent goes through the clock: the date sent to the roboRIO, the 20ms send interval, the
+/// connection timeouts, the reconnect backoff, the watchdog, and practice matches. The default, [`SystemClock`](struct.SystemClock.html),
+/// uses tokio's timers, so tests that pause tokio's time can pair it with [`SimulatedClock`](struct.SimulatedClock.html)
+/// to control the reported date as well.
+pub trait Clock: Send + Sync + 'static {
+    /// Returns the current wall clock time, used when the roboRIO asks for the date
+    fn now(&self) -> DateTime<Utc>;
+
+    /// Returns the current monotonic time, used to measure intervals and timeouts
+    fn instant(&self) -> Instant;
+
+    /// Returns a future that completes once `deadline` has been reached
+    fn delay_until(&self, deadline: Instant) -> BoxFuture<'static, ()>;
+
+    /// Returns a future that completes once `duration` has passed
+    fn delay_for(&self, duration: Duration) -> BoxFuture<'static, ()> {
+        self.delay_until(self.instant() + duration)
+    }
+}
+
+/// The default clock, reading the system time and sleeping on tokio's timers
+#[derive(Debug, Copy, Clone, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+
+    fn instant(&self) -> Instant {
+        time::Instant::now().into_std()
+    }
+
+    fn delay_until(&self, deadline: Instant) -> BoxFuture<'static, ()> {
+        time::delay_until(time::Instant::from_std(deadline)).boxed()
+    }
+}
+
+/// A clock whose wall time starts at a fixed date, and only moves forward with tokio's time
+///
+/// When tokio's time is paused with `tokio::time::pause`, time only moves when `tokio::time::advance` is called or
+/// when every task is waiting on a timer, making tests that depend on timing deterministic.
+#[derive(Debug, Clone)]
+pub struct SimulatedClock {
+    start: DateTime<Utc>,
+    epoch: Instant,
+}
+
+impl SimulatedClock {
+    /// Creates a clock reporting `start` as the current date at the moment it is constructed
+    pub fn new(start: DateTime<Utc>) -> SimulatedClock {
+        SimulatedClock {
+            start,
+            epoch: SystemClock.instant(),
+        }
+    }
+}
+
+impl Clock for SimulatedClock {
+    fn now(&self) -> DateTime<Utc> {
+        let elapsed = self.instant().duration_since(self.epoch);
+        self.start
+            + chrono::Duration::from_std(elapsed).unwrap_or_else(|_| chrono::Duration::max_value())
+    }
+
+    fn instant(&self) -> Instant {
+        SystemClock.instant()
+    }
+
+    fn delay_until(&self, deadline: Instant) -> BoxFuture<'static, ()> {
+        SystemClock.delay_until(deadline)
+    }
+}
+
+/// Returned when a future or stream times out
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) struct Elapsed;
+
+/// Stream yielding every `period`, starting immediately
+pub(crate) fn interval(clock: Arc<dyn Clock>, period: Duration) -> impl Stream<Item = ()> {
+    let start = clock.instant();
+    stream::unfold((clock, start), move |(clock, next)| async move {
+        clock.delay_until(next).await;
+        Some(((), (clock, next + period)))
+    })
+}
+
+/// Waits for `fut`, giving up if it doesn't complete within `duration`
+pub(crate) async fn timeout<F: Future>(
+    clock: &dyn Clock,
+    duration: Duration,
+    fut: F,
+) -> Result<F::Output, Elapsed> {
+    futures::pin_mut!(fut);
+    match future::select(fut, clock.delay_for(duration)).await {
+        Either::Left((out, _)) => Ok(out),
+        Either::Right(_) => Err(Elapsed),
+    }
+}
+
+/// Wraps `stream`, yielding `Err(Elapsed)` whenever no item arrives within `duration`
+///
+/// The stream keeps going after a timeout, ending only when `stream` does.
+pub(crate) fn timeout_stream<S: Stream + Unpin>(
+    clock: Arc<dyn Clock>,
+    duration: Duration,
+    stream: S,
+) -> impl Stream<Item = Result<S::Item, Elapsed>> {
+    stream::unfold((clock, stream), move |(clock, mut stream)| async move {
+        match timeout(&*clock, duration, stream.next()).await {
+            Ok(Some(item)) => Some((Ok(item), (clock, stream))),
+            Ok(None) => None,
+            Err(Elapsed) => Some((Err(Elapsed), (clock, stream))),
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::{NaiveDate, TimeZone};
+
+    #[tokio::test]
+    async fn simulated_clock_follows_paused_time() {
+        time::pause();
+        let start = Utc.from_utc_datetime(
+            &NaiveDate::from_ymd_opt(2020, 1, 4)
+                .unwrap()
+                .and_hms_opt(9, 0, 0)
+                .unwrap(),
+        );
+        let clock = SimulatedClock::new(start);
+        assert_eq!(clock.now(), start);
+
+        time::advance(Duration::from_millis(1500)).await;
+        assert_eq!(clock.now(), start + chrono::Duration::milliseconds(1500));
+    }
+
+    #[tokio::test]
+    async fn timeout_stream_recovers_after_elapsing() {
+        time::pause();
+        let clock: Arc<dyn Clock> = Arc::new(SystemClock);
+        let (tx, rx) = futures_channel::mpsc::unbounded();
+        let mut stream = Box::pin(timeout_stream(clock, Duration::from_secs(2), rx));
+
+        let next = stream.next();
+        futures::pin_mut!(next);
+        assert!(futures::poll!(next.as_mut()).is_pending());
+        time::advance(Duration::from_secs(2)).await;
+        assert_eq!(next.await, Some(Err(Elapsed)));
+
+        tx.unbounded_send(1).unwrap();
+        assert_eq!(stream.next().await, Some(Ok(1)));
+        drop(tx);
+        assert_eq!(stream.next().await, None);
+    }
+}
@@ -7,83 +7,231 @@ use crate::proto::udp::outbound::types::tags::{DateTime as DTTag, *};
 
 use futures_channel::mpsc::UnboundedReceiver;
 use futures_channel::mpsc::{unbounded, UnboundedSender};
+use futures_channel::oneshot;
 use futures_util::sink::SinkExt;
 use futures_util::stream::StreamExt;
-use std::sync::Arc;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{mpsc, Arc};
+use std::thread;
 use std::time::Duration;
 use tokio::net::{TcpStream, UdpSocket};
-use tokio::time;
+use tokio::task::JoinHandle;
 use tokio_util::codec::Decoder;
 use tokio_util::udp::UdpFramed;
 
-use chrono::prelude::*;
-
 use crate::proto::tcp::DsTcpCodec;
 use crate::proto::udp::DsUdpCodec;
 use crate::Result;
 
+use crate::capture::{Direction, Tap, Tapped, TappedStream, Transport};
+use crate::ds::clock::{self, Clock};
+use crate::ds::reconcile::Commanded;
 use crate::ds::state::{DsMode, DsState};
+use crate::ds::Ports;
+use crate::log::{DsLogWriter, RECORD_PERIOD};
 use crate::proto::tcp::outbound::TcpTag;
-use futures_util::future::Either;
-use futures_util::stream::select;
+use futures_util::future::{abortable, AbortHandle, Aborted, Either};
+use futures_util::stream::{self, select};
 
 mod backoff;
 
 use backoff::ExponentialBackoff;
-use std::io::ErrorKind;
+use std::fs::File;
+use std::io::{BufWriter, ErrorKind};
+use std::sync::Mutex;
+
+/// How long a control packet waits for the joystick supplier, before it is sent with the values it last returned
+const SUPPLIER_TIMEOUT: Duration = Duration::from_millis(10);
+
+/// How long the joystick supplier can go without answering, before packets are sent without joysticks
+const SUPPLIER_STALL: Duration = Duration::from_millis(500);
+
+/// How long the TCP task is given to close its connection on shutdown or retargeting, before it is aborted
+///
+/// The task can't see the disconnect signal while it is still connecting, which can take as long as the OS allows if
+/// the roboRIO went away.
+const TCP_SHUTDOWN_GRACE: Duration = Duration::from_millis(500);
+
+/// A running TCP task, and the handle used to abort it
+type TcpTask = (
+    JoinHandle<std::result::Result<Result<()>, Aborted>>,
+    AbortHandle,
+);
+
+/// Why the send task is constructing a control packet
+enum Cycle {
+    /// The regular 20ms interval
+    Interval,
+    /// A disable or E-stop request was raised through the safety latch
+    Safety,
+}
+
+/// A `.dslog` and `.dsevents` writer shared between the logging and TCP tasks
+pub(crate) type SharedLog = Arc<Mutex<DsLogWriter<BufWriter<File>>>>;
+
+/// Settings for the network tasks that are fixed when the driver station is built
+pub(crate) struct ConnConfig {
+    pub ports: Ports,
+    /// The IANA timezone sent to the roboRIO alongside the date
+    pub timezone: String,
+    /// Where sent and received frames are recorded, if capturing is enabled
+    pub tap: Option<Tap>,
+    /// Where `.dslog` records and events are written, if logging is enabled
+    pub log: Option<SharedLog>,
+}
 
 /// The root task of the tokio runtime.
 ///
-/// This task connects to the receiving UDP port, and spawns tasks for UDP sending, and for TCP communications once the connection to the RIO has been established.
+/// This task listens on the already bound receiving UDP socket, and spawns tasks for UDP sending, simulator detection,
+/// and for TCP communications once the connection to the RIO has been established.
+///
+/// On `Signal::Disconnect` every task spawned here is stopped before this one returns.
 pub(crate) async fn udp_conn(
     state: Arc<DsState>,
     mut target_ip: String,
+    config: ConnConfig,
+    udp_rx: UdpSocket,
+    mut udp_tx: UdpSocket,
+    sim: Option<UdpSocket>,
     rx: UnboundedReceiver<Signal>,
 ) -> Result<()> {
     let mut tcp_connected = false;
     let mut tcp_tx = None;
+    let mut tcp_task = None;
+    let ports = config.ports;
+    let clock = state.clock().clone();
 
-    let udp_rx = UdpSocket::bind("0.0.0.0:1150").await?;
-    let udp_rx = UdpFramed::new(udp_rx, DsUdpCodec);
+    let udp_rx = UdpFramed::new(
+        udp_rx,
+        Tapped::new(DsUdpCodec, config.tap.clone(), ports.udp_rx),
+    );
+    let send_port = udp_tx.local_addr()?.port();
+    let send_tap = config.tap.clone();
 
     let (fwd_tx, fwd_rx) = unbounded::<Signal>();
 
-    let send_state = state.clone();
-    let target = target_ip.clone();
-    tokio::spawn(async move {
-        let mut udp_tx = UdpSocket::bind("0.0.0.0:0")
-            .await
-            .expect("Failed to bind tx socket");
-        udp_tx
-            .connect(&format!("{}:1110", target))
-            .await
-            .expect("Failed to connect to target");
+    // The simulator task never finishes on its own, so it has to be aborted on shutdown
+    let (sim_tx, sim_rx) = unbounded::<Signal>();
+    let sim_task = sim.map(|sock| {
+        let (task, handle) = abortable(sim_conn(sock, clock.clone(), sim_tx));
+        tokio::spawn(task);
+        handle
+    });
+    let rx = select(rx, sim_rx);
 
-        let interval = time::interval(Duration::from_millis(20));
+    // Like the simulator task, the logging task runs until it is aborted
+    let log_task = config.log.clone().map(|log| {
+        let (task, handle) = abortable(log_conn(state.clone(), log));
+        tokio::spawn(task);
+        handle
+    });
 
-        let mut stream = select(interval.map(Either::Left), fwd_rx.map(Either::Right));
-        let mut backoff = ExponentialBackoff::new(Duration::new(5, 0));
+    let send_state = state.clone();
+    let send_clock = clock.clone();
+    let send_task = tokio::spawn(async move {
+        let interval = clock::interval(send_clock.clone(), Duration::from_millis(20));
+        let safety = stream::unfold(send_state.clone(), |state| async move {
+            state.safety().woken().await;
+            Some((Cycle::Safety, state))
+        });
+
+        let mut stream = select(
+            select(
+                Box::pin(interval).map(|_| Cycle::Interval),
+                Box::pin(safety),
+            )
+            .map(Either::Left),
+            fwd_rx.map(Either::Right),
+        );
+        let mut backoff = ExponentialBackoff::new(Duration::new(5, 0), send_clock.clone());
+        let supplier_jobs = supplier_thread();
+        // The supplier call still running and when it was made, which is waited on again by the next packet rather
+        // than called twice
+        let mut supplier_call = None;
+        // The values the supplier last returned, which are sent again while a call is running
+        let mut joysticks = Vec::new();
+        let mut supplier_stalled = false;
 
         loop {
             let item = stream.next().await.unwrap();
             match item {
-                // Action every 20ms interval.
-                Either::Left(_) => {
+                // Action every 20ms interval, or straight away when a safety request is raised
+                Either::Left(cycle) => {
+                    // The supplier runs on its own thread without the lock held, and is only waited on briefly, so
+                    // a slow or stuck one can't hold up this loop. Packets sent for safety requests don't wait at all
+                    if let Cycle::Interval = cycle {
+                        if supplier_call.is_none() {
+                            let supplier = send_state.send().lock().await.joystick_supplier();
+                            supplier_call = supplier.map(|supplier| {
+                                let (tx, rx) = oneshot::channel();
+                                let _ = supplier_jobs.send(Box::new(move || {
+                                    let _ = tx.send(supplier());
+                                }));
+                                (rx, send_clock.instant())
+                            });
+                        }
+                        match supplier_call.as_mut() {
+                            Some((call, called_at)) => {
+                                match clock::timeout(&*send_clock, SUPPLIER_TIMEOUT, call).await {
+                                    Ok(res) => {
+                                        supplier_call = None;
+                                        supplier_stalled = false;
+                                        // The supplier panicked if it never answered
+                                        joysticks = res.unwrap_or_default();
+                                    }
+                                    Err(_) => {
+                                        let waited = send_clock
+                                            .instant()
+                                            .saturating_duration_since(*called_at);
+                                        if waited >= SUPPLIER_STALL && !supplier_stalled {
+                                            warn!("Joystick supplier has stalled, sending packets without joysticks");
+                                            supplier_stalled = true;
+                                            joysticks = Vec::new();
+                                        }
+                                    }
+                                }
+                            }
+                            None => joysticks = Vec::new(),
+                        }
+                    }
+
                     let mut state = send_state.send().lock().await;
-                    let v = state.control().encode();
+                    let before = (state.enabled(), state.estopped());
+                    let v = state.control(&joysticks).encode();
+                    // The watchdog or a safety request may have disabled the robot while constructing the packet
+                    let mut changed = before != (state.enabled(), state.estopped());
+                    // Packets sent while the supplier is stalled don't hold what the driver was doing
+                    if let (Cycle::Interval, false, Some(recorder)) =
+                        (cycle, supplier_stalled, state.joystick_recorder())
+                    {
+                        recorder.record(state.enabled(), &joysticks, send_clock.instant());
+                    }
                     // Massively overengineered considering the _only_ time that this actually starts
                     // to come into play is directly after the simulator is closed before the DS switches to Normal mode again
                     // but I don't feel like changing it, and now it's fail safe
                     match backoff.run(udp_tx.send(&v[..])).await {
-                        Ok(_) => {}
+                        Ok(_) => {
+                            if let Some(ref tap) = send_tap {
+                                tap.record(Direction::ToRobot, Transport::Udp, send_port, &v);
+                            }
+                            let now = send_clock.instant();
+                            let seqnum = state.seqnum();
+                            send_state.recv().lock().await.link_mut().sent(seqnum, now);
+                        }
                         Err((e, dc)) => {
                             if e.kind() == ErrorKind::ConnectionRefused && dc {
                                 println!("Send socket disconnected");
                                 send_state.recv().lock().await.reset();
+                                changed = true;
                             }
                         }
                     }
                     state.increment_seqnum();
+                    drop(state);
+
+                    if changed {
+                        send_state.update_status().await;
+                    }
                 }
 
                 // Action on signal from main task on UDP receive?
@@ -93,28 +241,44 @@ pub(crate) async fn udp_conn(
                         state.reset_seqnum();
                         state.disable();
                         send_state.recv().lock().await.reset();
-                        udp_tx = UdpSocket::bind("0.0.0.0:0")
-                            .await
-                            .expect("Failed to bind tx socket");
-                        udp_tx
-                            .connect(&format!("{}:1110", &ip))
-                            .await
-                            .expect("Failed to connect to new target");
+                        drop(state);
+                        send_state.update_status().await;
+                        if let Err(e) = udp_tx.connect((ip.as_str(), ports.udp_tx)).await {
+                            error!("Failed to connect to new target {}: {}", ip, e);
+                        }
+                        backoff.reset();
+                    }
+
+                    Signal::NewMode(DsMode::Simulation) => {
+                        let mut state = send_state.send().lock().await;
+                        state.reset_seqnum();
+                        state.disable();
+                        send_state.recv().lock().await.reset();
+                        drop(state);
+                        send_state.update_status().await;
+                        if let Err(e) = udp_tx.connect(("127.0.0.1", ports.udp_tx)).await {
+                            error!("Failed to connect to simulator socket: {}", e);
+                        }
                         backoff.reset();
                     }
 
-                    Signal::NewMode(mode) => {
-                        if let DsMode::Simulation = mode {
-                            let mut state = send_state.send().lock().await;
-                            state.reset_seqnum();
-                            state.disable();
-                            send_state.recv().lock().await.reset();
-                            udp_tx
-                                .connect("127.0.0.1:1110")
-                                .await
-                                .expect("Failed to connect to simulator socket");
-                            backoff.reset();
+                    // Leave the robot disabled on the way out, rather than waiting for it to notice the missing packets
+                    Signal::Disconnect => {
+                        let mut state = send_state.send().lock().await;
+                        state.disable();
+                        let v = state.control(&[]).encode();
+                        match udp_tx.send(&v[..]).await {
+                            Ok(_) => {
+                                if let Some(ref tap) = send_tap {
+                                    tap.record(Direction::ToRobot, Transport::Udp, send_port, &v);
+                                }
+                            }
+                            Err(e) => debug!("Failed to send final control packet: {}", e),
                         }
+                        state.increment_seqnum();
+                        drop(state);
+                        send_state.update_status().await;
+                        return;
                     }
                     _ => {}
                 },
@@ -122,10 +286,8 @@ pub(crate) async fn udp_conn(
         }
     });
 
-    // I need the tokio extension for this, the futures extension to split codecs, and I can't import them both
-    // Thanks for coordinating trait names to make using both nicely impossible
-    let fut = tokio::stream::StreamExt::timeout(udp_rx, Duration::from_secs(2)).map(Either::Left);
-    let mut stream = select(fut, rx.map(Either::Right));
+    let fut = clock::timeout_stream(clock.clone(), Duration::from_secs(2), udp_rx);
+    let mut stream = select(Box::pin(fut).map(Either::Left), rx.map(Either::Right));
 
     // Main loop, watching incoming UDP packets from RIO.
     let mut connected = false;
@@ -139,42 +301,62 @@ pub(crate) async fn udp_conn(
                             connected = true;
                         }
                         let (packet, _): (UdpResponsePacket, _) = packet;
-                        let mut _state = state.recv().lock().await;
 
                         if packet.need_date {
-                            let local = Utc::now();
-                            let micros = local.naive_utc().timestamp_subsec_micros();
-                            let second = local.time().second() as u8;
-                            let minute = local.time().minute() as u8;
-                            let hour = local.time().hour() as u8;
-                            let day = local.date_naive().day() as u8;
-                            let month = local.date_naive().month0() as u8;
-                            let year = (local.date_naive().year() - 1900) as u8;
-                            let tag = DTTag::new(micros, second, minute, hour, day, month, year);
-                            state.send().lock().await.queue_udp(UdpTag::DateTime(tag));
+                            let mut send = state.send().lock().await;
+                            send.queue_udp(UdpTag::DateTime(DTTag::from_utc(&clock.now())));
+                            send.queue_udp(UdpTag::Timezone(Timezone::new(&config.timezone)));
                         }
 
                         if !tcp_connected {
                             let (tx, rx) = unbounded::<Signal>();
                             tcp_tx = Some(tx);
                             let mode = *state.send().lock().await.ds_mode();
-                            if mode == DsMode::Normal {
-                                tokio::spawn(tcp_conn(state.clone(), target_ip.clone(), rx));
+                            let target = if mode == DsMode::Normal {
+                                target_ip.clone()
                             } else {
-                                tokio::spawn(tcp_conn(state.clone(), "127.0.0.1".to_string(), rx));
-                            }
+                                "127.0.0.1".to_string()
+                            };
+                            let (task, handle) = abortable(tcp_conn(
+                                state.clone(),
+                                target,
+                                ports.tcp,
+                                config.tap.clone(),
+                                config.log.clone(),
+                                rx,
+                            ));
+                            tcp_task = Some((tokio::spawn(task), handle));
                             tcp_connected = true;
                         }
 
-                        if packet.status.emergency_stopped() {
+                        let commanded = {
                             let mut send = state.send().lock().await;
-                            if !send.estopped() {
+                            if packet.status.emergency_stopped() && !send.estopped() {
                                 send.estop();
                             }
+                            Commanded {
+                                enabled: send.enabled(),
+                                estopped: send.estopped(),
+                                mode: *send.mode(),
+                            }
+                        };
+
+                        // Lock the receive state last: every task locks send before recv, so they can't deadlock
+                        let mut recv = state.recv().lock().await;
+                        recv.set_connected(true);
+                        recv.set_trace(packet.trace);
+                        recv.set_status(packet.status);
+                        recv.set_battery_voltage(packet.battery);
+                        recv.update_tags(&packet.tags);
+                        recv.link_mut().received(packet.seqnum, clock.instant());
+                        let event =
+                            recv.reconciler_mut()
+                                .update(commanded, packet.status, packet.trace);
+                        drop(recv);
+                        if let Some(event) = event {
+                            state.emit(event);
                         }
-
-                        _state.set_trace(packet.trace);
-                        _state.set_battery_voltage(packet.battery);
+                        state.update_status().await;
                     }
                     Err(e) => println!("Error decoding packet: {:?}", e),
                 },
@@ -183,6 +365,7 @@ pub(crate) async fn udp_conn(
                     if connected {
                         // println!("RIO disconnected");
                         state.recv().lock().await.reset();
+                        state.update_status().await;
                         connected = false;
                     }
                 }
@@ -192,16 +375,25 @@ pub(crate) async fn udp_conn(
             Either::Right(sig) => match sig {
                 Signal::Disconnect => {
                     debug!("sig Disconnect");
+
+                    stop_tcp(&*clock, tcp_tx.take(), tcp_task.take()).await;
+                    if let Some(sim_task) = sim_task {
+                        sim_task.abort();
+                    }
+                    if let Some(log_task) = log_task {
+                        log_task.abort();
+                    }
+
+                    fwd_tx.unbounded_send(sig)?;
+                    let _ = send_task.await;
                     return Ok(());
                 }
 
                 Signal::NewTarget(ref target) => {
                     debug!("sig NewTarget {:?}", target);
 
-                    if let Some(ref tcp_tx) = tcp_tx {
-                        let _ = tcp_tx.unbounded_send(Signal::Disconnect);
-                        tcp_connected = false;
-                    }
+                    stop_tcp(&*clock, tcp_tx.take(), tcp_task.take()).await;
+                    tcp_connected = false;
 
                     target_ip = target.clone();
 
@@ -212,11 +404,10 @@ pub(crate) async fn udp_conn(
 
                     let current_mode = *state.send().lock().await.ds_mode();
                     if mode != current_mode {
-                        if let Some(ref tcp_tx) = tcp_tx {
-                            let _ = tcp_tx.unbounded_send(Signal::Disconnect);
-                            tcp_connected = false;
-                        }
+                        stop_tcp(&*clock, tcp_tx.take(), tcp_task.take()).await;
+                        tcp_connected = false;
                         state.send().lock().await.set_ds_mode(mode);
+                        state.update_status().await;
                         if mode == DsMode::Normal {
                             println!("Exiting simulation mode");
                             fwd_tx.unbounded_send(Signal::NewTarget(target_ip.clone()))?;
@@ -230,6 +421,41 @@ pub(crate) async fn udp_conn(
     Ok(())
 }
 
+/// Tells the TCP task to close its connection, and waits for it to stop
+///
+/// The task is aborted if it hasn't stopped within `TCP_SHUTDOWN_GRACE`, so that one still connecting to an old target
+/// can't connect later alongside its replacement.
+async fn stop_tcp(clock: &dyn Clock, tx: Option<UnboundedSender<Signal>>, task: Option<TcpTask>) {
+    if let Some(tx) = tx {
+        let _ = tx.unbounded_send(Signal::Disconnect);
+    }
+    if let Some((mut task, handle)) = task {
+        if clock::timeout(clock, TCP_SHUTDOWN_GRACE, &mut task)
+            .await
+            .is_err()
+        {
+            debug!("TCP task didn't stop in time, aborting it");
+            handle.abort();
+            let _ = task.await;
+        }
+    }
+}
+
+/// Starts a thread that runs the jobs sent to it, which call the joystick supplier, until the sender is dropped
+///
+/// A thread of its own is used rather than `spawn_blocking`, as the runtime waits for blocking tasks when it shuts
+/// down, and a supplier may never return.
+fn supplier_thread() -> mpsc::Sender<Box<dyn FnOnce() + Send>> {
+    let (tx, rx) = mpsc::channel::<Box<dyn FnOnce() + Send>>();
+    thread::spawn(move || {
+        for job in rx {
+            // A supplier that panics drops its reply, which is sent as no joysticks
+            let _ = panic::catch_unwind(AssertUnwindSafe(job));
+        }
+    });
+    tx
+}
+
 /// tokio task for all TCP communications
 ///
 /// This task will decode incoming TCP packets, and call the tcp consumer defined in `state` if it exists.
@@ -237,24 +463,38 @@ pub(crate) async fn udp_conn(
 pub(crate) async fn tcp_conn(
     state: Arc<DsState>,
     target_ip: String,
+    port: u16,
+    tap: Option<Tap>,
+    log: Option<SharedLog>,
     rx: UnboundedReceiver<Signal>,
 ) -> Result<()> {
-    let conn = TcpStream::connect(&format!("{}:1740", target_ip)).await?;
-    let codec = DsTcpCodec.framed(conn);
+    let conn = TcpStream::connect((target_ip.as_str(), port)).await?;
+    let local_port = conn.local_addr()?.port();
+    let codec = DsTcpCodec.framed(TappedStream::new(conn, tap, local_port));
     let (mut codec_tx, codec_rx) = codec.split();
 
     let (tag_tx, tag_rx) = unbounded::<TcpTag>();
-    state.tcp().lock().await.set_tcp_tx(Some(tag_tx));
+    {
+        let mut tcp = state.tcp().lock().await;
+        tcp.set_tcp_tx(Some(tag_tx));
+        tcp.send_joystick_descriptors();
+    }
 
     let stream = select(codec_rx.map(Either::Left), rx.map(Either::Right));
     let mut stream = select(stream.map(Either::Left), tag_rx.map(Either::Right));
 
+    let clock = state.clock().clone();
     let state = state.tcp();
     while let Some(msg) = stream.next().await {
         match msg {
             Either::Left(left) => match left {
                 Either::Left(packet) => {
                     if let Ok(packet) = packet {
+                        if let Some(ref log) = log {
+                            if let Err(e) = log.lock().unwrap().write_tcp(clock.now(), &packet) {
+                                error!("Failed to log TCP packet: {}", e);
+                            }
+                        }
                         let mut state = state.lock().await;
                         if let Some(ref mut consumer) = state.tcp_consumer {
                             consumer(packet);
@@ -263,6 +503,7 @@ pub(crate) async fn tcp_conn(
                 }
                 Either::Right(_) => {
                     state.lock().await.set_tcp_tx(None);
+                    break;
                 }
             },
             Either::Right(tag) => {
@@ -270,18 +511,41 @@ pub(crate) async fn tcp_conn(
             }
         }
     }
+
+    // Flushes anything outstanding and shuts down the write half of the socket
+    codec_tx.close().await?;
     Ok(())
 }
 
-pub(crate) async fn sim_conn(tx: UnboundedSender<Signal>) -> Result<()> {
-    use tokio::time::timeout;
+/// tokio task writing a `.dslog` record every 20ms
+///
+/// Records are flushed as they're written, so that the log is complete even if the process is killed.
+pub(crate) async fn log_conn(state: Arc<DsState>, log: SharedLog) {
+    let mut interval = Box::pin(clock::interval(state.clock().clone(), RECORD_PERIOD));
+    while interval.next().await.is_some() {
+        let record = state.log_record().await;
+        let mut log = log.lock().unwrap();
+        if let Err(e) = log.write_record(&record).and_then(|_| log.flush()) {
+            error!("Failed to write log record: {}", e);
+        }
+    }
+}
+
+/// tokio task watching for the WPILib simulator
+///
+/// The simulator pings the socket bound by `sock` while it is running, this task switches the driver station
+/// into and out of simulation mode as the pings start and stop.
+pub(crate) async fn sim_conn(
+    mut sock: UdpSocket,
+    clock: Arc<dyn Clock>,
+    tx: UnboundedSender<Signal>,
+) -> Result<()> {
     const SOCK_TIMEOUT: Duration = Duration::from_millis(250);
 
-    let mut sock = UdpSocket::bind("127.0.0.1:1135").await?;
     let mut buf = [0];
     let mut opmode = DsMode::Normal;
     loop {
-        match timeout(SOCK_TIMEOUT, sock.recv(&mut buf[..])).await {
+        match clock::timeout(&*clock, SOCK_TIMEOUT, sock.recv(&mut buf[..])).await {
             Ok(_) => {
                 if opmode != DsMode::Simulation {
                     opmode = DsMode::Simulation;
@@ -298,3 +562,76 @@ pub(crate) async fn sim_conn(tx: UnboundedSender<Signal>) -> Result<()> {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use crate::{DriverStation, Ports, SimulatedClock};
+    use chrono::Utc;
+    use futures_util::stream::StreamExt;
+    use tokio::net::UdpSocket;
+    use tokio::time;
+
+    #[tokio::test]
+    async fn detects_disconnect_after_timeout() {
+        time::pause();
+        let mut robot = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let udp_rx = crate::testing::free_port().unwrap();
+        let ds = DriverStation::builder()
+            .target("127.0.0.1")
+            .ports(Ports {
+                udp_tx: robot.local_addr().unwrap().port(),
+                udp_rx,
+                ..Ports::default()
+            })
+            .simulation_detection(false)
+            .clock(SimulatedClock::new(Utc::now()))
+            .build_async()
+            .unwrap();
+        let mut status = ds.status();
+        assert!(!status.next().await.unwrap().connected);
+
+        // A single status packet: disabled teleop, robot code running, 12V, no tags
+        robot
+            .send_to(&[0, 0, 1, 0, 0x20, 12, 0, 0], ("127.0.0.1", udp_rx))
+            .await
+            .unwrap();
+        while !status.next().await.unwrap().connected {}
+        let connected_at = time::Instant::now();
+
+        while status.next().await.unwrap().connected {}
+        let elapsed = time::Instant::now() - connected_at;
+        assert_eq!(elapsed.as_secs_f64().round() as u64, 2);
+    }
+
+    #[tokio::test]
+    async fn resends_joysticks_while_supplier_is_slow() {
+        use crate::proto::udp::outbound::types::tags::UdpTag;
+        use crate::testing::MockRoborio;
+        use crate::{JoystickValue, UdpControlPacket};
+        use std::time::Duration;
+
+        let has_joysticks = |control: &UdpControlPacket| {
+            control
+                .tags()
+                .iter()
+                .any(|tag| matches!(tag, UdpTag::Joysticks(_)))
+        };
+
+        let mock = MockRoborio::bind_any().await.unwrap();
+        let mut ds = mock.connected_ds().build_async().unwrap();
+        // Slower than a packet waits for it, but nowhere near stalled
+        ds.set_joystick_supplier(|| {
+            std::thread::sleep(Duration::from_millis(30));
+            vec![vec![JoystickValue::Button {
+                id: 1,
+                pressed: true,
+            }]]
+        })
+        .await;
+
+        mock.wait_for_control(has_joysticks).await;
+        let controls = mock.control_packets().take(10).collect::<Vec<_>>().await;
+        assert!(controls.iter().all(has_joysticks));
+        ds.shutdown(Duration::from_secs(1)).await.unwrap();
+    }
+}
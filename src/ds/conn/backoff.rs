@@ -1,22 +1,26 @@
+use crate::ds::Clock;
+
 use rand::{thread_rng, Rng};
 use std::future::Future;
+use std::sync::Arc;
 use std::time::Duration;
-use tokio::time;
 
 pub struct ExponentialBackoff {
     attempt: u8,
     max_timeout: Duration,
     use_max: bool,
     timeout: Option<Duration>,
+    clock: Arc<dyn Clock>,
 }
 
 impl ExponentialBackoff {
-    pub fn new(max_timeout: Duration) -> ExponentialBackoff {
+    pub fn new(max_timeout: Duration, clock: Arc<dyn Clock>) -> ExponentialBackoff {
         ExponentialBackoff {
             attempt: 0,
             max_timeout,
             use_max: false,
             timeout: None,
+            clock,
         }
     }
     pub async fn run<O, E>(
@@ -25,7 +29,7 @@ impl ExponentialBackoff {
     ) -> Result<O, (E, bool)> {
         if let Some(timeout) = self.timeout {
             println!("Backoff: waiting {:?}", timeout);
-            time::delay_for(timeout).await;
+            self.clock.delay_for(timeout).await;
         }
         match fut.await {
             Ok(out) => {
@@ -65,3 +69,34 @@ impl ExponentialBackoff {
         self.timeout = Some(delay);
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ds::SystemClock;
+    use futures::future::{ready, FutureExt};
+    use tokio::time;
+
+    #[tokio::test]
+    async fn waits_after_failure() {
+        time::pause();
+        let mut backoff = ExponentialBackoff::new(Duration::from_secs(5), Arc::new(SystemClock));
+        assert_eq!(backoff.run(ready(Err::<(), _>(()))).await, Err(((), true)));
+
+        // The first wait is a second, plus up to another second of jitter
+        {
+            let retry = backoff.run(ready(Ok::<_, ()>(())));
+            futures::pin_mut!(retry);
+            time::advance(Duration::from_secs(1)).await;
+            assert!(futures::poll!(retry.as_mut()).is_pending());
+            time::advance(Duration::from_secs(1)).await;
+            assert_eq!(retry.await, Ok(()));
+        }
+
+        // Succeeding resets the backoff, so the next attempt runs straight away
+        assert_eq!(
+            backoff.run(ready(Ok::<_, ()>(()))).now_or_never(),
+            Some(Ok(()))
+        );
+    }
+}
@@ -0,0 +1,316 @@
+use crate::proto::tcp::inbound::{ErrorMessage, Stdout, TcpPacket};
+
+use std::collections::VecDeque;
+
+/// The severity of a console message
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ConsoleLevel {
+    /// Output printed by robot code
+    Print,
+    /// A warning reported by robot code
+    Warning,
+    /// An error reported by robot code
+    Error,
+}
+
+/// A message printed or reported by robot code
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConsoleMessage {
+    pub level: ConsoleLevel,
+    /// Seconds since the robot code started, as reported by the roboRIO
+    pub timestamp: f32,
+    /// Sequence number of the first packet making up this message
+    pub seqnum: u16,
+    /// The text of the message. Lines printed together are joined with newlines
+    pub text: String,
+    /// The error code, for warnings and errors
+    pub code: Option<i32>,
+    /// Where the warning or error was reported from, empty for printed output
+    pub location: String,
+    /// The call stack of the warning or error, empty for printed output
+    pub call_stack: String,
+}
+
+impl ConsoleMessage {
+    /// Returns whether `needle` appears in the text, location, or call stack of this message, ignoring case
+    pub fn matches(&self, needle: &str) -> bool {
+        let needle = needle.to_lowercase();
+        [&self.text, &self.location, &self.call_stack]
+            .iter()
+            .any(|s| s.to_lowercase().contains(&needle))
+    }
+}
+
+/// An entry of a [`RobotConsole`](struct.RobotConsole.html)
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConsoleEntry {
+    Message(ConsoleMessage),
+    /// Sequence numbers were skipped, `missed` messages never arrived
+    Gap {
+        missed: u16,
+    },
+    /// The robot code restarted, timestamps and sequence numbers start over after this
+    Restart,
+}
+
+/// The console output of robot code, built from the TCP packets sent by the roboRIO
+///
+/// The console keeps the most recent entries, up to its capacity. It notices messages that went missing by watching
+/// sequence numbers, and robot code restarts by watching timestamps go backwards. Consecutive prints with the same
+/// timestamp, such as the lines of a stack trace, are merged into a single message.
+///
+/// Packets can be fed to a console from [`DriverStation::set_tcp_consumer`](struct.DriverStation.html#method.set_tcp_consumer),
+/// e.g. through an `Arc<Mutex<RobotConsole>>` shared with a UI.
+#[derive(Debug, Clone)]
+pub struct RobotConsole {
+    entries: VecDeque<ConsoleEntry>,
+    capacity: usize,
+    /// Sequence number and timestamp of the last message received
+    last: Option<(u16, f32)>,
+    /// Number of entries that were evicted to stay within capacity
+    evicted: usize,
+}
+
+impl RobotConsole {
+    /// Creates a console holding at most `capacity` entries
+    pub fn new(capacity: usize) -> RobotConsole {
+        RobotConsole {
+            entries: VecDeque::with_capacity(capacity.min(1024)),
+            capacity: capacity.max(1),
+            last: None,
+            evicted: 0,
+        }
+    }
+
+    /// Adds the contents of a TCP packet to the console, ignoring packets that aren't console output
+    pub fn push(&mut self, packet: &TcpPacket) {
+        match packet {
+            TcpPacket::Stdout(stdout) => self.push_stdout(stdout),
+            TcpPacket::ErrorMessage(error) => self.push_error(error),
+            TcpPacket::Dummy => {}
+        }
+    }
+
+    /// Adds printed output to the console
+    pub fn push_stdout(&mut self, stdout: &Stdout) {
+        let continues = self.track(stdout.seqnum, stdout.timestamp);
+        if continues {
+            if let Some(ConsoleEntry::Message(last)) = self.entries.back_mut() {
+                if last.level == ConsoleLevel::Print && last.timestamp == stdout.timestamp {
+                    last.text.push('\n');
+                    last.text.push_str(&stdout.message);
+                    return;
+                }
+            }
+        }
+
+        self.add(ConsoleEntry::Message(ConsoleMessage {
+            level: ConsoleLevel::Print,
+            timestamp: stdout.timestamp,
+            seqnum: stdout.seqnum,
+            text: stdout.message.clone(),
+            code: None,
+            location: String::new(),
+            call_stack: String::new(),
+        }));
+    }
+
+    /// Adds a reported warning or error to the console
+    pub fn push_error(&mut self, error: &ErrorMessage) {
+        self.track(error.seqnum, error.timestamp);
+        let level = if error.is_error {
+            ConsoleLevel::Error
+        } else {
+            ConsoleLevel::Warning
+        };
+
+        self.add(ConsoleEntry::Message(ConsoleMessage {
+            level,
+            timestamp: error.timestamp,
+            seqnum: error.seqnum,
+            text: error.details.clone(),
+            code: Some(error.code),
+            location: error.location.clone(),
+            call_stack: error.call_stack.clone(),
+        }));
+    }
+
+    /// Returns every entry currently held, oldest first
+    pub fn entries(&self) -> impl Iterator<Item = &ConsoleEntry> {
+        self.entries.iter()
+    }
+
+    /// Returns the messages at or above `level`, oldest first
+    pub fn messages(&self, level: ConsoleLevel) -> impl Iterator<Item = &ConsoleMessage> {
+        self.entries.iter().filter_map(move |entry| match entry {
+            ConsoleEntry::Message(message) if message.level >= level => Some(message),
+            _ => None,
+        })
+    }
+
+    /// Returns the messages containing `needle`, ignoring case, oldest first
+    pub fn search<'a>(&'a self, needle: &'a str) -> impl Iterator<Item = &'a ConsoleMessage> {
+        self.messages(ConsoleLevel::Print)
+            .filter(move |message| message.matches(needle))
+    }
+
+    /// Returns the number of entries that were dropped to stay within capacity
+    pub fn evicted(&self) -> usize {
+        self.evicted
+    }
+
+    /// Removes every entry, without forgetting the last sequence number seen
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    /// Records a gap or restart before a message if needed, returning whether the message directly follows the last
+    fn track(&mut self, seqnum: u16, timestamp: f32) -> bool {
+        let last = self.last.replace((seqnum, timestamp));
+        let (last_seqnum, last_timestamp) = match last {
+            Some(last) => last,
+            None => return false,
+        };
+
+        if timestamp < last_timestamp {
+            self.add(ConsoleEntry::Restart);
+            return false;
+        }
+
+        // Anything more than half the sequence space behind is an old or duplicated message rather than a gap
+        let missed = seqnum.wrapping_sub(last_seqnum.wrapping_add(1));
+        if missed == 0 {
+            true
+        } else {
+            if missed < 0x8000 {
+                self.add(ConsoleEntry::Gap { missed });
+            }
+            false
+        }
+    }
+
+    fn add(&mut self, entry: ConsoleEntry) {
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+            self.evicted += 1;
+        }
+        self.entries.push_back(entry);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn stdout(seqnum: u16, timestamp: f32, message: &str) -> TcpPacket {
+        TcpPacket::Stdout(Stdout {
+            timestamp,
+            message: message.to_string(),
+            seqnum,
+            raw: message.as_bytes().to_vec(),
+        })
+    }
+
+    fn error(seqnum: u16, timestamp: f32, is_error: bool, details: &str) -> TcpPacket {
+        TcpPacket::ErrorMessage(ErrorMessage {
+            timestamp,
+            seqnum,
+            occurrences: 1,
+            code: -1,
+            is_error,
+            details: details.to_string(),
+            location: "Robot.java:12".to_string(),
+            call_stack: String::new(),
+        })
+    }
+
+    #[test]
+    fn detects_gaps_and_restarts() {
+        let mut console = RobotConsole::new(16);
+        console.push(&stdout(0, 0.5, "Robot program starting"));
+        console.push(&stdout(1, 0.6, "NT: server listening"));
+        console.push(&stdout(4, 1.0, "Auto selected"));
+        console.push(&stdout(5, 0.1, "Robot program starting"));
+
+        let entries = console.entries().collect::<Vec<_>>();
+        assert_eq!(entries.len(), 6);
+        assert_eq!(*entries[2], ConsoleEntry::Gap { missed: 2 });
+        assert_eq!(*entries[4], ConsoleEntry::Restart);
+    }
+
+    #[test]
+    fn merges_lines_printed_together() {
+        let mut console = RobotConsole::new(16);
+        console.push(&stdout(10, 2.0, "Exception in thread main"));
+        console.push(&stdout(11, 2.0, "\tat Robot.teleopPeriodic"));
+        console.push(&stdout(12, 2.5, "Retrying"));
+        // Not merged across a gap, even with the same timestamp
+        console.push(&stdout(14, 2.5, "Retrying again"));
+
+        let texts = console
+            .messages(ConsoleLevel::Print)
+            .map(|m| m.text.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            texts,
+            vec![
+                "Exception in thread main\n\tat Robot.teleopPeriodic",
+                "Retrying",
+                "Retrying again"
+            ]
+        );
+    }
+
+    #[test]
+    fn filters_and_searches() {
+        let mut console = RobotConsole::new(3);
+        console.push(&stdout(0, 0.0, "hello"));
+        console.push(&error(1, 1.0, false, "Loop overrun"));
+        console.push(&error(2, 2.0, true, "CAN timeout"));
+        console.push(&stdout(3, 3.0, "Can you hear me"));
+
+        assert_eq!(console.evicted(), 1);
+        assert_eq!(console.messages(ConsoleLevel::Warning).count(), 2);
+        let errors = console.messages(ConsoleLevel::Error).collect::<Vec<_>>();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].code, Some(-1));
+        assert_eq!(console.search("can").count(), 2);
+        assert_eq!(console.search("robot.java").count(), 2);
+    }
+
+    #[tokio::test]
+    async fn collects_output_from_driver_station() {
+        use crate::testing::MockRoborio;
+        use std::sync::{Arc, Mutex};
+        use std::time::Duration;
+
+        let mock = MockRoborio::bind_any().await.unwrap();
+        mock.send_stdout(0.5, "Robot program starting");
+        mock.send_error(
+            1.0,
+            -44,
+            true,
+            "Joystick not found",
+            "Robot.java:20",
+            "at Robot.init",
+        );
+        let mut ds = mock.connected_ds().build_async().unwrap();
+        let console = Arc::new(Mutex::new(RobotConsole::new(64)));
+        let consumer = console.clone();
+        ds.set_tcp_consumer(move |packet| consumer.lock().unwrap().push(&packet))
+            .await;
+
+        while console.lock().unwrap().entries().count() < 2 {
+            tokio::time::delay_for(Duration::from_millis(10)).await;
+        }
+        ds.shutdown(Duration::from_secs(1)).await.unwrap();
+
+        let console = console.lock().unwrap();
+        let error = console.messages(ConsoleLevel::Error).next().unwrap();
+        assert_eq!(error.text, "Joystick not found");
+        assert_eq!(error.code, Some(-44));
+        assert_eq!(error.location, "Robot.java:20");
+        assert_eq!(error.call_stack, "at Robot.init");
+        assert_eq!(console.entries().count(), 2);
+    }
+}
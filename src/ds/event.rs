@@ -0,0 +1,61 @@
+use super::{MatchPhase, StateDiscrepancy};
+
+use futures::executor::block_on;
+use tokio::sync::broadcast::{self, RecvError, TryRecvError};
+
+/// Events raised by the driver station, for changes that can't be seen in a [`RobotStatus`](struct.RobotStatus.html) snapshot
+#[derive(Debug, Clone, PartialEq)]
+pub enum DsEvent {
+    /// The watchdog wasn't fed within its window, and the robot has been disabled
+    WatchdogExpired,
+    /// The state reported by the robot has disagreed with the commanded state for longer than expected
+    StateDiscrepancy(StateDiscrepancy),
+    /// The state reported by the robot agrees with the commanded state again
+    StateReconciled,
+    /// A practice match has moved into a new period
+    MatchPhase(MatchPhase),
+}
+
+/// Receives events from a blocking [`DriverStation`](struct.DriverStation.html)
+///
+/// Events raised before the receiver was created are not seen, and if the receiver falls too far behind the oldest
+/// events are skipped.
+pub struct EventReceiver {
+    rx: broadcast::Receiver<DsEvent>,
+}
+
+impl EventReceiver {
+    pub(crate) fn new(rx: broadcast::Receiver<DsEvent>) -> EventReceiver {
+        EventReceiver { rx }
+    }
+
+    /// Blocks until the next event is raised, returning None once the driver station has been dropped
+    pub fn recv(&mut self) -> Option<DsEvent> {
+        loop {
+            match block_on(self.rx.recv()) {
+                Ok(event) => return Some(event),
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    }
+
+    /// Returns the next event if one has been raised, without blocking
+    pub fn try_recv(&mut self) -> Option<DsEvent> {
+        loop {
+            match self.rx.try_recv() {
+                Ok(event) => return Some(event),
+                Err(TryRecvError::Lagged(_)) => continue,
+                Err(_) => return None,
+            }
+        }
+    }
+}
+
+impl Iterator for EventReceiver {
+    type Item = DsEvent;
+
+    fn next(&mut self) -> Option<DsEvent> {
+        self.recv()
+    }
+}
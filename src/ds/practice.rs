@@ -0,0 +1,281 @@
+use super::state::DsState;
+use super::DsEvent;
+
+use crate::proto::udp::outbound::types::tags::{Countdown, UdpTag};
+use crate::{Mode, Result};
+
+use futures::future::{select, Either};
+use futures_channel::oneshot;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::runtime::Handle;
+use tokio::task::JoinHandle;
+
+/// How often the countdown sent to the robot is refreshed during a practice match
+const TICK: Duration = Duration::from_millis(100);
+
+/// The length of each period of a practice match
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct PracticeTimings {
+    /// Time before autonomous starts, during which the robot is disabled
+    pub countdown: Duration,
+    /// Length of the autonomous period
+    pub autonomous: Duration,
+    /// Time between autonomous and teleop, during which the robot is disabled
+    pub delay: Duration,
+    /// Length of the teleop period, including the endgame
+    pub teleop: Duration,
+    /// Length of the endgame, measured from the end of teleop
+    pub endgame: Duration,
+}
+
+impl Default for PracticeTimings {
+    fn default() -> PracticeTimings {
+        PracticeTimings {
+            countdown: Duration::from_secs(3),
+            autonomous: Duration::from_secs(15),
+            delay: Duration::from_secs(1),
+            teleop: Duration::from_secs(135),
+            endgame: Duration::from_secs(20),
+        }
+    }
+}
+
+/// The periods of a practice match, reported through [`DsEvent::MatchPhase`](enum.DsEvent.html#variant.MatchPhase)
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum MatchPhase {
+    Countdown,
+    Autonomous,
+    Delay,
+    Teleop,
+    Endgame,
+    /// The match ran to completion, and the robot has been disabled
+    Finished,
+    /// The match was stopped early, and the robot has been disabled
+    Aborted,
+}
+
+/// Handle to a practice match running in the background
+///
+/// The match is aborted if the robot is disabled or E-stopped by anything other than the match itself, if
+/// [`abort`](#method.abort) is called, or if this handle is dropped.
+pub struct PracticeMatch {
+    abort: Option<oneshot::Sender<()>>,
+    task: Option<JoinHandle<MatchPhase>>,
+}
+
+impl PracticeMatch {
+    pub(crate) fn start(
+        handle: &Handle,
+        state: Arc<DsState>,
+        timings: PracticeTimings,
+    ) -> PracticeMatch {
+        let (abort_tx, abort_rx) = oneshot::channel();
+        let task = handle.spawn(run(state, timings, abort_rx));
+        PracticeMatch {
+            abort: Some(abort_tx),
+            task: Some(task),
+        }
+    }
+
+    /// Stops the match and disables the robot
+    pub fn abort(&mut self) {
+        if let Some(abort) = self.abort.take() {
+            let _ = abort.send(());
+        }
+    }
+
+    /// Waits for the match to end, returning either `MatchPhase::Finished` or `MatchPhase::Aborted`
+    pub async fn join(mut self) -> Result<MatchPhase> {
+        let task = self.task.take().unwrap();
+        let phase = task.await?;
+        // Keep the abort channel open until the task is done, dropping it would abort the match
+        drop(self.abort.take());
+        Ok(phase)
+    }
+
+    /// Blocks the calling thread until the match ends, returning either `MatchPhase::Finished` or `MatchPhase::Aborted`
+    pub fn wait(self) -> Result<MatchPhase> {
+        futures::executor::block_on(self.join())
+    }
+}
+
+impl Drop for PracticeMatch {
+    fn drop(&mut self) {
+        self.abort();
+    }
+}
+
+/// Drives the robot through each period of the match
+async fn run(
+    state: Arc<DsState>,
+    timings: PracticeTimings,
+    mut abort: oneshot::Receiver<()>,
+) -> MatchPhase {
+    let teleop_end = timings.teleop;
+    let endgame = timings.endgame.min(timings.teleop);
+    // (phase, length of the phase, length of the period being counted down, mode, enabled)
+    let phases = [
+        (
+            MatchPhase::Countdown,
+            timings.countdown,
+            timings.countdown,
+            Mode::Autonomous,
+            false,
+        ),
+        (
+            MatchPhase::Autonomous,
+            timings.autonomous,
+            timings.autonomous,
+            Mode::Autonomous,
+            true,
+        ),
+        (
+            MatchPhase::Delay,
+            timings.delay,
+            timings.delay,
+            Mode::Teleoperated,
+            false,
+        ),
+        (
+            MatchPhase::Teleop,
+            teleop_end - endgame,
+            teleop_end,
+            Mode::Teleoperated,
+            true,
+        ),
+        (
+            MatchPhase::Endgame,
+            endgame,
+            endgame,
+            Mode::Teleoperated,
+            true,
+        ),
+    ];
+
+    for &(phase, length, period, mode, enabled) in phases.iter() {
+        if length == Duration::from_secs(0) {
+            continue;
+        }
+
+        {
+            let mut send = state.send().lock().await;
+            if enabled && send.estopped() {
+                drop(send);
+                return stop(&state, MatchPhase::Aborted).await;
+            }
+            send.set_mode(mode);
+            // The endgame carries on from teleop, so only change the enabled state on real transitions
+            if phase != MatchPhase::Endgame {
+                if enabled {
+                    send.enable();
+                } else {
+                    send.disable();
+                }
+            }
+        }
+        state.update_status().await;
+        state.emit(DsEvent::MatchPhase(phase));
+
+        // The countdown sent to the robot is the time left in the whole period, which for teleop includes the endgame
+        let clock = state.clock();
+        let end = clock.instant() + length;
+        let period_end = end + (period - length);
+        loop {
+            let now = clock.instant();
+            if now >= end {
+                break;
+            }
+
+            {
+                let mut send = state.send().lock().await;
+                if enabled && !send.enabled() {
+                    drop(send);
+                    return stop(&state, MatchPhase::Aborted).await;
+                }
+                let remaining = period_end - now;
+                send.queue_udp(UdpTag::Countdown(Countdown::new(remaining.as_secs_f32())));
+            }
+
+            let tick = clock.delay_for(TICK.min(end - now));
+            if let Either::Right(_) = select(tick, &mut abort).await {
+                return stop(&state, MatchPhase::Aborted).await;
+            }
+        }
+    }
+
+    stop(&state, MatchPhase::Finished).await
+}
+
+async fn stop(state: &DsState, phase: MatchPhase) -> MatchPhase {
+    state.send().lock().await.disable();
+    state.update_status().await;
+    state.emit(DsEvent::MatchPhase(phase));
+    phase
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ds::SystemClock;
+    use crate::Alliance;
+    use tokio::time;
+
+    #[tokio::test]
+    async fn runs_each_period() {
+        // While time is paused, tokio skips ahead to the next timer whenever every task is idle
+        time::pause();
+        let state = Arc::new(DsState::new(Alliance::new_red(1), Arc::new(SystemClock)));
+        let mut events = state.subscribe_events();
+        let start = time::Instant::now();
+        let recorder = tokio::spawn(async move {
+            let mut started = Vec::new();
+            while let Ok(DsEvent::MatchPhase(phase)) = events.recv().await {
+                // Timers are rounded up to the millisecond, so only whole seconds are compared
+                let elapsed = (time::Instant::now() - start).as_secs_f64().round() as u64;
+                started.push((phase, elapsed));
+                if phase == MatchPhase::Finished {
+                    break;
+                }
+            }
+            started
+        });
+
+        let practice = PracticeMatch::start(
+            &Handle::current(),
+            state.clone(),
+            PracticeTimings::default(),
+        );
+        assert_eq!(practice.join().await.unwrap(), MatchPhase::Finished);
+        assert_eq!(
+            recorder.await.unwrap(),
+            vec![
+                (MatchPhase::Countdown, 0),
+                (MatchPhase::Autonomous, 3),
+                (MatchPhase::Delay, 18),
+                (MatchPhase::Teleop, 19),
+                (MatchPhase::Endgame, 134),
+                (MatchPhase::Finished, 154),
+            ]
+        );
+        assert!(!state.send().lock().await.enabled());
+    }
+
+    #[tokio::test]
+    async fn aborts_when_disabled() {
+        time::pause();
+        let state = Arc::new(DsState::new(Alliance::new_red(1), Arc::new(SystemClock)));
+        let timings = PracticeTimings {
+            countdown: Duration::from_millis(0),
+            ..PracticeTimings::default()
+        };
+
+        let practice = PracticeMatch::start(&Handle::current(), state.clone(), timings);
+        time::advance(TICK).await;
+        assert!(state.send().lock().await.enabled());
+
+        state.send().lock().await.disable();
+        time::advance(TICK).await;
+        assert_eq!(practice.join().await.unwrap(), MatchPhase::Aborted);
+    }
+}
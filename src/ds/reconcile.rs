@@ -0,0 +1,193 @@
+use super::DsEvent;
+
+use crate::proto::udp::inbound::types::{Status, Trace};
+use crate::Mode;
+
+/// The number of consecutive status packets a discrepancy must be seen in before it is reported
+///
+/// The robot takes a packet or two to echo a change in the commanded state, which shouldn't be reported.
+const GRACE_PACKETS: u32 = 25;
+
+/// A disagreement between the state commanded by the driver station and the state reported by the robot
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct StateDiscrepancy {
+    /// What the robot disagrees about
+    pub kind: DiscrepancyKind,
+    /// The likely cause, if one can be inferred from the status and trace reported by the robot
+    pub reason: Option<DiscrepancyReason>,
+}
+
+/// The part of the commanded state that the robot disagrees with
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum DiscrepancyKind {
+    /// The robot is commanded to be enabled, but reports that it is disabled
+    NotEnabled,
+    /// The robot is commanded to be disabled, but reports that it is enabled
+    NotDisabled,
+    /// The robot reports a different mode than the one commanded
+    ModeMismatch {
+        commanded: Mode,
+        reported: Option<Mode>,
+    },
+    /// The robot has been E-stopped, but has not reported it yet
+    EstopNotEchoed,
+}
+
+/// Possible causes of a [`StateDiscrepancy`](struct.StateDiscrepancy.html)
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DiscrepancyReason {
+    /// The robot code isn't running
+    NoRobotCode,
+    /// The robot is browning out
+    Brownout,
+    /// The robot reports that it has been E-stopped
+    RobotEstopped,
+}
+
+/// The state that the driver station is commanding the robot to be in
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct Commanded {
+    pub enabled: bool,
+    pub estopped: bool,
+    pub mode: Mode,
+}
+
+/// Tracks discrepancies between commanded and reported state across status packets
+pub(crate) struct Reconciler {
+    /// The discrepancy seen in the most recent packets, which may not have been reported yet
+    pending: Option<StateDiscrepancy>,
+    /// The number of consecutive packets `pending` has been seen in
+    count: u32,
+    /// The last discrepancy that was reported
+    current: Option<StateDiscrepancy>,
+}
+
+impl Reconciler {
+    pub fn new() -> Reconciler {
+        Reconciler {
+            pending: None,
+            count: 0,
+            current: None,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        *self = Reconciler::new();
+    }
+
+    /// Returns the discrepancy that is currently reported, if any
+    pub fn current(&self) -> Option<StateDiscrepancy> {
+        self.current
+    }
+
+    /// Compares the commanded state to the status and trace of a newly received packet
+    ///
+    /// Returns an event to raise if the reported discrepancy has changed.
+    pub fn update(
+        &mut self,
+        commanded: Commanded,
+        status: Status,
+        trace: Trace,
+    ) -> Option<DsEvent> {
+        let observed = detect(commanded, status, trace);
+        if observed != self.pending {
+            self.pending = observed;
+            self.count = 0;
+        }
+        self.count = self.count.saturating_add(1);
+
+        // Discrepancies have to persist before they're reported, but resolving one is reported straight away
+        if self.pending != self.current && (self.pending.is_none() || self.count >= GRACE_PACKETS) {
+            self.current = self.pending;
+            return Some(match self.current {
+                Some(discrepancy) => DsEvent::StateDiscrepancy(discrepancy),
+                None => DsEvent::StateReconciled,
+            });
+        }
+
+        None
+    }
+}
+
+/// Finds the most significant disagreement between the commanded and reported state, if there is one
+fn detect(commanded: Commanded, status: Status, trace: Trace) -> Option<StateDiscrepancy> {
+    let kind = if commanded.estopped && !status.emergency_stopped() {
+        DiscrepancyKind::EstopNotEchoed
+    } else if commanded.enabled && !status.contains(Status::ENABLED) {
+        DiscrepancyKind::NotEnabled
+    } else if !commanded.enabled && status.contains(Status::ENABLED) {
+        DiscrepancyKind::NotDisabled
+    } else {
+        let reported = Mode::from_status(status);
+        if reported == Some(commanded.mode) {
+            return None;
+        }
+        DiscrepancyKind::ModeMismatch {
+            commanded: commanded.mode,
+            reported,
+        }
+    };
+
+    let reason = if kind == DiscrepancyKind::EstopNotEchoed {
+        None
+    } else if status.emergency_stopped() {
+        Some(DiscrepancyReason::RobotEstopped)
+    } else if status.is_browning_out() {
+        Some(DiscrepancyReason::Brownout)
+    } else if !trace.is_code_started() {
+        Some(DiscrepancyReason::NoRobotCode)
+    } else {
+        None
+    };
+
+    Some(StateDiscrepancy { kind, reason })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const ENABLED_TELEOP: Commanded = Commanded {
+        enabled: true,
+        estopped: false,
+        mode: Mode::Teleoperated,
+    };
+
+    #[test]
+    fn detects_missing_code() {
+        let discrepancy = detect(ENABLED_TELEOP, Status::empty(), Trace::IS_ROBORIO).unwrap();
+        assert_eq!(discrepancy.kind, DiscrepancyKind::NotEnabled);
+        assert_eq!(discrepancy.reason, Some(DiscrepancyReason::NoRobotCode));
+
+        let trace = Trace::IS_ROBORIO | Trace::ROBOT_CODE | Trace::TELEOP;
+        assert_eq!(detect(ENABLED_TELEOP, Status::ENABLED, trace), None);
+    }
+
+    #[test]
+    fn reports_after_grace_period() {
+        let mut reconciler = Reconciler::new();
+        let trace = Trace::IS_ROBORIO | Trace::ROBOT_CODE;
+        let status = Status::ENABLED | Status::AUTO;
+
+        for _ in 1..GRACE_PACKETS {
+            assert_eq!(reconciler.update(ENABLED_TELEOP, status, trace), None);
+        }
+        let expected = StateDiscrepancy {
+            kind: DiscrepancyKind::ModeMismatch {
+                commanded: Mode::Teleoperated,
+                reported: Some(Mode::Autonomous),
+            },
+            reason: None,
+        };
+        assert_eq!(
+            reconciler.update(ENABLED_TELEOP, status, trace),
+            Some(DsEvent::StateDiscrepancy(expected))
+        );
+        assert_eq!(reconciler.update(ENABLED_TELEOP, status, trace), None);
+
+        assert_eq!(
+            reconciler.update(ENABLED_TELEOP, Status::ENABLED, trace),
+            Some(DsEvent::StateReconciled)
+        );
+    }
+}
@@ -1,16 +1,24 @@
 use super::JoystickValue;
 
-use crate::ds::state::recv::{RecvState, TcpState};
+pub(crate) use crate::ds::state::recv::RecvState;
+use crate::ds::state::recv::TcpState;
 use crate::ds::state::send::SendState;
-use crate::proto::udp::inbound::types::Status;
+use crate::ds::{Clock, DsEvent, StateDiscrepancy, Watchdog};
+use crate::log::DsLogRecord;
+use crate::proto::udp::inbound::types::{Status, Trace};
 use crate::proto::udp::outbound::types::{Alliance, Control};
 use crate::TcpPacket;
 use std::fmt::Debug;
-use tokio::sync::Mutex;
+use std::sync::Arc;
+use tokio::sync::{broadcast, watch, Mutex};
 
+mod link;
 mod recv;
+mod safety;
 mod send;
 
+pub use self::safety::SafetyLatch;
+
 type JoystickSupplier = dyn Fn() -> Vec<Vec<JoystickValue>> + Send + Sync + 'static;
 type TcpConsumer = dyn FnMut(TcpPacket) + Send + Sync + 'static;
 
@@ -24,6 +32,27 @@ pub enum DsMode {
     Simulation,
 }
 
+/// A snapshot of the state of the driver station and the robot it is connected to
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct RobotStatus {
+    /// Whether status packets are being received from the roboRIO
+    pub connected: bool,
+    /// Whether the driver station is commanding the robot to be enabled
+    pub enabled: bool,
+    /// Whether the driver station has E-stopped the robot
+    pub estopped: bool,
+    /// The mode the driver station is commanding the robot to be in
+    pub mode: Mode,
+    /// Whether the driver station is talking to a real robot or the simulator
+    pub ds_mode: DsMode,
+    /// The last received Trace from the robot
+    pub trace: Trace,
+    /// The last received battery voltage from the robot
+    pub battery_voltage: f32,
+    /// The current disagreement between the commanded state and the state reported by the robot, if any
+    pub discrepancy: Option<StateDiscrepancy>,
+}
+
 /// The core state of the driver station, containing locks over all relevant substates
 pub struct DsState {
     /// The state associated with the sending UDP socket
@@ -32,21 +61,99 @@ pub struct DsState {
     recv_state: Mutex<RecvState>,
     /// The state associated with the TCP socket
     tcp_state: Mutex<TcpState>,
+    /// Channel that status snapshots are published to when they change
+    status_tx: watch::Sender<RobotStatus>,
+    status_rx: watch::Receiver<RobotStatus>,
+    /// Channel that events are broadcast on
+    events_tx: broadcast::Sender<DsEvent>,
+    /// Source of time for every task driving this state
+    clock: Arc<dyn Clock>,
+    /// Disable and E-stop requests that bypass the lock on the send state
+    safety: Arc<SafetyLatch>,
 }
 
 impl DsState {
-    pub fn new(alliance: Alliance) -> DsState {
-        let send_state = Mutex::new(SendState::new(alliance));
-        let recv_state = Mutex::new(RecvState::new());
-        let tcp_state = Mutex::new(TcpState::new());
+    pub fn new(alliance: Alliance, clock: Arc<dyn Clock>) -> DsState {
+        let (events_tx, _) = broadcast::channel(64);
+        let safety = Arc::new(SafetyLatch::new());
+        let send = SendState::new(alliance, events_tx.clone(), safety.clone());
+        let recv = RecvState::new();
+        let (status_tx, status_rx) = watch::channel(Self::snapshot(&send, &recv));
 
         DsState {
-            send_state,
-            recv_state,
-            tcp_state,
+            send_state: Mutex::new(send),
+            recv_state: Mutex::new(recv),
+            tcp_state: Mutex::new(TcpState::new()),
+            status_tx,
+            status_rx,
+            events_tx,
+            clock,
+            safety,
+        }
+    }
+
+    fn snapshot(send: &SendState, recv: &RecvState) -> RobotStatus {
+        RobotStatus {
+            connected: recv.connected(),
+            enabled: send.enabled(),
+            estopped: send.estopped(),
+            mode: *send.mode(),
+            ds_mode: *send.ds_mode(),
+            trace: *recv.trace(),
+            battery_voltage: recv.battery_voltage(),
+            discrepancy: recv.reconciler().current(),
+        }
+    }
+
+    /// Builds a `.dslog` record from the current state of the link and of the robot
+    pub async fn log_record(&self) -> DsLogRecord {
+        let send = self.send_state.lock().await;
+        let recv = self.recv_state.lock().await;
+
+        DsLogRecord {
+            watchdog: send.watchdog().is_some_and(Watchdog::expired),
+            ds_teleop: *send.mode() == Mode::Teleoperated,
+            ds_disabled: !send.enabled(),
+            ..recv.log_record(self.clock.instant())
+        }
+    }
+
+    /// Publishes a new status snapshot to subscribers if it differs from the last one
+    pub async fn update_status(&self) {
+        let status = {
+            let send = self.send_state.lock().await;
+            let recv = self.recv_state.lock().await;
+            Self::snapshot(&send, &recv)
+        };
+
+        if *self.status_rx.borrow() != status {
+            let _ = self.status_tx.broadcast(status);
         }
     }
 
+    /// Returns a receiver for status snapshots, yielding the latest one first
+    pub fn subscribe_status(&self) -> watch::Receiver<RobotStatus> {
+        self.status_rx.clone()
+    }
+
+    /// Returns a receiver for events raised after this is called
+    pub fn subscribe_events(&self) -> broadcast::Receiver<DsEvent> {
+        self.events_tx.subscribe()
+    }
+
+    /// Broadcasts an event to all current subscribers
+    pub fn emit(&self, event: DsEvent) {
+        let _ = self.events_tx.send(event);
+    }
+
+    pub fn clock(&self) -> &Arc<dyn Clock> {
+        &self.clock
+    }
+
+    pub fn safety(&self) -> &Arc<SafetyLatch> {
+        &self.safety
+    }
+
     pub fn send(&self) -> &Mutex<SendState> {
         &self.send_state
     }
@@ -71,20 +178,18 @@ pub enum Mode {
 impl Mode {
     /// Decodes the mode of the robot from the given status byte
     pub fn from_status(status: Status) -> Option<Mode> {
-        if status & Status::TELEOP == Status::TELEOP {
-            Some(Mode::Teleoperated)
-        } else if status & Status::AUTO == Status::AUTO {
-            Some(Mode::Autonomous)
-        } else if status & Status::TEST == Status::TEST {
-            Some(Mode::Test)
-        } else {
-            None
+        // The mode is a 2 bit field rather than a set of flags, teleop is 0 so it can't be tested with contains()
+        match status.bits() & 0b11 {
+            0b00 => Some(Mode::Teleoperated),
+            0b01 => Some(Mode::Test),
+            0b10 => Some(Mode::Autonomous),
+            _ => None,
         }
     }
 
     /// Converts this `Mode` into a `Control` byte that can be modified for encoding the control packet.
-    fn to_control(&self) -> Control {
-        match *self {
+    fn to_control(self) -> Control {
+        match self {
             Mode::Teleoperated => Control::TELEOP,
             Mode::Autonomous => Control::AUTO,
             Mode::Test => Control::TEST,
@@ -0,0 +1,120 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// How far back sent packets are kept when computing packet loss
+const WINDOW: Duration = Duration::from_secs(1);
+/// How long a packet is given to be answered before it counts as lost
+const GRACE: Duration = Duration::from_millis(100);
+
+struct SentPacket {
+    seqnum: u16,
+    sent_at: Instant,
+    answered: bool,
+}
+
+/// Measures the round trip time and packet loss of the UDP link, by matching the sequence numbers echoed by the
+/// roboRIO against the packets that were sent
+pub struct LinkStats {
+    sent: VecDeque<SentPacket>,
+    trip_time: Option<Duration>,
+}
+
+impl LinkStats {
+    pub fn new() -> LinkStats {
+        LinkStats {
+            sent: VecDeque::new(),
+            trip_time: None,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.sent.clear();
+        self.trip_time = None;
+    }
+
+    /// Records that the control packet `seqnum` was sent at `at`
+    pub fn sent(&mut self, seqnum: u16, at: Instant) {
+        while let Some(oldest) = self.sent.front() {
+            if at.duration_since(oldest.sent_at) <= WINDOW {
+                break;
+            }
+            self.sent.pop_front();
+        }
+        self.sent.push_back(SentPacket {
+            seqnum,
+            sent_at: at,
+            answered: false,
+        });
+    }
+
+    /// Records that a status packet answering `seqnum` was received at `at`
+    pub fn received(&mut self, seqnum: u16, at: Instant) {
+        if let Some(packet) = self
+            .sent
+            .iter_mut()
+            .rev()
+            .find(|p| p.seqnum == seqnum && !p.answered)
+        {
+            packet.answered = true;
+            self.trip_time = Some(at.duration_since(packet.sent_at));
+        }
+    }
+
+    /// The round trip time of the last answered packet, if any have been answered
+    pub fn trip_time(&self) -> Option<Duration> {
+        self.trip_time
+    }
+
+    /// The fraction of packets sent over the last second that went unanswered, between 0 and 1
+    ///
+    /// Packets sent in the last 100ms are ignored, as their answers may still be on the way.
+    pub fn packet_loss(&self, now: Instant) -> f32 {
+        let (total, lost) = self
+            .sent
+            .iter()
+            .filter(|p| now.duration_since(p.sent_at) >= GRACE)
+            .fold((0, 0), |(total, lost), p| {
+                (total + 1, lost + !p.answered as usize)
+            });
+
+        if total == 0 {
+            0.0
+        } else {
+            lost as f32 / total as f32
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn measures_trip_time_and_loss() {
+        let start = Instant::now();
+        let mut link = LinkStats::new();
+        for seqnum in 0..50u16 {
+            let sent_at = start + Duration::from_millis(u64::from(seqnum) * 20);
+            link.sent(seqnum, sent_at);
+            // Every fifth packet is dropped
+            if seqnum % 5 != 0 {
+                link.received(seqnum, sent_at + Duration::from_millis(3));
+            }
+        }
+
+        assert_eq!(link.trip_time(), Some(Duration::from_millis(3)));
+        let now = start + Duration::from_millis(1080);
+        assert!((link.packet_loss(now) - 0.2).abs() < f32::EPSILON);
+
+        // Old packets fall out of the window
+        link.sent(50, start + Duration::from_millis(2500));
+        link.received(50, start + Duration::from_millis(2501));
+        assert_eq!(link.packet_loss(start + Duration::from_millis(2700)), 0.0);
+        assert_eq!(link.trip_time(), Some(Duration::from_millis(1)));
+
+        // Packets still within the grace period don't count as lost yet
+        link.sent(51, start + Duration::from_millis(2520));
+        assert_eq!(link.packet_loss(start + Duration::from_millis(2600)), 0.0);
+        assert!((link.packet_loss(start + Duration::from_millis(2620)) - 0.5).abs() < f32::EPSILON);
+    }
+}
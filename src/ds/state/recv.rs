@@ -1,10 +1,15 @@
+use crate::ds::reconcile::Reconciler;
+use crate::ds::state::link::LinkStats;
 use crate::ds::state::TcpConsumer;
-use crate::proto::tcp::outbound::TcpTag;
+use crate::log::DsLogRecord;
+use crate::proto::tcp::outbound::{JoystickDesc, TcpTag};
+use crate::proto::udp::inbound::types::tags::*;
 use crate::proto::udp::inbound::types::*;
 use crate::Result;
 use crate::TcpPacket;
 use failure::format_err;
 use futures_channel::mpsc::UnboundedSender;
+use std::time::Instant;
 
 /// All the data received from roboRIO UDP status packets that isn't already encoded in the send state
 pub struct RecvState {
@@ -12,12 +17,38 @@ pub struct RecvState {
     battery_voltage: f32,
     /// A bitflags struct that can be used to query the state of various aspects of the RIO
     trace: Trace,
+    /// The status bits of the last status packet, such as whether the robot is browning out
+    status: Status,
+    /// The last CPU usage reported by the RIO
+    cpu: Option<CpuInfo>,
+    /// The last CAN bus metrics reported by the RIO
+    can: Option<CanMetrics>,
+    /// The last power distribution panel data reported by the RIO
+    pdp: Option<PdpLog>,
+    /// The last memory usage reported by the RIO
+    ram: Option<RamInfo>,
+    /// Number of times the RIO has started browning out. Kept across reconnects, so that it only ever increases
+    brownouts: u64,
+    /// Round trip time and packet loss of the UDP link
+    link: LinkStats,
+    /// Whether packets are currently being received from the RIO
+    connected: bool,
+    /// Tracks disagreements between what is commanded and what the RIO reports
+    reconciler: Reconciler,
 }
 
 impl RecvState {
     pub fn reset(&mut self) {
         self.battery_voltage = 0f32;
         self.trace = Trace::empty();
+        self.status = Status::empty();
+        self.cpu = None;
+        self.can = None;
+        self.pdp = None;
+        self.ram = None;
+        self.link.reset();
+        self.connected = false;
+        self.reconciler.reset();
     }
 }
 
@@ -27,6 +58,8 @@ pub struct TcpState {
     pub tcp_consumer: Option<Box<TcpConsumer>>,
     /// A channel of packets that should be sent to the roboRIO
     pending_tcp: Option<UnboundedSender<TcpTag>>,
+    /// Descriptors of the joysticks on each port, sent every time the connection is established
+    joystick_descriptors: Vec<JoystickDesc>,
 }
 
 impl TcpState {
@@ -34,6 +67,7 @@ impl TcpState {
         TcpState {
             tcp_consumer: None,
             pending_tcp: None,
+            joystick_descriptors: Vec::new(),
         }
     }
 
@@ -50,6 +84,18 @@ impl TcpState {
         self.pending_tcp = tx;
     }
 
+    pub fn set_joystick_descriptors(&mut self, descriptors: Vec<JoystickDesc>) {
+        self.joystick_descriptors = descriptors;
+        self.send_joystick_descriptors();
+    }
+
+    /// Queues the joystick descriptors to be sent, if the connection is established
+    pub fn send_joystick_descriptors(&self) {
+        for desc in &self.joystick_descriptors {
+            let _ = self.queue_tcp(TcpTag::JoystickDesc(desc.clone()));
+        }
+    }
+
     pub fn set_tcp_consumer(&mut self, consumer: impl FnMut(TcpPacket) + Send + Sync + 'static) {
         self.tcp_consumer = Some(Box::new(consumer));
     }
@@ -60,9 +106,34 @@ impl RecvState {
         RecvState {
             battery_voltage: 0f32,
             trace: Trace::empty(),
+            status: Status::empty(),
+            cpu: None,
+            can: None,
+            pdp: None,
+            ram: None,
+            brownouts: 0,
+            link: LinkStats::new(),
+            connected: false,
+            reconciler: Reconciler::new(),
         }
     }
 
+    pub fn reconciler(&self) -> &Reconciler {
+        &self.reconciler
+    }
+
+    pub fn reconciler_mut(&mut self) -> &mut Reconciler {
+        &mut self.reconciler
+    }
+
+    pub fn connected(&self) -> bool {
+        self.connected
+    }
+
+    pub fn set_connected(&mut self, connected: bool) {
+        self.connected = connected;
+    }
+
     pub fn battery_voltage(&self) -> f32 {
         self.battery_voltage
     }
@@ -78,4 +149,70 @@ impl RecvState {
     pub fn set_trace(&mut self, trace: Trace) {
         self.trace = trace;
     }
+
+    pub fn status(&self) -> Status {
+        self.status
+    }
+
+    pub fn set_status(&mut self, status: Status) {
+        if status.is_browning_out() && !self.status.is_browning_out() {
+            self.brownouts += 1;
+        }
+        self.status = status;
+    }
+
+    pub fn brownouts(&self) -> u64 {
+        self.brownouts
+    }
+
+    pub fn cpu(&self) -> Option<&CpuInfo> {
+        self.cpu.as_ref()
+    }
+
+    pub fn can(&self) -> Option<&CanMetrics> {
+        self.can.as_ref()
+    }
+
+    pub fn ram(&self) -> Option<&RamInfo> {
+        self.ram.as_ref()
+    }
+
+    /// Stores the tags of a status packet. The RIO only sends most tags every so often, so the last value of each is kept
+    pub fn update_tags(&mut self, tags: &[RobotTag]) {
+        for tag in tags {
+            match tag {
+                RobotTag::Cpu(cpu) => self.cpu = Some(cpu.clone()),
+                RobotTag::Can(can) => self.can = Some(*can),
+                RobotTag::Pdp(pdp) => self.pdp = Some(pdp.clone()),
+                RobotTag::Ram(ram) => self.ram = Some(*ram),
+                RobotTag::Other { .. } => {}
+            }
+        }
+    }
+
+    pub fn link(&self) -> &LinkStats {
+        &self.link
+    }
+
+    pub fn link_mut(&mut self) -> &mut LinkStats {
+        &mut self.link
+    }
+
+    /// Builds a `.dslog` record from the link and what the RIO reported, leaving the driver station's half at its
+    /// defaults
+    pub fn log_record(&self, now: Instant) -> DsLogRecord {
+        DsLogRecord {
+            trip_time: self.link.trip_time().unwrap_or_default(),
+            packet_loss: self.link.packet_loss(now),
+            battery_voltage: self.battery_voltage,
+            cpu: self.cpu.as_ref().map_or(0.0, CpuInfo::utilization),
+            can_utilization: self.can.map_or(0.0, |can| can.utilization),
+            brownout: self.status.is_browning_out(),
+            robot_teleop: self.trace.is_teleop(),
+            robot_auto: self.trace.is_autonomous(),
+            robot_disabled: self.trace.is_disabled(),
+            pdp: self.pdp.clone(),
+            ..DsLogRecord::default()
+        }
+    }
 }
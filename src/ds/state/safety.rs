@@ -0,0 +1,45 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use tokio::sync::Notify;
+
+/// Disable and E-stop requests that can be raised from any thread without locking the send state
+///
+/// Requests are applied to the send state the next time a control packet is constructed. Raising one also wakes up
+/// the send task, so that the packet goes out straight away rather than at the next 20ms interval.
+pub struct SafetyLatch {
+    disable: AtomicBool,
+    estop: AtomicBool,
+    wake: Notify,
+}
+
+impl SafetyLatch {
+    pub fn new() -> SafetyLatch {
+        SafetyLatch {
+            disable: AtomicBool::new(false),
+            estop: AtomicBool::new(false),
+            wake: Notify::new(),
+        }
+    }
+
+    pub fn disable(&self) {
+        self.disable.store(true, Ordering::SeqCst);
+        self.wake.notify();
+    }
+
+    pub fn estop(&self) {
+        self.estop.store(true, Ordering::SeqCst);
+        self.wake.notify();
+    }
+
+    /// Clears the pending requests, returning whether a disable and an E-stop were requested
+    pub fn take(&self) -> (bool, bool) {
+        (
+            self.disable.swap(false, Ordering::SeqCst),
+            self.estop.swap(false, Ordering::SeqCst),
+        )
+    }
+
+    /// Waits until a request is raised
+    pub async fn woken(&self) {
+        self.wake.notified().await
+    }
+}
@@ -1,11 +1,16 @@
 use log::*;
 
+use crate::ds::state::safety::SafetyLatch;
 use crate::ds::state::{DsMode, JoystickSupplier};
+use crate::ds::{DsEvent, Watchdog};
+use crate::input::{InputProfile, JoystickRecorder};
 use crate::proto::udp::outbound::types::tags::*;
 use crate::proto::udp::outbound::types::{Control, Request};
 use crate::proto::udp::outbound::*;
 use crate::{Alliance, JoystickValue, Mode};
 use std::f32;
+use std::sync::Arc;
+use tokio::sync::broadcast;
 
 /// State containing all the data relevant to constructing a UDP control packet to the roboRIO
 pub struct SendState {
@@ -22,14 +27,28 @@ pub struct SendState {
     /// Any UDP tags that are to be sent with the next UDP control packet
     pending_udp: Vec<UdpTag>,
     /// An optional source for joystick values that will be encoded and sent with the packet
-    joystick_provider: Option<Box<JoystickSupplier>>,
+    joystick_provider: Option<Arc<JoystickSupplier>>,
+    /// The profile applied to the values of each joystick port, if any
+    input_profiles: Vec<Option<InputProfile>>,
+    /// Records the joystick values sent while the robot is enabled, if set
+    joystick_recorder: Option<JoystickRecorder>,
     /// Pending reboot or code restart requests
     pending_request: Option<Request>,
     dsmode: DsMode,
+    /// The watchdog that must be fed for the robot to stay enabled, if one is configured
+    watchdog: Option<Watchdog>,
+    /// Channel used to raise events when the robot is disabled from here
+    events: broadcast::Sender<DsEvent>,
+    /// Disable and E-stop requests raised without taking the lock on this state
+    safety: Arc<SafetyLatch>,
 }
 
 impl SendState {
-    pub fn new(alliance: Alliance) -> SendState {
+    pub fn new(
+        alliance: Alliance,
+        events: broadcast::Sender<DsEvent>,
+        safety: Arc<SafetyLatch>,
+    ) -> SendState {
         SendState {
             mode: Mode::Autonomous,
             udp_seqnum: 0,
@@ -38,11 +57,24 @@ impl SendState {
             alliance,
             pending_udp: Vec::new(),
             joystick_provider: None,
+            input_profiles: Vec::new(),
+            joystick_recorder: None,
             pending_request: None,
             dsmode: DsMode::Normal,
+            watchdog: None,
+            events,
+            safety,
         }
     }
 
+    pub fn set_watchdog(&mut self, watchdog: Option<Watchdog>) {
+        self.watchdog = watchdog;
+    }
+
+    pub fn watchdog(&self) -> Option<&Watchdog> {
+        self.watchdog.as_ref()
+    }
+
     pub fn request(&mut self, request: Request) {
         self.pending_request = Some(request);
     }
@@ -59,70 +91,123 @@ impl SendState {
         &mut self,
         supplier: impl Fn() -> Vec<Vec<JoystickValue>> + Send + Sync + 'static,
     ) {
-        self.joystick_provider = Some(Box::new(supplier))
+        self.joystick_provider = Some(Arc::new(supplier))
+    }
+
+    /// Returns the joystick supplier, so that it can be called without holding the lock on this state
+    pub fn joystick_supplier(&self) -> Option<Arc<JoystickSupplier>> {
+        self.joystick_provider.clone()
+    }
+
+    /// Applies `profile` to the values of joystick port `port`, or stops processing them if it is None
+    pub fn set_input_profile(&mut self, port: usize, profile: Option<InputProfile>) {
+        if self.input_profiles.len() <= port {
+            self.input_profiles.resize(port + 1, None);
+        }
+        self.input_profiles[port] = profile;
+    }
+
+    pub fn set_joystick_recorder(&mut self, recorder: Option<JoystickRecorder>) {
+        self.joystick_recorder = recorder;
+    }
+
+    pub fn joystick_recorder(&self) -> Option<&JoystickRecorder> {
+        self.joystick_recorder.as_ref()
     }
 
     pub fn set_alliance(&mut self, alliance: Alliance) {
         self.alliance = alliance;
     }
 
-    /// Constructs a control packet from the current state
+    /// Constructs a control packet from the current state, with a joysticks tag for each of `joysticks`
+    ///
+    /// The values of each joystick are processed by the input profile of its port before they are encoded.
     ///
-    /// if [self.joystick_provider] is Some, it will be used to construct the joysticks tag
     /// if [self.request] is Some, its value will be consumed and sent to the roboRIO
-    pub fn control(&mut self) -> UdpControlPacket {
-        if let Some(ref supplier) = &self.joystick_provider {
-            let joysticks = supplier();
-
-            // Joystick tags come one after another, iterate over the outer Vec and queue with each loop
-            for joystick in &joysticks {
-                let mut axes = vec![0; 6];
-                let mut buttons = vec![false; 10];
-                let mut povs = vec![-1i16];
-
-                // This has various flaws including the fixed-size vecs,
-                // the use of remove/insert instead of just modifying the entries,
-                // the fact it will panic if you try to use ids beyond the
-                // entries here, and the fact it will always generate a
-                // packet even if there are no changes in the data since last
-                // time (that the last one's a problem is just a theory for now...
-                // but it really shouldn't be necessary to send this data
-                // in every single packet if it has not changed).
-                for value in joystick {
-                    // If statements bound check to stop it from crashing
-                    match value {
-                        JoystickValue::Button { id, pressed } => {
-                            if *id >= 1 && *id <= 10 {
-                                let id = id - 1;
-                                buttons.remove(id as usize);
-                                buttons.insert(id as usize, *pressed)
-                            }
+    /// if [self.watchdog] is Some and has expired, the robot will be disabled before the packet is constructed
+    /// if [self.safety] holds a disable or E-stop request, it is applied before the packet is constructed
+    pub fn control(&mut self, joysticks: &[Vec<JoystickValue>]) -> UdpControlPacket {
+        let (disable, estop) = self.safety.take();
+        if disable {
+            self.disable();
+        }
+        if estop {
+            self.estop();
+        }
+
+        if let Some(ref watchdog) = self.watchdog {
+            if self.enabled && watchdog.expired() {
+                warn!(
+                    "Watchdog not fed within {:?}, disabling robot",
+                    watchdog.window()
+                );
+                self.disable();
+                let _ = self.events.send(DsEvent::WatchdogExpired);
+            }
+        }
+
+        // Joystick tags come one after another, iterate over the outer Vec and queue with each loop
+        for (port, joystick) in joysticks.iter().enumerate() {
+            let processed;
+            let joystick = match self.input_profiles.get(port) {
+                Some(Some(profile)) => {
+                    processed = profile.apply(joystick);
+                    &processed
+                }
+                _ => joystick,
+            };
+
+            let mut axes = vec![0; 6];
+            // Robot code has always been sent at least 10 buttons, more are sent when a joystick has them
+            let button_count = joystick
+                .iter()
+                .filter_map(|value| match value {
+                    JoystickValue::Button { id, .. } if *id <= MAX_BUTTONS => Some(*id),
+                    _ => None,
+                })
+                .max()
+                .unwrap_or(0)
+                .max(10);
+            let mut buttons = vec![false; button_count as usize];
+            let mut povs = vec![-1i16];
+
+            // This has various flaws including the fixed-size axis and POV vecs, and the fact
+            // it will always generate a packet even if there are no changes in the data since last
+            // time (that the last one's a problem is just a theory for now...
+            // but it really shouldn't be necessary to send this data
+            // in every single packet if it has not changed).
+            for value in joystick {
+                // If statements bound check to stop it from crashing
+                match value {
+                    JoystickValue::Button { id, pressed } => {
+                        if *id >= 1 && *id <= MAX_BUTTONS {
+                            buttons[*id as usize - 1] = *pressed;
                         }
-                        JoystickValue::Axis { id, value } => {
-                            if *id <= 5 {
-                                let value = if (*value - 1.0).abs() < f32::EPSILON {
-                                    127i8
-                                } else {
-                                    (value * 128f32) as i8
-                                };
-
-                                axes.remove(*id as usize);
-                                axes.insert(*id as usize, value);
-                            }
+                    }
+                    JoystickValue::Axis { id, value } => {
+                        if *id <= 5 {
+                            let value = if (*value - 1.0).abs() < f32::EPSILON {
+                                127i8
+                            } else {
+                                (value * 128f32) as i8
+                            };
+
+                            axes.remove(*id as usize);
+                            axes.insert(*id as usize, value);
                         }
-                        JoystickValue::POV { id, angle } => {
-                            if *id == 0 {
-                                povs.remove(*id as usize);
-                                povs.insert(*id as usize, *angle);
-                            }
+                    }
+                    JoystickValue::POV { id, angle } => {
+                        if *id == 0 {
+                            povs.remove(*id as usize);
+                            povs.insert(*id as usize, *angle);
                         }
                     }
                 }
-
-                let tag = Joysticks::new(axes, buttons, povs);
-                // debug!("{}", hex::encode(tag.data()));
-                self.queue_udp(UdpTag::Joysticks(tag));
             }
+
+            let tag = Joysticks::new(axes, buttons, povs);
+            // debug!("{}", hex::encode(tag.data()));
+            self.queue_udp(UdpTag::Joysticks(tag));
         }
 
         let mut control = self.mode.to_control();
@@ -135,25 +220,12 @@ impl SendState {
             control |= Control::ESTOP
         }
 
-        let mut tags: Vec<Box<dyn Tag>> = Vec::new();
-
-        for tag in self.pending_udp.clone() {
-            match tag {
-                UdpTag::Timezone(tz) => tags.push(Box::new(tz)),
-                UdpTag::DateTime(dt) => tags.push(Box::new(dt)),
-                UdpTag::Joysticks(joy) => tags.push(Box::new(joy)),
-                UdpTag::Countdown(cnt) => tags.push(Box::new(cnt)),
-            }
-        }
-
-        self.pending_udp.clear();
-
         UdpControlPacket {
             seqnum: self.udp_seqnum,
             control,
             request: self.pending_request.take(),
             alliance: self.alliance,
-            tags,
+            tags: self.pending_udp.drain(..).collect(),
         }
     }
 
@@ -181,12 +253,15 @@ impl SendState {
         self.udp_seqnum = 0;
     }
 
-    #[allow(unused)]
     pub fn seqnum(&self) -> u16 {
         self.udp_seqnum
     }
 
     pub fn enable(&mut self) {
+        // Enabling is as good a sign of life as any, and stops a stale watchdog from disabling the robot straight away
+        if let Some(ref watchdog) = self.watchdog {
+            watchdog.feed();
+        }
         self.enabled = true;
     }
 
@@ -207,3 +282,90 @@ impl SendState {
         self.estopped
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ds::SystemClock;
+    use std::time::Duration;
+    use tokio::time;
+
+    #[tokio::test]
+    async fn watchdog_disables_when_starved() {
+        time::pause();
+        let (tx, mut rx) = broadcast::channel(4);
+        let mut state = SendState::new(Alliance::new_red(1), tx, Arc::new(SafetyLatch::new()));
+        let watchdog = Watchdog::new(Duration::from_millis(20), Arc::new(SystemClock));
+        state.set_watchdog(Some(watchdog.clone()));
+
+        state.enable();
+        assert!(state.control(&[]).control.contains(Control::ENABLED));
+
+        time::advance(Duration::from_millis(30)).await;
+        watchdog.feed();
+        assert!(state.control(&[]).control.contains(Control::ENABLED));
+
+        time::advance(Duration::from_millis(30)).await;
+        assert!(!state.control(&[]).control.contains(Control::ENABLED));
+        assert!(!state.enabled());
+        assert_eq!(rx.try_recv().unwrap(), DsEvent::WatchdogExpired);
+    }
+
+    #[test]
+    fn applies_input_profiles() {
+        let (tx, _) = broadcast::channel(4);
+        let mut state = SendState::new(Alliance::new_red(1), tx, Arc::new(SafetyLatch::new()));
+        state.set_input_profile(1, Some(InputProfile::new().remap_button(1, 3)));
+
+        let joystick = vec![JoystickValue::Button {
+            id: 1,
+            pressed: true,
+        }];
+        let packet = state.control(&[joystick.clone(), joystick]);
+        let buttons = packet
+            .tags
+            .iter()
+            .map(|tag| match tag {
+                UdpTag::Joysticks(joysticks) => joysticks.buttons()[..3].to_vec(),
+                _ => panic!("Unexpected tag {:?}", tag),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            buttons,
+            vec![vec![true, false, false], vec![false, false, true]]
+        );
+    }
+
+    #[test]
+    fn sends_buttons_past_ten() {
+        let (tx, _) = broadcast::channel(4);
+        let mut state = SendState::new(Alliance::new_red(1), tx, Arc::new(SafetyLatch::new()));
+
+        // The touchpad of a PS4 controller is button 14
+        let joystick = vec![
+            JoystickValue::Button {
+                id: 14,
+                pressed: true,
+            },
+            JoystickValue::Button {
+                id: MAX_BUTTONS + 1,
+                pressed: true,
+            },
+        ];
+        let packet = state.control(&[joystick, Vec::new()]);
+        let encoded = packet.encode();
+        let decoded = UdpControlPacket::decode(&mut &encoded[..]).unwrap();
+
+        let buttons = decoded
+            .tags
+            .iter()
+            .map(|tag| match tag {
+                UdpTag::Joysticks(joysticks) => joysticks.buttons().to_vec(),
+                _ => panic!("Unexpected tag {:?}", tag),
+            })
+            .collect::<Vec<_>>();
+        let mut expected = vec![false; 14];
+        expected[13] = true;
+        assert_eq!(buttons, vec![expected, vec![false; 10]]);
+    }
+}
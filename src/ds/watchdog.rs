@@ -0,0 +1,51 @@
+use super::Clock;
+
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Handle used to feed the driver station's communication watchdog
+///
+/// When a watchdog is configured, the robot is disabled if it isn't fed at least once every window. Handles can be
+/// cloned freely, e.g. to be moved into a joystick supplier so that it only feeds the watchdog when it has fresh input.
+#[derive(Clone)]
+pub struct Watchdog {
+    window: Duration,
+    last_fed: Arc<Mutex<Instant>>,
+    clock: Arc<dyn Clock>,
+}
+
+impl Watchdog {
+    pub(crate) fn new(window: Duration, clock: Arc<dyn Clock>) -> Watchdog {
+        Watchdog {
+            window,
+            last_fed: Arc::new(Mutex::new(clock.instant())),
+            clock,
+        }
+    }
+
+    /// Resets the watchdog, proving that the application is still alive
+    pub fn feed(&self) {
+        *self.last_fed.lock().unwrap() = self.clock.instant();
+    }
+
+    /// Returns the window that the watchdog must be fed within
+    pub fn window(&self) -> Duration {
+        self.window
+    }
+
+    /// Returns whether the watchdog has gone unfed for longer than its window
+    pub fn expired(&self) -> bool {
+        let last_fed = *self.last_fed.lock().unwrap();
+        self.clock.instant().duration_since(last_fed) > self.window
+    }
+}
+
+impl fmt::Debug for Watchdog {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Watchdog")
+            .field("window", &self.window)
+            .field("last_fed", &self.last_fed)
+            .finish()
+    }
+}
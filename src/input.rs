@@ -0,0 +1,41 @@
+//! Sources of driver input for a [`DriverStation`](../struct.DriverStation.html)
+//!
+//! Keyboards are represented as streams of [`KeyEvent`](struct.KeyEvent.html)s, so that the same components work with
+//! keys read from a terminal or from a Linux input device. [`SafetyKeys`](struct.SafetyKeys.html) turns the NI driver
+//! station's safety hotkeys into disable and E-stop requests.
+//!
+//! Gamepads are mapped to the layouts of WPILib's `XboxController` and `PS4Controller` by
+//! [`Gamepad`](struct.Gamepad.html), and assigned to ports by [`Gamepads`](struct.Gamepads.html), which keeps the
+//! ports of the other gamepads when one is unplugged. The `gilrs` feature adds
+//! [`GilrsGamepads`](struct.GilrsGamepads.html), which reads gamepads with gilrs on any platform it supports. On
+//! Linux, the `evdev` feature adds [`EvdevGamepads`](struct.EvdevGamepads.html), which reads gamepads straight from
+//! `/dev/input` without other libraries. Both keep each gamepad on the same port while it is unplugged.
+//!
+//! Without a gamepad, [`VirtualJoystick`](struct.VirtualJoystick.html) drives one from the keyboard.
+//!
+//! Whatever the source, an [`InputProfile`](struct.InputProfile.html) applies a driver's deadbands, curves, and button
+//! layout to the values of a port before they are sent. A [`JoystickRecorder`](struct.JoystickRecorder.html) saves
+//! the values sent while the robot is enabled, and [`JoystickPlayback`](struct.JoystickPlayback.html) sends them again
+//! in place of a driver.
+
+#[cfg(all(feature = "evdev", target_os = "linux"))]
+mod evdev;
+mod gamepad;
+#[cfg(feature = "gilrs")]
+mod gilrs;
+mod keys;
+mod profile;
+mod recording;
+mod safety;
+mod virtual_joystick;
+
+#[cfg(all(feature = "evdev", target_os = "linux"))]
+pub use self::evdev::EvdevGamepads;
+pub use self::gamepad::{Gamepad, GamepadAxis, GamepadButton, GamepadLayout, Gamepads, MAX_PORTS};
+#[cfg(feature = "gilrs")]
+pub use self::gilrs::GilrsGamepads;
+pub use self::keys::{read_terminal_keys, Key, KeyEvent};
+pub use self::profile::{AxisButton, AxisProfile, InputProfile};
+pub use self::recording::{JoystickPlayback, JoystickRecorder, JoystickRecording, JoystickSample};
+pub use self::safety::SafetyKeys;
+pub use self::virtual_joystick::VirtualJoystick;
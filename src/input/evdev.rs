@@ -0,0 +1,431 @@
+use super::gamepad::SharedGamepads;
+use super::{Gamepad, GamepadAxis, GamepadButton, GamepadLayout, Gamepads};
+use crate::{AsyncDriverStation, DriverStation};
+
+use futures::executor::block_on;
+use log::*;
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::{self, ErrorKind, Read};
+use std::mem;
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard, Weak};
+use std::thread;
+use std::time::Duration;
+
+/// How often the input directory is scanned for new gamepads
+const SCAN_PERIOD: Duration = Duration::from_secs(1);
+
+const EV_KEY: u16 = 0x01;
+const EV_ABS: u16 = 0x03;
+
+const ABS_X: u16 = 0x00;
+const ABS_Y: u16 = 0x01;
+const ABS_Z: u16 = 0x02;
+const ABS_RX: u16 = 0x03;
+const ABS_RY: u16 = 0x04;
+const ABS_RZ: u16 = 0x05;
+const ABS_HAT0X: u16 = 0x10;
+const ABS_HAT0Y: u16 = 0x11;
+
+/// The first gamepad button, which every gamepad has
+const BTN_SOUTH: u16 = 0x130;
+const KEY_MAX: usize = 0x2ff;
+
+/// The axes whose ranges are queried, indexed by their event code
+const AXES: [GamepadAxis; 6] = [
+    GamepadAxis::LeftStickX,
+    GamepadAxis::LeftStickY,
+    GamepadAxis::LeftTrigger,
+    GamepadAxis::RightStickX,
+    GamepadAxis::RightStickY,
+    GamepadAxis::RightTrigger,
+];
+
+/// Returns the request number of an ioctl reading `size` bytes from an input device, like the kernel's `_IOR('E', nr)`
+fn ioc_read(nr: u16, size: usize) -> libc::c_ulong {
+    (2 << 30)
+        | ((size as libc::c_ulong) << 16)
+        | ((b'E' as libc::c_ulong) << 8)
+        | nr as libc::c_ulong
+}
+
+/// Reads the value of an ioctl into `buf`
+fn ioctl(file: &File, nr: u16, buf: &mut [u8]) -> io::Result<()> {
+    let request = ioc_read(nr, buf.len());
+    if unsafe { libc::ioctl(file.as_raw_fd(), request as _, buf.as_mut_ptr()) } < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+/// Reads a string property of an input device, such as its name
+fn ioctl_string(file: &File, nr: u16) -> String {
+    let mut buf = [0; 256];
+    match ioctl(file, nr, &mut buf) {
+        Ok(()) => {
+            let len = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
+            String::from_utf8_lossy(&buf[..len]).into_owned()
+        }
+        Err(_) => String::new(),
+    }
+}
+
+/// An input device that reports gamepad buttons
+struct Device {
+    file: File,
+    path: PathBuf,
+    name: String,
+    /// Identifies the gamepad across replugs: its serial number if it has one, or otherwise the USB port it is in
+    key: String,
+    /// The minimum and maximum of each axis, indexed by event code
+    ranges: [(i32, i32); 6],
+}
+
+impl Device {
+    /// Opens the input device at `path`, returning None if it isn't a gamepad
+    fn open(path: &Path) -> io::Result<Option<Device>> {
+        let file = File::open(path)?;
+
+        let mut keys = [0u8; KEY_MAX / 8 + 1];
+        ioctl(&file, 0x20 + EV_KEY, &mut keys)?;
+        let south = BTN_SOUTH as usize;
+        if keys[south / 8] & (1 << (south % 8)) == 0 {
+            return Ok(None);
+        }
+
+        let mut ranges = [(0, 0); 6];
+        for (code, range) in ranges.iter_mut().enumerate() {
+            let mut info = [0u8; mem::size_of::<libc::input_absinfo>()];
+            if ioctl(&file, 0x40 + code as u16, &mut info).is_ok() {
+                let info: libc::input_absinfo =
+                    unsafe { std::ptr::read_unaligned(info.as_ptr() as *const _) };
+                *range = (info.minimum, info.maximum);
+            }
+        }
+
+        let name = ioctl_string(&file, 0x06);
+        let phys = ioctl_string(&file, 0x07);
+        let uniq = ioctl_string(&file, 0x08);
+        let key = if !uniq.is_empty() {
+            uniq
+        } else if !phys.is_empty() {
+            phys
+        } else {
+            path.display().to_string()
+        };
+
+        Ok(Some(Device {
+            file,
+            path: path.to_path_buf(),
+            name,
+            key,
+            ranges,
+        }))
+    }
+}
+
+/// Scales `value` from `range` to 0 to 1, or -1 to 1 if `centred`
+fn normalize(value: i32, (min, max): (i32, i32), centred: bool) -> f32 {
+    if max <= min {
+        return 0.0;
+    }
+    let fraction = (value - min) as f32 / (max - min) as f32;
+    if centred {
+        fraction * 2.0 - 1.0
+    } else {
+        fraction
+    }
+}
+
+/// Applies an input event to a gamepad
+///
+/// Linux reports stick Y axes with down positive, so they are inverted to match the convention of `GamepadAxis`.
+fn apply(gamepad: &mut Gamepad, ranges: &[(i32, i32); 6], kind: u16, code: u16, value: i32) {
+    match kind {
+        EV_KEY => {
+            let button = match code {
+                0x130 => GamepadButton::South,
+                0x131 => GamepadButton::East,
+                0x133 => GamepadButton::North,
+                0x134 => GamepadButton::West,
+                0x136 => GamepadButton::LeftBumper,
+                0x137 => GamepadButton::RightBumper,
+                0x138 => GamepadButton::LeftTrigger,
+                0x139 => GamepadButton::RightTrigger,
+                0x13a => GamepadButton::Select,
+                0x13b => GamepadButton::Start,
+                0x13c => GamepadButton::Mode,
+                0x13d => GamepadButton::LeftStick,
+                0x13e => GamepadButton::RightStick,
+                0x220 => GamepadButton::DPadUp,
+                0x221 => GamepadButton::DPadDown,
+                0x222 => GamepadButton::DPadLeft,
+                0x223 => GamepadButton::DPadRight,
+                _ => return,
+            };
+            gamepad.set_button(button, value != 0);
+        }
+        EV_ABS => match code {
+            ABS_X | ABS_Y | ABS_Z | ABS_RX | ABS_RY | ABS_RZ => {
+                let axis = AXES[code as usize];
+                let range = ranges[code as usize];
+                let value = match axis {
+                    GamepadAxis::LeftTrigger | GamepadAxis::RightTrigger => {
+                        normalize(value, range, false)
+                    }
+                    GamepadAxis::LeftStickY | GamepadAxis::RightStickY => {
+                        -normalize(value, range, true)
+                    }
+                    _ => normalize(value, range, true),
+                };
+                gamepad.set_axis(axis, value);
+            }
+            ABS_HAT0X => {
+                gamepad.set_button(GamepadButton::DPadLeft, value < 0);
+                gamepad.set_button(GamepadButton::DPadRight, value > 0);
+            }
+            ABS_HAT0Y => {
+                gamepad.set_button(GamepadButton::DPadUp, value < 0);
+                gamepad.set_button(GamepadButton::DPadDown, value > 0);
+            }
+            _ => {}
+        },
+        _ => {}
+    }
+}
+
+/// The gamepads shared between the scanning and reading threads
+type Shared = SharedGamepads<String>;
+
+/// Paths of the devices currently being read, or that aren't gamepads
+type Seen = Arc<Mutex<HashSet<PathBuf>>>;
+
+/// Gamepads read directly from Linux input devices, `/dev/input/event*`
+///
+/// The input directory is scanned every second for gamepads, each of which is read on its own thread. Gamepads are
+/// assigned ports by [`Gamepads`](struct.Gamepads.html), with the ports locked: a gamepad that is unplugged keeps its
+/// port until it is plugged back in. Gamepads are recognised by their serial number, or by the USB port they are
+/// plugged in to if they don't report one.
+///
+/// Reading input devices needs permission, usually by being in the `input` group. The scanning thread stops once this
+/// and the joystick supplier installed by [`install`](#method.install) have been dropped.
+pub struct EvdevGamepads {
+    shared: Arc<Shared>,
+}
+
+impl EvdevGamepads {
+    /// Starts watching `/dev/input` for gamepads
+    pub fn start() -> EvdevGamepads {
+        EvdevGamepads::start_in("/dev/input")
+    }
+
+    /// Starts watching `dir` for input devices that are gamepads
+    pub fn start_in(dir: impl Into<PathBuf>) -> EvdevGamepads {
+        let shared = Arc::new(Shared::new());
+        let seen = Seen::default();
+
+        let dir = dir.into();
+        let weak = Arc::downgrade(&shared);
+        thread::spawn(move || {
+            while let Some(shared) = weak.upgrade() {
+                scan(&dir, &shared, &seen);
+                drop(shared);
+                thread::sleep(SCAN_PERIOD);
+            }
+        });
+
+        EvdevGamepads { shared }
+    }
+
+    /// Sends the values of the gamepads with every control packet of `ds`, replacing its joystick supplier
+    ///
+    /// Descriptors are sent to robot code now, and again whenever a gamepad is plugged in or unplugged.
+    pub fn install(&self, ds: &mut DriverStation) {
+        block_on(self.install_async(ds.as_async()));
+    }
+
+    /// Sends the values of the gamepads with every control packet of `ds`, replacing its joystick supplier
+    pub async fn install_async(&self, ds: &mut AsyncDriverStation) {
+        Shared::install(self.shared.clone(), ds).await;
+    }
+
+    /// Returns the gamepads, to show their state or to unlock their ports
+    pub fn gamepads(&self) -> MutexGuard<'_, Gamepads<String>> {
+        self.shared.gamepads()
+    }
+}
+
+/// Opens the gamepads in `dir` that haven't been seen before, and starts reading them
+fn scan(dir: &Path, shared: &Arc<Shared>, seen: &Seen) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            debug!("Failed to scan {} for gamepads: {}", dir.display(), e);
+            return;
+        }
+    };
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let is_event = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| name.starts_with("event"));
+        if !is_event || !seen.lock().unwrap().insert(path.clone()) {
+            continue;
+        }
+
+        match Device::open(&path) {
+            Ok(Some(device)) => {
+                let port = shared.gamepads().connect(
+                    device.key.clone(),
+                    Gamepad::new(device.name.clone(), GamepadLayout::from_name(&device.name)),
+                );
+                match port {
+                    Some(port) => {
+                        info!("Gamepad {} connected on port {}", device.name, port);
+                        shared.update_descriptors();
+                        let weak = Arc::downgrade(shared);
+                        let seen = seen.clone();
+                        thread::spawn(move || read(device, weak, seen));
+                    }
+                    None => {
+                        warn!("No free joystick port for gamepad {}", device.name);
+                        // Try again on the next scan, in case a port has been freed
+                        seen.lock().unwrap().remove(&path);
+                    }
+                }
+            }
+            // Devices that aren't gamepads stay in the seen set, so they aren't opened again
+            Ok(None) => {}
+            Err(e) => {
+                // Devices that can't be opened, usually for lack of permission, are only reported once
+                if e.kind() == ErrorKind::PermissionDenied {
+                    warn!(
+                        "Permission denied opening {}, is this user in the input group?",
+                        path.display()
+                    );
+                } else {
+                    debug!("Failed to open {}: {}", path.display(), e);
+                }
+            }
+        }
+    }
+}
+
+/// Reads the events of a gamepad until it is unplugged
+fn read(device: Device, shared: Weak<Shared>, seen: Seen) {
+    let size = mem::size_of::<libc::input_event>();
+    let mut buf = vec![0u8; size * 64];
+    let mut file = &device.file;
+
+    loop {
+        let n = match file.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(_) => break,
+        };
+        let shared = match shared.upgrade() {
+            Some(shared) => shared,
+            None => return,
+        };
+
+        let mut gamepads = shared.gamepads();
+        if let Some(gamepad) = gamepads.get_mut(&device.key) {
+            for chunk in buf[..n].chunks_exact(size) {
+                let event: libc::input_event =
+                    unsafe { std::ptr::read_unaligned(chunk.as_ptr() as *const _) };
+                apply(
+                    gamepad,
+                    &device.ranges,
+                    event.type_,
+                    event.code,
+                    event.value,
+                );
+            }
+        }
+    }
+
+    if let Some(shared) = shared.upgrade() {
+        info!("Gamepad {} disconnected", device.name);
+        shared.gamepads().disconnect(&device.key);
+        seen.lock().unwrap().remove(&device.path);
+        shared.update_descriptors();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn applies_events() {
+        let ranges = [
+            (-32768, 32767),
+            (-32768, 32767),
+            (0, 1023),
+            (-32768, 32767),
+            (-32768, 32767),
+            (0, 255),
+        ];
+        let mut gamepad = Gamepad::new("Xbox", GamepadLayout::Xbox);
+
+        apply(&mut gamepad, &ranges, EV_ABS, ABS_Y, -32768);
+        apply(&mut gamepad, &ranges, EV_ABS, ABS_RX, 32767);
+        apply(&mut gamepad, &ranges, EV_ABS, ABS_RZ, 255);
+        apply(&mut gamepad, &ranges, EV_ABS, ABS_HAT0Y, -1);
+        apply(&mut gamepad, &ranges, EV_KEY, 0x134, 1);
+        apply(&mut gamepad, &ranges, EV_KEY, 0x13b, 1);
+        apply(&mut gamepad, &ranges, EV_KEY, 0x13b, 0);
+
+        // Pushing the left stick all the way up reads as up, which robot code then sees as negative
+        assert_eq!(gamepad.axis(GamepadAxis::LeftStickY), 1.0);
+        assert_eq!(gamepad.axis(GamepadAxis::RightStickX), 1.0);
+        assert_eq!(gamepad.axis(GamepadAxis::RightTrigger), 1.0);
+        assert_eq!(gamepad.pov(), 0);
+        assert!(gamepad.pressed(GamepadButton::West));
+        assert!(!gamepad.pressed(GamepadButton::Start));
+
+        apply(&mut gamepad, &ranges, EV_ABS, ABS_HAT0Y, 0);
+        apply(&mut gamepad, &ranges, EV_ABS, ABS_HAT0X, 1);
+        assert_eq!(gamepad.pov(), 90);
+    }
+
+    #[test]
+    fn encodes_ioctl_requests() {
+        // EVIOCGNAME(256) and EVIOCGABS(ABS_X) as defined by linux/input.h
+        assert_eq!(ioc_read(0x06, 256), 0x8100_4506);
+        assert_eq!(
+            ioc_read(0x40, mem::size_of::<libc::input_absinfo>()),
+            0x8018_4540
+        );
+    }
+
+    #[tokio::test]
+    async fn installs_as_supplier() {
+        use crate::input::MAX_PORTS;
+        use crate::testing::MockRoborio;
+
+        // A directory without devices, so every port is sent as empty
+        let gamepads = EvdevGamepads::start_in("/nonexistent");
+        let mock = MockRoborio::bind_any().await.unwrap();
+        let mut ds = mock.connected_ds().build_async().unwrap();
+        gamepads.install_async(&mut ds).await;
+
+        let descriptors = || {
+            mock.tcp_frames()
+                .into_iter()
+                .filter(|frame| frame.id == 0x02)
+                .count()
+        };
+        while descriptors() < MAX_PORTS {
+            tokio::time::delay_for(Duration::from_millis(10)).await;
+        }
+        assert!(gamepads.gamepads().joysticks().is_empty());
+        ds.shutdown(Duration::from_secs(1)).await.unwrap();
+    }
+}
@@ -0,0 +1,642 @@
+use crate::{JoystickDesc, JoystickType, JoystickValue};
+
+use std::collections::HashSet;
+
+/// The number of joystick ports the driver station sends to robot code
+pub const MAX_PORTS: usize = 6;
+
+/// A button of a gamepad, named by its position so that the same name works for every brand of controller
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum GamepadButton {
+    /// A on Xbox controllers, cross on PlayStation controllers
+    South,
+    /// B, or circle
+    East,
+    /// X, or square
+    West,
+    /// Y, or triangle
+    North,
+    LeftBumper,
+    RightBumper,
+    /// The left trigger pressed past its click point, which PlayStation controllers report as a button
+    LeftTrigger,
+    RightTrigger,
+    /// Back, or share
+    Select,
+    /// Start, or options
+    Start,
+    /// The Xbox or PS button
+    Mode,
+    LeftStick,
+    RightStick,
+    /// The touchpad of a PS4 controller
+    Touchpad,
+    DPadUp,
+    DPadDown,
+    DPadLeft,
+    DPadRight,
+}
+
+/// Every button that can be sent to robot code, the d-pad being sent as a POV instead
+const BUTTONS: [GamepadButton; 14] = [
+    GamepadButton::South,
+    GamepadButton::East,
+    GamepadButton::West,
+    GamepadButton::North,
+    GamepadButton::LeftBumper,
+    GamepadButton::RightBumper,
+    GamepadButton::LeftTrigger,
+    GamepadButton::RightTrigger,
+    GamepadButton::Select,
+    GamepadButton::Start,
+    GamepadButton::Mode,
+    GamepadButton::LeftStick,
+    GamepadButton::RightStick,
+    GamepadButton::Touchpad,
+];
+
+/// An axis of a gamepad
+///
+/// Sticks range from -1 to 1 with up and right positive, as reported by gamepad libraries such as gilrs. Triggers
+/// range from 0 to 1.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum GamepadAxis {
+    LeftStickX,
+    LeftStickY,
+    RightStickX,
+    RightStickY,
+    LeftTrigger,
+    RightTrigger,
+}
+
+pub(super) const AXES: [GamepadAxis; 6] = [
+    GamepadAxis::LeftStickX,
+    GamepadAxis::LeftStickY,
+    GamepadAxis::RightStickX,
+    GamepadAxis::RightStickY,
+    GamepadAxis::LeftTrigger,
+    GamepadAxis::RightTrigger,
+];
+
+/// How the inputs of a gamepad are numbered for robot code, following WPILib's `XboxController` and `PS4Controller`
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum GamepadLayout {
+    Xbox,
+    Ps4,
+}
+
+impl GamepadLayout {
+    /// Guesses the layout of a controller from its name, defaulting to Xbox
+    pub fn from_name(name: &str) -> GamepadLayout {
+        let name = name.to_lowercase();
+        let sony = ["ps4", "dualshock", "wireless controller", "sony"];
+        if sony.iter().any(|s| name.contains(s)) {
+            GamepadLayout::Ps4
+        } else {
+            GamepadLayout::Xbox
+        }
+    }
+
+    /// Returns the number robot code reads `axis` from
+    pub fn axis_id(self, axis: GamepadAxis) -> u8 {
+        use self::GamepadAxis::*;
+        use self::GamepadLayout::*;
+
+        match (self, axis) {
+            (_, LeftStickX) => 0,
+            (_, LeftStickY) => 1,
+            (Xbox, LeftTrigger) => 2,
+            (Xbox, RightTrigger) => 3,
+            (Xbox, RightStickX) => 4,
+            (Xbox, RightStickY) => 5,
+            (Ps4, RightStickX) => 2,
+            (Ps4, LeftTrigger) => 3,
+            (Ps4, RightTrigger) => 4,
+            (Ps4, RightStickY) => 5,
+        }
+    }
+
+    /// Returns the number robot code reads `button` from, counting from 1
+    ///
+    /// Returns None for buttons that aren't part of the layout. The d-pad is never a button, it is sent as a POV.
+    pub fn button_id(self, button: GamepadButton) -> Option<u8> {
+        use self::GamepadButton::*;
+        use self::GamepadLayout::*;
+
+        let id = match (self, button) {
+            (Xbox, South) => 1,
+            (Xbox, East) => 2,
+            (Xbox, West) => 3,
+            (Xbox, North) => 4,
+            (Xbox, LeftBumper) => 5,
+            (Xbox, RightBumper) => 6,
+            (Xbox, Select) => 7,
+            (Xbox, Start) => 8,
+            (Xbox, LeftStick) => 9,
+            (Xbox, RightStick) => 10,
+            (Ps4, West) => 1,
+            (Ps4, South) => 2,
+            (Ps4, East) => 3,
+            (Ps4, North) => 4,
+            (Ps4, LeftBumper) => 5,
+            (Ps4, RightBumper) => 6,
+            (Ps4, LeftTrigger) => 7,
+            (Ps4, RightTrigger) => 8,
+            (Ps4, Select) => 9,
+            (Ps4, Start) => 10,
+            (Ps4, LeftStick) => 11,
+            (Ps4, RightStick) => 12,
+            (Ps4, Mode) => 13,
+            (Ps4, Touchpad) => 14,
+            _ => return None,
+        };
+        Some(id)
+    }
+
+    fn button_count(self) -> u8 {
+        match self {
+            GamepadLayout::Xbox => 10,
+            GamepadLayout::Ps4 => 14,
+        }
+    }
+
+    /// Returns the descriptor of a gamepad with this layout named `name`, plugged in to `port`
+    pub fn descriptor(self, port: u8, name: &str) -> JoystickDesc {
+        let (is_xbox, joystick_type, axis_types) = match self {
+            GamepadLayout::Xbox => (true, JoystickType::XInputGamepad, vec![0, 1, 2, 2, 0, 1]),
+            GamepadLayout::Ps4 => (false, JoystickType::HidGamepad, vec![0, 1, 0, 2, 2, 1]),
+        };
+
+        JoystickDesc {
+            index: port,
+            is_xbox,
+            joystick_type,
+            name: name.to_string(),
+            axis_types,
+            button_count: self.button_count(),
+            pov_count: 1,
+        }
+    }
+}
+
+/// The state of the inputs of a gamepad
+#[derive(Debug, Clone)]
+pub struct Gamepad {
+    pub name: String,
+    pub layout: GamepadLayout,
+    /// Indexed by `GamepadAxis as usize`
+    axes: [f32; 6],
+    pressed: HashSet<GamepadButton>,
+}
+
+impl Gamepad {
+    /// Creates a gamepad with every stick centred and no buttons pressed
+    pub fn new(name: impl Into<String>, layout: GamepadLayout) -> Gamepad {
+        Gamepad {
+            name: name.into(),
+            layout,
+            axes: [0.0; 6],
+            pressed: HashSet::new(),
+        }
+    }
+
+    pub fn set_axis(&mut self, axis: GamepadAxis, value: f32) {
+        self.axes[axis as usize] = value.clamp(-1.0, 1.0);
+    }
+
+    pub fn axis(&self, axis: GamepadAxis) -> f32 {
+        self.axes[axis as usize]
+    }
+
+    pub fn set_button(&mut self, button: GamepadButton, pressed: bool) {
+        if pressed {
+            self.pressed.insert(button);
+        } else {
+            self.pressed.remove(&button);
+        }
+    }
+
+    pub fn pressed(&self, button: GamepadButton) -> bool {
+        self.pressed.contains(&button)
+    }
+
+    /// Returns the direction of the d-pad in degrees clockwise from up, or -1 if it isn't pressed
+    pub fn pov(&self) -> i16 {
+        let vertical =
+            self.pressed(GamepadButton::DPadDown) as i8 - self.pressed(GamepadButton::DPadUp) as i8;
+        let horizontal = self.pressed(GamepadButton::DPadRight) as i8
+            - self.pressed(GamepadButton::DPadLeft) as i8;
+
+        match (horizontal, vertical) {
+            (0, -1) => 0,
+            (1, -1) => 45,
+            (1, 0) => 90,
+            (1, 1) => 135,
+            (0, 1) => 180,
+            (-1, 1) => 225,
+            (-1, 0) => 270,
+            (-1, -1) => 315,
+            _ => -1,
+        }
+    }
+
+    /// Returns the values of every axis, button, and the d-pad, numbered for robot code
+    ///
+    /// The Y axes of the sticks are inverted, since robot code expects pushing a stick forward to read as negative.
+    pub fn values(&self) -> Vec<JoystickValue> {
+        let mut values = Vec::new();
+        for &axis in &AXES {
+            let value = match axis {
+                GamepadAxis::LeftStickY | GamepadAxis::RightStickY => -self.axis(axis),
+                _ => self.axis(axis),
+            };
+            values.push(JoystickValue::Axis {
+                id: self.layout.axis_id(axis),
+                value,
+            });
+        }
+
+        for &button in &BUTTONS {
+            if let Some(id) = self.layout.button_id(button) {
+                values.push(JoystickValue::Button {
+                    id,
+                    pressed: self.pressed(button),
+                });
+            }
+        }
+
+        values.push(JoystickValue::POV {
+            id: 0,
+            angle: self.pov(),
+        });
+        values
+    }
+}
+
+/// The gamepads plugged in to the driver station, each assigned to a joystick port
+///
+/// A newly connected gamepad takes the lowest free port. Unplugging one frees its port without moving any other, so
+/// robot code keeps reading the same controller from the same port. Gamepads are identified by a key chosen by the
+/// source of input, such as a gilrs `GamepadId`.
+///
+/// Sources of gamepad input translate their events into calls to [`connect`](#method.connect),
+/// [`disconnect`](#method.disconnect), and [`get_mut`](#method.get_mut), usually on a shared `Arc<Mutex<Gamepads>>`
+/// whose [`joysticks`](#method.joysticks) are returned by the joystick supplier. After a gamepad is connected or
+/// disconnected, the new [`descriptors`](#method.descriptors) should be given to
+/// [`DriverStation::set_joystick_descriptors`](../struct.DriverStation.html#method.set_joystick_descriptors).
+#[derive(Debug, Clone)]
+pub struct Gamepads<K> {
+    ports: Vec<Port<K>>,
+    /// Whether unplugged gamepads keep their port until they come back
+    locked: bool,
+}
+
+/// A joystick port of the driver station
+#[derive(Debug, Clone)]
+enum Port<K> {
+    Free,
+    /// Kept for a gamepad that was unplugged while the ports were locked
+    Reserved(K),
+    Connected(K, Gamepad),
+}
+
+impl<K: PartialEq> Gamepads<K> {
+    pub fn new() -> Gamepads<K> {
+        Gamepads {
+            ports: Vec::new(),
+            locked: false,
+        }
+    }
+
+    /// Locks or unlocks the ports, like the lock button of the NI driver station's USB devices tab
+    ///
+    /// While the ports are locked, a gamepad that is unplugged keeps its port until it is plugged back in, even if
+    /// other gamepads are connected in the meantime. Unlocking frees the ports of gamepads that are still unplugged.
+    pub fn set_locked(&mut self, locked: bool) {
+        self.locked = locked;
+        if !locked {
+            for port in &mut self.ports {
+                if let Port::Reserved(_) = port {
+                    *port = Port::Free;
+                }
+            }
+            self.trim();
+        }
+    }
+
+    pub fn locked(&self) -> bool {
+        self.locked
+    }
+
+    /// Assigns a newly connected gamepad to the lowest free port, returning the port
+    ///
+    /// Returns None if every port is taken. A gamepad connected again under the same key keeps its port.
+    pub fn connect(&mut self, key: K, gamepad: Gamepad) -> Option<usize> {
+        let known = self.ports.iter().position(|port| match port {
+            Port::Reserved(k) | Port::Connected(k, _) => *k == key,
+            Port::Free => false,
+        });
+        let port = match known {
+            Some(port) => port,
+            None => match self
+                .ports
+                .iter()
+                .position(|port| matches!(port, Port::Free))
+            {
+                Some(port) => port,
+                None if self.ports.len() < MAX_PORTS => {
+                    self.ports.push(Port::Free);
+                    self.ports.len() - 1
+                }
+                None => return None,
+            },
+        };
+
+        self.ports[port] = Port::Connected(key, gamepad);
+        Some(port)
+    }
+
+    /// Marks a gamepad as unplugged, returning the port it had
+    ///
+    /// The port is freed, unless the ports are locked.
+    pub fn disconnect(&mut self, key: &K) -> Option<usize> {
+        let port = self.port(key)?;
+        let removed = std::mem::replace(&mut self.ports[port], Port::Free);
+        if let Port::Connected(key, _) = removed {
+            if self.locked {
+                self.ports[port] = Port::Reserved(key);
+            }
+        }
+        self.trim();
+        Some(port)
+    }
+
+    /// Returns the port of a connected gamepad
+    pub fn port(&self, key: &K) -> Option<usize> {
+        self.ports
+            .iter()
+            .position(|port| matches!(port, Port::Connected(k, _) if k == key))
+    }
+
+    /// Returns the gamepad identified by `key`, so that its inputs can be updated
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut Gamepad> {
+        self.ports.iter_mut().find_map(|port| match port {
+            Port::Connected(k, gamepad) if k == key => Some(gamepad),
+            _ => None,
+        })
+    }
+
+    /// Returns the gamepad on `port`
+    pub fn on_port(&self, port: usize) -> Option<&Gamepad> {
+        match self.ports.get(port) {
+            Some(Port::Connected(_, gamepad)) => Some(gamepad),
+            _ => None,
+        }
+    }
+
+    /// Drops the free ports after the last one in use
+    fn trim(&mut self) {
+        while let Some(Port::Free) = self.ports.last() {
+            self.ports.pop();
+        }
+    }
+
+    /// Returns the values of every port up to the last one in use, for a joystick supplier
+    ///
+    /// Free and reserved ports before the last one in use have no values, which robot code reads as centred sticks.
+    pub fn joysticks(&self) -> Vec<Vec<JoystickValue>> {
+        self.ports
+            .iter()
+            .map(|port| match port {
+                Port::Connected(_, gamepad) => gamepad.values(),
+                _ => Vec::new(),
+            })
+            .collect()
+    }
+
+    /// Returns a descriptor for each of the driver station's ports, telling robot code which ones are free
+    pub fn descriptors(&self) -> Vec<JoystickDesc> {
+        (0..MAX_PORTS)
+            .map(|port| match self.on_port(port) {
+                Some(gamepad) => gamepad.layout.descriptor(port as u8, &gamepad.name),
+                None => JoystickDesc {
+                    index: port as u8,
+                    is_xbox: false,
+                    joystick_type: JoystickType::Unknown,
+                    name: String::new(),
+                    axis_types: Vec::new(),
+                    button_count: 0,
+                    pov_count: 0,
+                },
+            })
+            .collect()
+    }
+}
+
+impl<K: PartialEq> Default for Gamepads<K> {
+    fn default() -> Self {
+        Gamepads::new()
+    }
+}
+
+#[cfg(any(feature = "gilrs", all(feature = "evdev", target_os = "linux")))]
+pub(super) use self::shared::SharedGamepads;
+
+/// State shared by the gamepad backends
+#[cfg(any(feature = "gilrs", all(feature = "evdev", target_os = "linux")))]
+mod shared {
+    use super::Gamepads;
+    use crate::ds::state::DsState;
+    use crate::AsyncDriverStation;
+
+    use futures::executor::block_on;
+    use std::sync::{Arc, Mutex, MutexGuard};
+
+    /// The gamepads of a backend, shared between the threads that read them and the joystick supplier it installs
+    pub(in crate::input) struct SharedGamepads<K> {
+        gamepads: Mutex<Gamepads<K>>,
+        /// The driver station that is told about gamepads being plugged in and unplugged
+        ds: Mutex<Option<Arc<DsState>>>,
+    }
+
+    impl<K: PartialEq + Send + 'static> SharedGamepads<K> {
+        /// Creates the shared state of a backend, without gamepads and with the ports locked
+        pub fn new() -> SharedGamepads<K> {
+            let mut gamepads = Gamepads::new();
+            gamepads.set_locked(true);
+            SharedGamepads {
+                gamepads: Mutex::new(gamepads),
+                ds: Mutex::new(None),
+            }
+        }
+
+        pub fn gamepads(&self) -> MutexGuard<'_, Gamepads<K>> {
+            self.gamepads.lock().unwrap()
+        }
+
+        /// Sends the descriptors of the gamepads to the driver station, if one has been installed
+        ///
+        /// This blocks on the driver station's state, so it's only called from the backend's own threads.
+        pub fn update_descriptors(&self) {
+            let descriptors = self.gamepads().descriptors();
+            if let Some(ref state) = *self.ds.lock().unwrap() {
+                block_on(state.tcp().lock()).set_joystick_descriptors(descriptors);
+            }
+        }
+
+        /// Replaces the joystick supplier of `ds` with one returning the values of the gamepads, and sends their
+        /// descriptors
+        ///
+        /// The supplier keeps `shared` alive, so the backend's threads run for as long as it is installed.
+        pub async fn install(shared: Arc<Self>, ds: &mut AsyncDriverStation) {
+            let supplier = shared.clone();
+            ds.set_joystick_supplier(move || supplier.gamepads().joysticks())
+                .await;
+            let descriptors = {
+                let gamepads = shared.gamepads();
+                *shared.ds.lock().unwrap() = Some(ds.state.clone());
+                gamepads.descriptors()
+            };
+            ds.set_joystick_descriptors(descriptors).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn maps_layouts() {
+        let mut xbox = Gamepad::new("Xbox Wireless Controller", GamepadLayout::Xbox);
+        xbox.set_axis(GamepadAxis::LeftStickY, 0.5);
+        xbox.set_axis(GamepadAxis::RightTrigger, 2.0);
+        xbox.set_button(GamepadButton::Start, true);
+        xbox.set_button(GamepadButton::DPadUp, true);
+        xbox.set_button(GamepadButton::DPadLeft, true);
+
+        let values = xbox.values();
+        assert!(values.contains(&JoystickValue::Axis { id: 1, value: -0.5 }));
+        assert!(values.contains(&JoystickValue::Axis { id: 3, value: 1.0 }));
+        assert!(values.contains(&JoystickValue::Button {
+            id: 8,
+            pressed: true
+        }));
+        assert!(values.contains(&JoystickValue::POV { id: 0, angle: 315 }));
+        assert_eq!(
+            values
+                .iter()
+                .filter(|v| matches!(v, JoystickValue::Button { .. }))
+                .count(),
+            10
+        );
+
+        assert_eq!(
+            GamepadLayout::from_name("Sony Interactive Entertainment Wireless Controller"),
+            GamepadLayout::Ps4
+        );
+        let mut ps4 = Gamepad::new("PS4", GamepadLayout::Ps4);
+        ps4.set_axis(GamepadAxis::RightStickX, -1.0);
+        ps4.set_button(GamepadButton::South, true);
+        let values = ps4.values();
+        assert!(values.contains(&JoystickValue::Axis { id: 2, value: -1.0 }));
+        assert!(values.contains(&JoystickValue::Button {
+            id: 2,
+            pressed: true
+        }));
+        assert!(values.contains(&JoystickValue::POV { id: 0, angle: -1 }));
+    }
+
+    #[test]
+    fn keeps_ports_across_hot_plug() {
+        let mut gamepads = Gamepads::new();
+        let pad = |name| Gamepad::new(name, GamepadLayout::Xbox);
+        assert_eq!(gamepads.connect(1, pad("first")), Some(0));
+        assert_eq!(gamepads.connect(2, pad("second")), Some(1));
+
+        // Unplugging the first gamepad leaves the second on port 1
+        assert_eq!(gamepads.disconnect(&1), Some(0));
+        assert_eq!(gamepads.port(&2), Some(1));
+        let joysticks = gamepads.joysticks();
+        assert_eq!(joysticks.len(), 2);
+        assert!(joysticks[0].is_empty() && !joysticks[1].is_empty());
+
+        let descriptors = gamepads.descriptors();
+        assert_eq!(descriptors.len(), MAX_PORTS);
+        assert_eq!(descriptors[0].joystick_type, JoystickType::Unknown);
+        assert_eq!(descriptors[1].name, "second");
+        assert!(descriptors[1].is_xbox);
+
+        gamepads
+            .get_mut(&2)
+            .unwrap()
+            .set_button(GamepadButton::South, true);
+        assert!(gamepads.on_port(1).unwrap().pressed(GamepadButton::South));
+
+        assert_eq!(gamepads.connect(3, pad("third")), Some(0));
+        for key in 4..8 {
+            gamepads.connect(key, pad("more"));
+        }
+        assert_eq!(gamepads.connect(8, pad("too many")), None);
+
+        gamepads.disconnect(&2);
+        gamepads.disconnect(&7);
+        assert_eq!(gamepads.joysticks().len(), 5);
+    }
+
+    #[test]
+    fn locked_ports_wait_for_their_gamepad() {
+        let mut gamepads = Gamepads::new();
+        let pad = |name| Gamepad::new(name, GamepadLayout::Xbox);
+        gamepads.set_locked(true);
+        gamepads.connect("left", pad("left"));
+        gamepads.connect("right", pad("right"));
+
+        // A student unplugs the left controller and plugs in a third one before plugging it back in
+        gamepads.disconnect(&"left");
+        assert_eq!(gamepads.port(&"left"), None);
+        assert_eq!(gamepads.connect("spare", pad("spare")), Some(2));
+        assert_eq!(gamepads.connect("left", pad("left")), Some(0));
+        assert_eq!(gamepads.port(&"right"), Some(1));
+
+        // The last port stays reserved as well, and unlocking frees it
+        gamepads.disconnect(&"spare");
+        assert_eq!(gamepads.joysticks().len(), 3);
+        gamepads.set_locked(false);
+        assert_eq!(gamepads.joysticks().len(), 2);
+        gamepads.disconnect(&"left");
+        assert_eq!(gamepads.connect("spare", pad("spare")), Some(0));
+    }
+
+    #[tokio::test]
+    async fn sends_descriptors_when_connected() {
+        use crate::testing::{MockRoborio, ReceivedTcp};
+        use std::time::Duration;
+
+        // TCP only connects once the robot answers, so it comes up after the descriptors were set
+        let mock = MockRoborio::bind_any().await.unwrap();
+        mock.update_response(|response| response.respond = false);
+        let mut ds = mock.connected_ds().build_async().unwrap();
+        let mut gamepads = Gamepads::new();
+        gamepads.connect(0, Gamepad::new("Xbox", GamepadLayout::Xbox));
+        ds.set_joystick_descriptors(gamepads.descriptors()).await;
+        mock.update_response(|response| response.respond = true);
+        let expected = ReceivedTcp {
+            id: 0x02,
+            data: vec![
+                0, 1, 1, 4, b'X', b'b', b'o', b'x', 6, 0, 1, 2, 2, 0, 1, 10, 1,
+            ],
+        };
+        let descriptors = || {
+            mock.tcp_frames()
+                .into_iter()
+                .filter(|frame| frame.id == 0x02)
+                .collect::<Vec<_>>()
+        };
+        while descriptors().len() < MAX_PORTS {
+            tokio::time::delay_for(Duration::from_millis(10)).await;
+        }
+        assert_eq!(descriptors()[0], expected);
+        ds.shutdown(Duration::from_secs(1)).await.unwrap();
+    }
+}
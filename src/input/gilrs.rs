@@ -0,0 +1,259 @@
+use super::gamepad::SharedGamepads;
+use super::{Gamepad, GamepadAxis, GamepadButton, GamepadLayout, Gamepads};
+use crate::{AsyncDriverStation, DriverStation, Result};
+
+use failure::format_err;
+use futures::executor::block_on;
+use gilrs::{Axis, Button, Event, EventType, GamepadId, Gilrs};
+use log::*;
+use std::sync::mpsc;
+use std::sync::{Arc, MutexGuard, Weak};
+use std::thread;
+use std::time::Duration;
+
+/// How long the event pump waits for an event before checking whether it should stop
+const POLL_PERIOD: Duration = Duration::from_millis(100);
+
+/// Applies a change in the value of a gilrs button to a gamepad
+///
+/// gilrs calls the bumpers `LeftTrigger` and `RightTrigger`, and the analog triggers `LeftTrigger2` and
+/// `RightTrigger2`, whose value is also that of the trigger axes.
+fn apply_button(gamepad: &mut Gamepad, button: Button, value: f32) {
+    let mapped = match button {
+        Button::South => GamepadButton::South,
+        Button::East => GamepadButton::East,
+        Button::North => GamepadButton::North,
+        Button::West => GamepadButton::West,
+        Button::LeftTrigger => GamepadButton::LeftBumper,
+        Button::RightTrigger => GamepadButton::RightBumper,
+        Button::LeftTrigger2 => {
+            gamepad.set_axis(GamepadAxis::LeftTrigger, value);
+            GamepadButton::LeftTrigger
+        }
+        Button::RightTrigger2 => {
+            gamepad.set_axis(GamepadAxis::RightTrigger, value);
+            GamepadButton::RightTrigger
+        }
+        Button::Select => GamepadButton::Select,
+        Button::Start => GamepadButton::Start,
+        Button::Mode => GamepadButton::Mode,
+        Button::LeftThumb => GamepadButton::LeftStick,
+        Button::RightThumb => GamepadButton::RightStick,
+        Button::DPadUp => GamepadButton::DPadUp,
+        Button::DPadDown => GamepadButton::DPadDown,
+        Button::DPadLeft => GamepadButton::DPadLeft,
+        Button::DPadRight => GamepadButton::DPadRight,
+        _ => return,
+    };
+    gamepad.set_button(mapped, value > 0.5);
+}
+
+/// Applies a change in the value of a gilrs axis to a gamepad
+///
+/// gilrs already reports stick Y axes with up positive, like `GamepadAxis`. Gamepads without a mapping report their
+/// triggers as the Z axes, from -1 released to 1 pressed, and their d-pad as a pair of axes.
+fn apply_axis(gamepad: &mut Gamepad, axis: Axis, value: f32) {
+    match axis {
+        Axis::LeftStickX => gamepad.set_axis(GamepadAxis::LeftStickX, value),
+        Axis::LeftStickY => gamepad.set_axis(GamepadAxis::LeftStickY, value),
+        Axis::RightStickX => gamepad.set_axis(GamepadAxis::RightStickX, value),
+        Axis::RightStickY => gamepad.set_axis(GamepadAxis::RightStickY, value),
+        Axis::LeftZ => gamepad.set_axis(GamepadAxis::LeftTrigger, (value + 1.0) / 2.0),
+        Axis::RightZ => gamepad.set_axis(GamepadAxis::RightTrigger, (value + 1.0) / 2.0),
+        Axis::DPadX => {
+            gamepad.set_button(GamepadButton::DPadLeft, value < -0.5);
+            gamepad.set_button(GamepadButton::DPadRight, value > 0.5);
+        }
+        Axis::DPadY => {
+            gamepad.set_button(GamepadButton::DPadUp, value > 0.5);
+            gamepad.set_button(GamepadButton::DPadDown, value < -0.5);
+        }
+        _ => {}
+    }
+}
+
+/// The gamepads shared between the event pump and the joystick supplier
+type Shared = SharedGamepads<usize>;
+
+/// Assigns a port to a gamepad that gilrs has found
+fn connect(shared: &Shared, gilrs: &Gilrs, id: GamepadId) {
+    let name = gilrs.gamepad(id).name().to_string();
+    let port = shared.gamepads().connect(
+        usize::from(id),
+        Gamepad::new(name.clone(), GamepadLayout::from_name(&name)),
+    );
+    match port {
+        Some(port) => {
+            info!("Gamepad {} connected on port {}", name, port);
+            shared.update_descriptors();
+        }
+        None => warn!("No free joystick port for gamepad {}", name),
+    }
+}
+
+fn handle(shared: &Shared, gilrs: &Gilrs, Event { id, event, .. }: Event) {
+    match event {
+        EventType::Connected => connect(shared, gilrs, id),
+        EventType::Disconnected => {
+            info!("Gamepad {} disconnected", gilrs.gamepad(id).name());
+            shared.gamepads().disconnect(&usize::from(id));
+            shared.update_descriptors();
+        }
+        _ => {
+            let mut gamepads = shared.gamepads();
+            let gamepad = match gamepads.get_mut(&usize::from(id)) {
+                Some(gamepad) => gamepad,
+                None => return,
+            };
+            match event {
+                EventType::ButtonPressed(button, _) => apply_button(gamepad, button, 1.0),
+                EventType::ButtonReleased(button, _) => apply_button(gamepad, button, 0.0),
+                EventType::ButtonChanged(button, value, _) => apply_button(gamepad, button, value),
+                EventType::AxisChanged(axis, value, _) => apply_axis(gamepad, axis, value),
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Gamepads read with [gilrs](https://docs.rs/gilrs), on any platform it supports
+///
+/// Gamepads that are connected when this starts, and any plugged in later, are assigned ports by
+/// [`Gamepads`](struct.Gamepads.html) with the ports locked: gilrs gives a gamepad the same id when it is plugged back
+/// in, so an unplugged gamepad keeps its port until then. Events are pumped on a thread of their own, which stops once
+/// this and the joystick supplier installed by [`install`](#method.install) have been dropped.
+///
+/// On platforms that gilrs doesn't support, its dummy backend is used and no gamepads are ever connected.
+pub struct GilrsGamepads {
+    shared: Arc<Shared>,
+}
+
+impl GilrsGamepads {
+    /// Starts pumping gilrs events, returning an error if gilrs failed to initialize
+    pub fn start() -> Result<GilrsGamepads> {
+        let shared = Arc::new(Shared::new());
+
+        // gilrs isn't Send on every platform, so it is created on the thread that uses it
+        let (init_tx, init_rx) = mpsc::channel();
+        let weak = Arc::downgrade(&shared);
+        thread::spawn(move || {
+            let gilrs = match Gilrs::new() {
+                Ok(gilrs) => gilrs,
+                Err(gilrs::Error::NotImplemented(dummy)) => {
+                    warn!("gilrs doesn't support this platform, no gamepads will be found");
+                    dummy
+                }
+                Err(e) => {
+                    let _ = init_tx.send(Err(format_err!("Failed to start gilrs: {}", e)));
+                    return;
+                }
+            };
+            if let Some(shared) = weak.upgrade() {
+                for (id, _) in gilrs.gamepads() {
+                    connect(&shared, &gilrs, id);
+                }
+            }
+            let _ = init_tx.send(Ok(()));
+            pump(gilrs, weak);
+        });
+
+        init_rx
+            .recv()
+            .map_err(|_| format_err!("gilrs thread stopped while starting"))??;
+        Ok(GilrsGamepads { shared })
+    }
+
+    /// Sends the values of the gamepads with every control packet of `ds`, replacing its joystick supplier
+    ///
+    /// Descriptors are sent to robot code now, and again whenever a gamepad is plugged in or unplugged.
+    pub fn install(&self, ds: &mut DriverStation) {
+        block_on(self.install_async(ds.as_async()));
+    }
+
+    /// Sends the values of the gamepads with every control packet of `ds`, replacing its joystick supplier
+    pub async fn install_async(&self, ds: &mut AsyncDriverStation) {
+        Shared::install(self.shared.clone(), ds).await;
+    }
+
+    /// Returns the gamepads, to show their state or to unlock their ports
+    pub fn gamepads(&self) -> MutexGuard<'_, Gamepads<usize>> {
+        self.shared.gamepads()
+    }
+}
+
+/// Applies gilrs events to the gamepads until they are dropped
+fn pump(mut gilrs: Gilrs, shared: Weak<Shared>) {
+    loop {
+        let event = gilrs.next_event_blocking(Some(POLL_PERIOD));
+        let shared = match shared.upgrade() {
+            Some(shared) => shared,
+            None => return,
+        };
+        if let Some(event) = event {
+            handle(&shared, &gilrs, event);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::input::MAX_PORTS;
+    use crate::testing::MockRoborio;
+
+    #[test]
+    fn applies_events() {
+        let mut gamepad = Gamepad::new("Xbox", GamepadLayout::Xbox);
+
+        apply_axis(&mut gamepad, Axis::LeftStickY, 1.0);
+        apply_axis(&mut gamepad, Axis::RightZ, 1.0);
+        apply_axis(&mut gamepad, Axis::DPadY, 1.0);
+        apply_button(&mut gamepad, Button::West, 1.0);
+        apply_button(&mut gamepad, Button::LeftTrigger, 1.0);
+        apply_button(&mut gamepad, Button::LeftTrigger2, 0.25);
+        apply_button(&mut gamepad, Button::Start, 1.0);
+        apply_button(&mut gamepad, Button::Start, 0.0);
+
+        assert_eq!(gamepad.axis(GamepadAxis::LeftStickY), 1.0);
+        assert_eq!(gamepad.axis(GamepadAxis::RightTrigger), 1.0);
+        assert_eq!(gamepad.axis(GamepadAxis::LeftTrigger), 0.25);
+        assert_eq!(gamepad.pov(), 0);
+        assert!(gamepad.pressed(GamepadButton::West));
+        assert!(gamepad.pressed(GamepadButton::LeftBumper));
+        assert!(!gamepad.pressed(GamepadButton::LeftTrigger));
+        assert!(!gamepad.pressed(GamepadButton::Start));
+
+        apply_axis(&mut gamepad, Axis::DPadY, 0.0);
+        apply_axis(&mut gamepad, Axis::DPadX, 1.0);
+        assert_eq!(gamepad.pov(), 90);
+    }
+
+    #[tokio::test]
+    async fn installs_as_supplier() {
+        use crate::testing::ReceivedTcp;
+
+        // Without gamepads attached, gilrs finds none and every port is sent as empty
+        let gamepads = GilrsGamepads::start().unwrap();
+        let mock = MockRoborio::bind_any().await.unwrap();
+        let mut ds = mock.connected_ds().build_async().unwrap();
+        gamepads.install_async(&mut ds).await;
+
+        let descriptors = || {
+            mock.tcp_frames()
+                .into_iter()
+                .filter(|frame| frame.id == 0x02)
+                .collect::<Vec<_>>()
+        };
+        while descriptors().len() < MAX_PORTS {
+            tokio::time::delay_for(Duration::from_millis(10)).await;
+        }
+        assert_eq!(
+            descriptors()[0],
+            ReceivedTcp {
+                id: 0x02,
+                data: vec![0, 0, 0xff, 0, 0, 0, 0],
+            }
+        );
+        ds.shutdown(Duration::from_secs(1)).await.unwrap();
+    }
+}
@@ -0,0 +1,125 @@
+use std::io::Read;
+
+/// A key on a keyboard
+///
+/// Printable keys, including the space bar, are represented by the character they type.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Key {
+    Char(char),
+    Enter,
+    Escape,
+    Up,
+    Down,
+    Left,
+    Right,
+    PageUp,
+    PageDown,
+    /// Ctrl+C, which a terminal in raw mode reports as a key rather than a signal
+    Interrupt,
+}
+
+/// A key being pressed or released
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct KeyEvent {
+    pub key: Key,
+    /// Whether the key went down. Terminals only report key presses, so sources reading from one never release keys
+    pub pressed: bool,
+}
+
+impl KeyEvent {
+    pub fn press(key: Key) -> KeyEvent {
+        KeyEvent { key, pressed: true }
+    }
+
+    pub fn release(key: Key) -> KeyEvent {
+        KeyEvent {
+            key,
+            pressed: false,
+        }
+    }
+
+    /// Parses the bytes read from a terminal into key presses, ignoring keys and escape sequences that aren't
+    /// represented by [`Key`](enum.Key.html)
+    pub fn parse_terminal(bytes: &[u8]) -> Vec<KeyEvent> {
+        let text = String::from_utf8_lossy(bytes);
+        let mut chars = text.chars().peekable();
+        let mut events = Vec::new();
+
+        while let Some(c) = chars.next() {
+            let key = match c {
+                '\r' | '\n' => Key::Enter,
+                '\x03' => Key::Interrupt,
+                '\x1b' if chars.peek() == Some(&'[') => {
+                    chars.next();
+                    // CSI sequences end with a character between '@' and '~'
+                    let mut sequence = String::new();
+                    for c in chars.by_ref() {
+                        sequence.push(c);
+                        if ('@'..='~').contains(&c) {
+                            break;
+                        }
+                    }
+                    match sequence.as_str() {
+                        "A" => Key::Up,
+                        "B" => Key::Down,
+                        "C" => Key::Right,
+                        "D" => Key::Left,
+                        "5~" => Key::PageUp,
+                        "6~" => Key::PageDown,
+                        _ => continue,
+                    }
+                }
+                '\x1b' => Key::Escape,
+                c if c.is_control() => continue,
+                c => Key::Char(c),
+            };
+            events.push(KeyEvent::press(key));
+        }
+
+        events
+    }
+}
+
+/// Returns an iterator over the keys pressed in a terminal, read from `reader` until it is closed or fails
+///
+/// `reader` is usually stdin. Unless the terminal has been switched to raw mode, it only sends keys once Enter is
+/// pressed, which delays every key but Enter itself.
+pub fn read_terminal_keys(mut reader: impl Read) -> impl Iterator<Item = KeyEvent> {
+    let mut buf = [0; 64];
+    std::iter::from_fn(move || match reader.read(&mut buf) {
+        Ok(0) | Err(_) => None,
+        Ok(n) => Some(KeyEvent::parse_terminal(&buf[..n])),
+    })
+    .flatten()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parses_terminal_keys() {
+        let keys = read_terminal_keys(&b"[]\\ \r\x1b[A\x1b[6~\x1b[1;5C\x1b[D\x03q\x1b"[..])
+            .map(|event| {
+                assert!(event.pressed);
+                event.key
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            keys,
+            vec![
+                Key::Char('['),
+                Key::Char(']'),
+                Key::Char('\\'),
+                Key::Char(' '),
+                Key::Enter,
+                Key::Up,
+                Key::PageDown,
+                Key::Left,
+                Key::Interrupt,
+                Key::Char('q'),
+                Key::Escape,
+            ]
+        );
+    }
+}
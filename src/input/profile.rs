@@ -0,0 +1,474 @@
+use crate::proto::udp::outbound::types::tags::MAX_BUTTONS;
+use crate::{JoystickValue, Result};
+
+use failure::{bail, format_err, Error};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+#[cfg(feature = "serde")]
+use std::convert::TryFrom;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+/// Presses a button while an axis is pushed past a threshold, such as for using a trigger as a button
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "RawAxisButton"))]
+pub struct AxisButton {
+    /// The button that is pressed, numbered from 1
+    pub id: u8,
+    /// The processed value the axis has to reach. Negative thresholds are reached by values at or below them
+    pub threshold: f32,
+}
+
+impl AxisButton {
+    fn pressed(&self, value: f32) -> bool {
+        if self.threshold < 0.0 {
+            value <= self.threshold
+        } else {
+            value >= self.threshold
+        }
+    }
+}
+
+/// How the value of an axis is processed before it is sent to the robot
+///
+/// The deadband is applied first, then the expo curve, the scale, and finally the inversion.
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct AxisProfile {
+    /// Values closer to zero than this read as zero. The rest are stretched so that the axis still reaches 1
+    pub deadband: f32,
+    /// Blends a linear response at 0 with a cubic one at 1, giving finer control around the centre
+    pub expo: f32,
+    /// Multiplies the value, such as to limit the top speed of a new driver
+    pub scale: f32,
+    pub inverted: bool,
+    pub button: Option<AxisButton>,
+}
+
+impl AxisProfile {
+    /// Processes the value of an axis
+    pub fn apply(&self, value: f32) -> f32 {
+        let magnitude = value.abs();
+        let value = if magnitude <= self.deadband || self.deadband >= 1.0 {
+            0.0
+        } else {
+            value.signum() * (magnitude - self.deadband) / (1.0 - self.deadband)
+        };
+        let value = (1.0 - self.expo) * value + self.expo * value.powi(3);
+        let value = value * self.scale;
+        let value = if self.inverted { -value } else { value };
+        value.clamp(-1.0, 1.0)
+    }
+}
+
+impl Default for AxisProfile {
+    /// Returns a profile that sends the value of the axis unchanged
+    fn default() -> Self {
+        AxisProfile {
+            deadband: 0.0,
+            expo: 0.0,
+            scale: 1.0,
+            inverted: false,
+            button: None,
+        }
+    }
+}
+
+/// A driver's preferences for the joystick on one port, applied to its values before they are sent to the robot
+///
+/// Axes and buttons are identified by the numbers robot code reads them with. Axes without a profile and POVs are sent
+/// unchanged. Buttons can be moved to other numbers; a button pressed by several sources, such as a remapped button
+/// and an [`AxisButton`](struct.AxisButton.html), is pressed while any of them are.
+///
+/// Profiles are saved as text, with a line for each axis and remapped button:
+///
+/// ```text
+/// # Lines starting with # are ignored
+/// axis 1 deadband=0.1 expo=0.4 scale=0.8 invert
+/// axis 3 button=11@0.5
+/// button 1 2
+/// ```
+///
+/// This processes axis 1, presses button 11 while axis 3 is at least 0.5, and sends button 1 as button 2. Profiles
+/// are given to [`DriverStation::set_input_profile`](../struct.DriverStation.html#method.set_input_profile).
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "RawInputProfile"))]
+pub struct InputProfile {
+    axes: BTreeMap<u8, AxisProfile>,
+    buttons: BTreeMap<u8, u8>,
+}
+
+/// An `AxisButton` as it is deserialized, before its button number is checked
+#[cfg(feature = "serde")]
+#[derive(Deserialize)]
+struct RawAxisButton {
+    id: u8,
+    threshold: f32,
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<RawAxisButton> for AxisButton {
+    type Error = Error;
+
+    fn try_from(raw: RawAxisButton) -> Result<AxisButton> {
+        Ok(AxisButton {
+            id: check_button(raw.id)?,
+            threshold: raw.threshold,
+        })
+    }
+}
+
+/// An `InputProfile` as it is deserialized, before its button remaps are checked
+#[cfg(feature = "serde")]
+#[derive(Deserialize)]
+struct RawInputProfile {
+    axes: BTreeMap<u8, AxisProfile>,
+    buttons: BTreeMap<u8, u8>,
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<RawInputProfile> for InputProfile {
+    type Error = Error;
+
+    fn try_from(raw: RawInputProfile) -> Result<InputProfile> {
+        for (from, to) in &raw.buttons {
+            check_button(*from)?;
+            check_button(*to)?;
+        }
+        Ok(InputProfile {
+            axes: raw.axes,
+            buttons: raw.buttons,
+        })
+    }
+}
+
+impl InputProfile {
+    /// Creates a profile that sends every value unchanged
+    pub fn new() -> InputProfile {
+        InputProfile::default()
+    }
+
+    /// Processes axis `id` with `profile`, replacing any profile it had before
+    pub fn axis(mut self, id: u8, profile: AxisProfile) -> Self {
+        self.axes.insert(id, profile);
+        self
+    }
+
+    /// Sends button `from` as button `to`
+    pub fn remap_button(mut self, from: u8, to: u8) -> Self {
+        self.buttons.insert(from, to);
+        self
+    }
+
+    /// Returns the profile of axis `id`, if it has one
+    pub fn axis_profile(&self, id: u8) -> Option<&AxisProfile> {
+        self.axes.get(&id)
+    }
+
+    /// Returns the number that button `id` is sent as
+    pub fn button_id(&self, id: u8) -> u8 {
+        self.buttons.get(&id).copied().unwrap_or(id)
+    }
+
+    /// Processes the values of a joystick
+    pub fn apply(&self, values: &[JoystickValue]) -> Vec<JoystickValue> {
+        let mut processed = Vec::with_capacity(values.len());
+        let mut buttons = BTreeMap::new();
+
+        for value in values {
+            match *value {
+                JoystickValue::Axis { id, value } => match self.axes.get(&id) {
+                    Some(profile) => {
+                        let value = profile.apply(value);
+                        processed.push(JoystickValue::Axis { id, value });
+                        if let Some(button) = profile.button {
+                            *buttons.entry(button.id).or_insert(false) |= button.pressed(value);
+                        }
+                    }
+                    None => processed.push(JoystickValue::Axis { id, value }),
+                },
+                JoystickValue::Button { id, pressed } => {
+                    *buttons.entry(self.button_id(id)).or_insert(false) |= pressed;
+                }
+                JoystickValue::POV { .. } => processed.push(*value),
+            }
+        }
+
+        processed.extend(
+            buttons
+                .into_iter()
+                .map(|(id, pressed)| JoystickValue::Button { id, pressed }),
+        );
+        processed
+    }
+
+    /// Reads a profile saved with [`save`](#method.save)
+    pub fn load(path: impl AsRef<Path>) -> Result<InputProfile> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)
+            .map_err(|e| format_err!("Failed to read profile {}: {}", path.display(), e))?;
+        text.parse()
+    }
+
+    /// Writes the profile to `path` as text
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        fs::write(path, self.to_string())
+            .map_err(|e| format_err!("Failed to write profile {}: {}", path.display(), e))?;
+        Ok(())
+    }
+}
+
+impl fmt::Display for InputProfile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let default = AxisProfile::default();
+        for (id, axis) in &self.axes {
+            write!(f, "axis {}", id)?;
+            if axis.deadband != default.deadband {
+                write!(f, " deadband={}", axis.deadband)?;
+            }
+            if axis.expo != default.expo {
+                write!(f, " expo={}", axis.expo)?;
+            }
+            if axis.scale != default.scale {
+                write!(f, " scale={}", axis.scale)?;
+            }
+            if axis.inverted {
+                write!(f, " invert")?;
+            }
+            if let Some(button) = axis.button {
+                write!(f, " button={}@{}", button.id, button.threshold)?;
+            }
+            writeln!(f)?;
+        }
+
+        for (from, to) in &self.buttons {
+            writeln!(f, "button {} {}", from, to)?;
+        }
+        Ok(())
+    }
+}
+
+fn parse_number<T: FromStr>(text: &str, what: &str) -> Result<T> {
+    text.parse()
+        .map_err(|_| format_err!("Invalid {} '{}'", what, text))
+}
+
+/// Checks a button number, which robot code can only read from 1 to `MAX_BUTTONS`
+fn check_button(id: u8) -> Result<u8> {
+    if !(1..=MAX_BUTTONS).contains(&id) {
+        bail!("Button {} is outside 1 to {}", id, MAX_BUTTONS);
+    }
+    Ok(id)
+}
+
+fn parse_button(text: &str) -> Result<u8> {
+    check_button(parse_number(text, "button")?)
+}
+
+fn parse_axis(words: &[&str]) -> Result<(u8, AxisProfile)> {
+    let id = match words.first() {
+        Some(id) => parse_number(id, "axis")?,
+        None => bail!("Missing axis number"),
+    };
+
+    let mut profile = AxisProfile::default();
+    for word in &words[1..] {
+        let (option, value) = match word.find('=') {
+            Some(i) => (&word[..i], &word[i + 1..]),
+            None => (*word, ""),
+        };
+        match option {
+            "deadband" => profile.deadband = parse_number(value, "deadband")?,
+            "expo" => profile.expo = parse_number(value, "expo")?,
+            "scale" => profile.scale = parse_number(value, "scale")?,
+            "invert" => profile.inverted = true,
+            "button" => {
+                let at = value.find('@').ok_or_else(|| {
+                    format_err!("Axis button '{}' should be <button>@<threshold>", value)
+                })?;
+                profile.button = Some(AxisButton {
+                    id: parse_button(&value[..at])?,
+                    threshold: parse_number(&value[at + 1..], "threshold")?,
+                });
+            }
+            _ => bail!("Unknown axis option '{}'", option),
+        }
+    }
+    Ok((id, profile))
+}
+
+impl FromStr for InputProfile {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<InputProfile> {
+        let mut profile = InputProfile::new();
+        for (i, line) in s.lines().enumerate() {
+            let words = line.split_whitespace().collect::<Vec<_>>();
+            let result = match words.first() {
+                None => continue,
+                Some(word) if word.starts_with('#') => continue,
+                Some(&"axis") => parse_axis(&words[1..]).map(|(id, axis)| {
+                    profile.axes.insert(id, axis);
+                }),
+                Some(&"button") if words.len() == 3 => parse_button(words[1]).and_then(|from| {
+                    profile.buttons.insert(from, parse_button(words[2])?);
+                    Ok(())
+                }),
+                Some(&"button") => Err(format_err!("Button remaps should be button <from> <to>")),
+                Some(word) => Err(format_err!("Unknown setting '{}'", word)),
+            };
+            result.map_err(|e| format_err!("Line {}: {}", i + 1, e))?;
+        }
+        Ok(profile)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn processes_axes() {
+        let profile = AxisProfile {
+            deadband: 0.5,
+            ..AxisProfile::default()
+        };
+        assert_eq!(profile.apply(0.4), 0.0);
+        assert_eq!(profile.apply(-0.75), -0.5);
+        assert_eq!(profile.apply(1.0), 1.0);
+
+        let profile = AxisProfile {
+            expo: 1.0,
+            scale: 0.5,
+            inverted: true,
+            ..AxisProfile::default()
+        };
+        assert_eq!(profile.apply(0.5), -0.0625);
+        assert_eq!(profile.apply(-1.0), 0.5);
+    }
+
+    #[test]
+    fn processes_joysticks() {
+        let profile = InputProfile::new()
+            .axis(
+                3,
+                AxisProfile {
+                    button: Some(AxisButton {
+                        id: 9,
+                        threshold: 0.5,
+                    }),
+                    ..AxisProfile::default()
+                },
+            )
+            .remap_button(1, 2)
+            .remap_button(2, 1);
+
+        let values = profile.apply(&[
+            JoystickValue::Axis { id: 0, value: 0.3 },
+            JoystickValue::Axis { id: 3, value: 0.7 },
+            JoystickValue::Button {
+                id: 1,
+                pressed: true,
+            },
+            JoystickValue::Button {
+                id: 2,
+                pressed: false,
+            },
+            JoystickValue::Button {
+                id: 9,
+                pressed: false,
+            },
+            JoystickValue::POV { id: 0, angle: 90 },
+        ]);
+        assert_eq!(
+            values,
+            vec![
+                JoystickValue::Axis { id: 0, value: 0.3 },
+                JoystickValue::Axis { id: 3, value: 0.7 },
+                JoystickValue::POV { id: 0, angle: 90 },
+                JoystickValue::Button {
+                    id: 1,
+                    pressed: false
+                },
+                JoystickValue::Button {
+                    id: 2,
+                    pressed: true
+                },
+                JoystickValue::Button {
+                    id: 9,
+                    pressed: true
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn saves_as_text() {
+        let text = "# Driver 1\naxis 1 deadband=0.1 expo=0.4 scale=0.8 invert\n\naxis 3 button=11@-0.5\nbutton 1 2\n";
+        let profile = text.parse::<InputProfile>().unwrap();
+        assert_eq!(
+            profile.axis_profile(1),
+            Some(&AxisProfile {
+                deadband: 0.1,
+                expo: 0.4,
+                scale: 0.8,
+                inverted: true,
+                button: None,
+            })
+        );
+        assert_eq!(profile.button_id(1), 2);
+        assert_eq!(
+            profile.to_string(),
+            "axis 1 deadband=0.1 expo=0.4 scale=0.8 invert\naxis 3 button=11@-0.5\nbutton 1 2\n"
+        );
+        assert_eq!(
+            profile.to_string().parse::<InputProfile>().unwrap(),
+            profile
+        );
+
+        let error = "axis 1\naxis 2 deadzone=0.1"
+            .parse::<InputProfile>()
+            .unwrap_err();
+        assert_eq!(error.to_string(), "Line 2: Unknown axis option 'deadzone'");
+        assert!("button 1".parse::<InputProfile>().is_err());
+        assert!("axis 1 button=3".parse::<InputProfile>().is_err());
+    }
+
+    #[test]
+    fn rejects_out_of_range_buttons() {
+        let error = "button 1 33".parse::<InputProfile>().unwrap_err();
+        assert_eq!(error.to_string(), "Line 1: Button 33 is outside 1 to 32");
+        assert!("button 0 1".parse::<InputProfile>().is_err());
+        assert!("axis 3 button=40@0.5".parse::<InputProfile>().is_err());
+        assert!("button 32 1\naxis 3 button=32@0.5"
+            .parse::<InputProfile>()
+            .is_ok());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serializes() {
+        let profile = "axis 1 deadband=0.1 invert\naxis 3 button=11@0.5\nbutton 1 2"
+            .parse::<InputProfile>()
+            .unwrap();
+        let json = serde_json::to_string(&profile).unwrap();
+        assert_eq!(
+            serde_json::from_str::<InputProfile>(&json).unwrap(),
+            profile
+        );
+
+        let error =
+            serde_json::from_str::<InputProfile>(r#"{"axes":{},"buttons":{"1":33}}"#).unwrap_err();
+        assert_eq!(error.to_string(), "Button 33 is outside 1 to 32");
+        assert!(serde_json::from_str::<InputProfile>(
+            r#"{"axes":{"3":{"deadband":0.0,"expo":0.0,"scale":1.0,"inverted":false,"button":{"id":0,"threshold":0.5}}},"buttons":{}}"#
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,536 @@
+use crate::ds::state::RobotStatus;
+use crate::ds::Clock;
+use crate::{AsyncDriverStation, DriverStation, JoystickValue, Result};
+
+use failure::{bail, format_err};
+use futures::executor::block_on;
+use log::*;
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+
+/// The first line of every recording
+const HEADER: &str = "# ds joystick recording";
+
+/// The joystick values of every port, sent in one control packet
+#[derive(Debug, Clone, PartialEq)]
+pub struct JoystickSample {
+    /// Time since the robot was enabled
+    pub time: Duration,
+    pub joysticks: Vec<Vec<JoystickValue>>,
+}
+
+impl JoystickSample {
+    /// Writes the sample as a line of text: its time in seconds, then the values of each port after a `|`
+    fn write_line(&self, line: &mut String) {
+        let _ = write!(line, "{:.3}", self.time.as_secs_f64());
+        for joystick in &self.joysticks {
+            line.push_str(" |");
+            for value in joystick {
+                let _ = match *value {
+                    JoystickValue::Axis { id, value } => write!(line, " a{}={}", id, value),
+                    JoystickValue::Button { id, pressed } => {
+                        write!(line, " b{}={}", id, pressed as u8)
+                    }
+                    JoystickValue::POV { id, angle } => write!(line, " p{}={}", id, angle),
+                };
+            }
+        }
+        line.push('\n');
+    }
+
+    fn parse_line(line: &str) -> Result<JoystickSample> {
+        let mut ports = line.split('|');
+        let time = ports.next().unwrap_or_default().trim();
+        let time = time
+            .parse::<f64>()
+            .ok()
+            .filter(|time| *time >= 0.0 && time.is_finite())
+            .ok_or_else(|| format_err!("Invalid time '{}'", time))?;
+
+        let mut joysticks = Vec::new();
+        for port in ports {
+            let mut joystick = Vec::new();
+            for value in port.split_whitespace() {
+                joystick.push(
+                    parse_value(value).ok_or_else(|| format_err!("Invalid value '{}'", value))?,
+                );
+            }
+            joysticks.push(joystick);
+        }
+
+        Ok(JoystickSample {
+            time: Duration::from_secs_f64(time),
+            joysticks,
+        })
+    }
+}
+
+/// Parses a value such as `a1=0.5`, `b3=1`, or `p0=90`
+fn parse_value(s: &str) -> Option<JoystickValue> {
+    let eq = s.find('=')?;
+    let id = s.get(1..eq)?.parse().ok()?;
+    let value = &s[eq + 1..];
+    match s.as_bytes()[0] {
+        b'a' => Some(JoystickValue::Axis {
+            id,
+            value: value.parse().ok()?,
+        }),
+        b'b' => Some(JoystickValue::Button {
+            id,
+            pressed: match value {
+                "0" => false,
+                "1" => true,
+                _ => return None,
+            },
+        }),
+        b'p' => Some(JoystickValue::POV {
+            id,
+            angle: value.parse().ok()?,
+        }),
+        _ => None,
+    }
+}
+
+/// Joystick values recorded from a driver, to be played back with [`JoystickPlayback`](struct.JoystickPlayback.html)
+///
+/// Recordings are text files with a line for each control packet, written by
+/// [`JoystickRecorder`](struct.JoystickRecorder.html).
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct JoystickRecording {
+    samples: Vec<JoystickSample>,
+}
+
+impl JoystickRecording {
+    /// Creates a recording of `samples`, which have to be in order of time
+    pub fn new(samples: Vec<JoystickSample>) -> JoystickRecording {
+        JoystickRecording { samples }
+    }
+
+    /// Reads a recording saved by a [`JoystickRecorder`](struct.JoystickRecorder.html)
+    pub fn open(path: impl AsRef<Path>) -> Result<JoystickRecording> {
+        let path = path.as_ref();
+        let file = File::open(path)
+            .map_err(|e| format_err!("Failed to open recording {}: {}", path.display(), e))?;
+        JoystickRecording::read(BufReader::new(file))
+    }
+
+    /// Reads a recording, ignoring blank lines and lines starting with `#`
+    pub fn read(reader: impl BufRead) -> Result<JoystickRecording> {
+        let mut samples: Vec<JoystickSample> = Vec::new();
+        for (i, line) in reader.lines().enumerate() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let sample = JoystickSample::parse_line(line)
+                .map_err(|e| format_err!("Line {}: {}", i + 1, e))?;
+            if samples.last().is_some_and(|last| last.time > sample.time) {
+                bail!("Line {}: Samples are out of order", i + 1);
+            }
+            samples.push(sample);
+        }
+        Ok(JoystickRecording { samples })
+    }
+
+    /// Writes the recording in the format read by [`read`](#method.read)
+    pub fn write(&self, mut writer: impl Write) -> Result<()> {
+        writeln!(writer, "{}", HEADER)?;
+        let mut line = String::new();
+        for sample in &self.samples {
+            line.clear();
+            sample.write_line(&mut line);
+            writer.write_all(line.as_bytes())?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    pub fn samples(&self) -> &[JoystickSample] {
+        &self.samples
+    }
+
+    /// Returns the time of the last sample
+    pub fn duration(&self) -> Duration {
+        self.samples
+            .last()
+            .map_or(Duration::default(), |sample| sample.time)
+    }
+
+    /// Returns the joystick values at `time` after the robot was enabled: those of the last sample at or before it
+    ///
+    /// Returns None once the recording has ended, or if it is empty.
+    pub fn at(&self, time: Duration) -> Option<&[Vec<JoystickValue>]> {
+        if time > self.duration() + Duration::from_millis(20) {
+            return None;
+        }
+
+        let next = self.samples.partition_point(|sample| sample.time <= time);
+        let sample = self.samples.get(next.saturating_sub(1))?;
+        Some(&sample.joysticks)
+    }
+}
+
+enum RecorderState {
+    /// Waiting for the robot to be enabled
+    Waiting,
+    Recording {
+        enabled_at: Instant,
+    },
+    Finished,
+}
+
+struct RecorderInner {
+    writer: Box<dyn Write + Send>,
+    state: RecorderState,
+    samples: usize,
+}
+
+impl RecorderInner {
+    fn write(&mut self, sample: &JoystickSample) -> std::io::Result<()> {
+        let mut line = String::new();
+        sample.write_line(&mut line);
+        self.writer.write_all(line.as_bytes())
+    }
+}
+
+/// Records the joystick values sent to the robot while it is enabled
+///
+/// Recording starts the first time the robot is enabled after the recorder is given to
+/// [`DriverStation::set_joystick_recorder`](../struct.DriverStation.html#method.set_joystick_recorder), and finishes
+/// when the robot is next disabled, so that a recording covers one enabled period. The values are those returned by
+/// the joystick supplier, before any [`InputProfile`](struct.InputProfile.html) is applied.
+///
+/// Clones share the same recording.
+#[derive(Clone)]
+pub struct JoystickRecorder {
+    inner: Arc<Mutex<RecorderInner>>,
+}
+
+impl JoystickRecorder {
+    /// Creates a recorder writing to the file at `path`
+    pub fn create(path: impl AsRef<Path>) -> Result<JoystickRecorder> {
+        let path = path.as_ref();
+        let file = File::create(path)
+            .map_err(|e| format_err!("Failed to create recording {}: {}", path.display(), e))?;
+        JoystickRecorder::new(BufWriter::new(file))
+    }
+
+    /// Creates a recorder writing to `writer`
+    pub fn new(mut writer: impl Write + Send + 'static) -> Result<JoystickRecorder> {
+        writeln!(writer, "{}", HEADER)?;
+        Ok(JoystickRecorder {
+            inner: Arc::new(Mutex::new(RecorderInner {
+                writer: Box::new(writer),
+                state: RecorderState::Waiting,
+                samples: 0,
+            })),
+        })
+    }
+
+    /// Records the values sent in a control packet at `now`, while the robot is `enabled`
+    pub(crate) fn record(&self, enabled: bool, joysticks: &[Vec<JoystickValue>], now: Instant) {
+        let mut inner = self.inner.lock().unwrap();
+        let enabled_at = match (&inner.state, enabled) {
+            (RecorderState::Waiting, true) => {
+                inner.state = RecorderState::Recording { enabled_at: now };
+                now
+            }
+            (RecorderState::Recording { enabled_at }, true) => *enabled_at,
+            (RecorderState::Recording { .. }, false) => {
+                inner.state = RecorderState::Finished;
+                if let Err(e) = inner.writer.flush() {
+                    warn!("Failed to write joystick recording: {}", e);
+                }
+                return;
+            }
+            _ => return,
+        };
+
+        let sample = JoystickSample {
+            time: now.saturating_duration_since(enabled_at),
+            joysticks: joysticks.to_vec(),
+        };
+        match inner.write(&sample) {
+            Ok(()) => inner.samples += 1,
+            Err(e) => {
+                warn!("Failed to write joystick recording: {}", e);
+                inner.state = RecorderState::Finished;
+            }
+        }
+    }
+
+    /// Returns whether the robot has been disabled since recording started, so nothing more will be recorded
+    pub fn finished(&self) -> bool {
+        matches!(self.inner.lock().unwrap().state, RecorderState::Finished)
+    }
+
+    /// Returns the number of control packets recorded so far
+    pub fn samples(&self) -> usize {
+        self.inner.lock().unwrap().samples
+    }
+
+    /// Stops recording, and writes out anything still buffered
+    pub fn finish(&self) -> Result<()> {
+        let mut inner = self.inner.lock().unwrap();
+        inner.state = RecorderState::Finished;
+        inner.writer.flush()?;
+        Ok(())
+    }
+}
+
+struct PlaybackState {
+    /// When the robot was enabled, while it is
+    enabled_at: Option<Instant>,
+    finished: bool,
+}
+
+/// Plays a [`JoystickRecording`](struct.JoystickRecording.html) back as the joystick supplier of a driver station
+///
+/// Playback starts from the beginning whenever the robot is enabled, so the recording lines up with robot code in the
+/// same way as when it was recorded. No joystick values are sent while the robot is disabled, or once the recording
+/// has ended, which robot code reads as no joysticks being plugged in. Paired with
+/// [`simulation mode`](../enum.DsMode.html) this repeats a driver's inputs against the WPILib simulator:
+///
+/// ```no_run
+/// # use ds::{DriverStation, input::{JoystickPlayback, JoystickRecording}};
+/// # fn main() -> ds::Result<()> {
+/// let mut ds = DriverStation::builder().target("localhost").build()?;
+/// let playback = JoystickPlayback::new(JoystickRecording::open("drive.txt")?);
+/// playback.install(&mut ds);
+/// ds.enable();
+/// while !playback.finished() {
+///     std::thread::sleep(std::time::Duration::from_millis(20));
+/// }
+/// ds.disable();
+/// # Ok(())
+/// # }
+/// ```
+///
+/// Clones share the same playback.
+#[derive(Clone)]
+pub struct JoystickPlayback {
+    recording: Arc<JoystickRecording>,
+    state: Arc<Mutex<PlaybackState>>,
+}
+
+impl JoystickPlayback {
+    pub fn new(recording: JoystickRecording) -> JoystickPlayback {
+        JoystickPlayback {
+            recording: Arc::new(recording),
+            state: Arc::new(Mutex::new(PlaybackState {
+                enabled_at: None,
+                finished: false,
+            })),
+        }
+    }
+
+    /// Makes the playback the joystick supplier of `ds`, replacing any other supplier
+    pub fn install(&self, ds: &mut DriverStation) {
+        block_on(self.install_async(ds.as_async()));
+    }
+
+    /// Makes the playback the joystick supplier of `ds`, replacing any other supplier
+    pub async fn install_async(&self, ds: &mut AsyncDriverStation) {
+        let status = ds.state.subscribe_status();
+        let clock = ds.state.clock().clone();
+        let playback = self.clone();
+        ds.set_joystick_supplier(move || playback.supply(&status, &*clock))
+            .await;
+    }
+
+    fn supply(
+        &self,
+        status: &watch::Receiver<RobotStatus>,
+        clock: &dyn Clock,
+    ) -> Vec<Vec<JoystickValue>> {
+        let enabled = status.borrow().enabled;
+        self.values(enabled, clock.instant())
+    }
+
+    /// Returns the values to send at `now`, while the robot is `enabled`
+    fn values(&self, enabled: bool, now: Instant) -> Vec<Vec<JoystickValue>> {
+        let mut state = self.state.lock().unwrap();
+        if !enabled {
+            state.enabled_at = None;
+            return Vec::new();
+        }
+
+        let enabled_at = *state.enabled_at.get_or_insert(now);
+        match self.recording.at(now.saturating_duration_since(enabled_at)) {
+            Some(joysticks) => joysticks.to_vec(),
+            None => {
+                state.finished = true;
+                Vec::new()
+            }
+        }
+    }
+
+    /// Returns whether the whole recording has been played back since the playback was created
+    pub fn finished(&self) -> bool {
+        self.state.lock().unwrap().finished
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn joystick(value: f32, pressed: bool) -> Vec<JoystickValue> {
+        vec![
+            JoystickValue::Axis { id: 1, value },
+            JoystickValue::Button { id: 2, pressed },
+            JoystickValue::POV { id: 0, angle: -1 },
+        ]
+    }
+
+    /// A writer whose output can be read back after it has been given to a recorder
+    #[derive(Clone, Default)]
+    struct SharedBuf(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuf {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn records_one_enabled_period() {
+        let buf = SharedBuf::default();
+        let recorder = JoystickRecorder::new(buf.clone()).unwrap();
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+
+        recorder.record(false, &[joystick(0.1, false)], at(0));
+        recorder.record(true, &[joystick(0.5, true)], at(20));
+        recorder.record(true, &[joystick(-0.25, false), Vec::new()], at(40));
+        recorder.record(false, &[joystick(1.0, true)], at(60));
+        recorder.record(true, &[joystick(1.0, true)], at(80));
+        assert!(recorder.finished());
+        assert_eq!(recorder.samples(), 2);
+
+        let text = String::from_utf8(buf.0.lock().unwrap().clone()).unwrap();
+        assert_eq!(
+            text,
+            "# ds joystick recording\n0.000 | a1=0.5 b2=1 p0=-1\n0.020 | a1=-0.25 b2=0 p0=-1 |\n"
+        );
+
+        let recording = JoystickRecording::read(text.as_bytes()).unwrap();
+        assert_eq!(
+            recording.samples(),
+            &[
+                JoystickSample {
+                    time: Duration::from_millis(0),
+                    joysticks: vec![joystick(0.5, true)],
+                },
+                JoystickSample {
+                    time: Duration::from_millis(20),
+                    joysticks: vec![joystick(-0.25, false), Vec::new()],
+                },
+            ]
+        );
+
+        let mut written = Vec::new();
+        recording.write(&mut written).unwrap();
+        assert_eq!(written, text.as_bytes());
+
+        assert!(JoystickRecording::read(&b"0.020 | a1=0\n0.000 | a1=0\n"[..]).is_err());
+        assert!(JoystickRecording::read(&b"0.000 | x1=0\n"[..]).is_err());
+    }
+
+    #[test]
+    fn plays_back_from_enable() {
+        let recording = JoystickRecording::new(vec![
+            JoystickSample {
+                time: Duration::from_millis(0),
+                joysticks: vec![joystick(0.5, true)],
+            },
+            JoystickSample {
+                time: Duration::from_millis(20),
+                joysticks: vec![joystick(1.0, false)],
+            },
+        ]);
+        let playback = JoystickPlayback::new(recording);
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+
+        assert_eq!(
+            playback.values(false, at(0)),
+            Vec::<Vec<JoystickValue>>::new()
+        );
+        assert_eq!(playback.values(true, at(100)), vec![joystick(0.5, true)]);
+        assert_eq!(playback.values(true, at(119)), vec![joystick(0.5, true)]);
+        assert_eq!(playback.values(true, at(125)), vec![joystick(1.0, false)]);
+
+        // Disabling and enabling again starts from the beginning
+        playback.values(false, at(130));
+        assert_eq!(playback.values(true, at(200)), vec![joystick(0.5, true)]);
+        assert!(!playback.finished());
+        assert!(playback.values(true, at(300)).is_empty());
+        assert!(playback.finished());
+    }
+
+    #[tokio::test]
+    async fn records_and_plays_back_driver_station() {
+        use crate::proto::udp::outbound::types::tags::UdpTag;
+        use crate::testing::MockRoborio;
+        use crate::Control;
+
+        let mock = MockRoborio::bind_any().await.unwrap();
+        let mut ds = mock.connected_ds().build_async().unwrap();
+        let axis = |control: &crate::UdpControlPacket| {
+            control.tags.iter().find_map(|tag| match tag {
+                UdpTag::Joysticks(joysticks) => Some(joysticks.axes()[1]),
+                _ => None,
+            })
+        };
+
+        let buf = SharedBuf::default();
+        let recorder = JoystickRecorder::new(buf.clone()).unwrap();
+        ds.set_joystick_recorder(Some(recorder.clone())).await;
+        ds.set_joystick_supplier(|| vec![joystick(0.5, true)]).await;
+        ds.enable().await;
+        mock.wait_for_control(|control| control.control.contains(Control::ENABLED))
+            .await;
+        while recorder.samples() < 3 {
+            tokio::time::delay_for(Duration::from_millis(10)).await;
+        }
+        ds.disable().await;
+        while !recorder.finished() {
+            tokio::time::delay_for(Duration::from_millis(10)).await;
+        }
+        ds.set_joystick_recorder(None).await;
+
+        let recording = JoystickRecording::read(&buf.0.lock().unwrap()[..]).unwrap();
+        assert!(recording
+            .samples()
+            .iter()
+            .all(|sample| sample.joysticks == vec![joystick(0.5, true)]));
+
+        // Played back, the recorded values replace those of the supplier while the robot is enabled
+        let playback = JoystickPlayback::new(recording);
+        playback.install_async(&mut ds).await;
+        mock.wait_for_control(|control| axis(control).is_none())
+            .await;
+        ds.enable().await;
+        mock.wait_for_control(|control| axis(control) == Some(64))
+            .await;
+        while !playback.finished() {
+            tokio::time::delay_for(Duration::from_millis(10)).await;
+        }
+        assert!(ds.enabled().await);
+        mock.wait_for_control(|control| axis(control).is_none())
+            .await;
+        ds.shutdown(Duration::from_secs(1)).await.unwrap();
+    }
+}
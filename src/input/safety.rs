@@ -0,0 +1,176 @@
+use super::{Key, KeyEvent};
+use crate::ds::state::SafetyLatch;
+
+use std::sync::Arc;
+
+/// The safety hotkeys of the NI driver station: the space bar E-stops the robot, and Enter disables it
+///
+/// Key presses are handled without taking any of the locks that
+/// [`DriverStation::estop`](../struct.DriverStation.html#method.estop) and
+/// [`disable`](../struct.DriverStation.html#method.disable) wait on. The request is recorded atomically, and the
+/// control packet carrying it is sent straight away without calling the joystick supplier, rather than at the next
+/// 20ms interval. The supplier runs on a thread of its own and other packets only wait 10ms for it, so even a supplier
+/// that never returns doesn't delay them.
+///
+/// `SafetyKeys` can be driven by any source of key events, such as
+/// [`read_terminal_keys`](fn.read_terminal_keys.html). Clones share the same driver station.
+#[derive(Clone)]
+pub struct SafetyKeys {
+    latch: Arc<SafetyLatch>,
+    estop: Key,
+    disable: Key,
+}
+
+impl SafetyKeys {
+    pub(crate) fn new(latch: Arc<SafetyLatch>) -> SafetyKeys {
+        SafetyKeys {
+            latch,
+            estop: Key::Char(' '),
+            disable: Key::Enter,
+        }
+    }
+
+    /// Changes the key that E-stops the robot, the space bar by default
+    pub fn estop_key(mut self, key: Key) -> Self {
+        self.estop = key;
+        self
+    }
+
+    /// Changes the key that disables the robot, Enter by default
+    pub fn disable_key(mut self, key: Key) -> Self {
+        self.disable = key;
+        self
+    }
+
+    /// E-stops the robot if `event` is a press of the E-stop key, or disables it if it is a press of the disable key
+    ///
+    /// Returns whether the event was a press of either key.
+    pub fn handle(&self, event: &KeyEvent) -> bool {
+        if !event.pressed {
+            return false;
+        }
+
+        if event.key == self.estop {
+            self.estop();
+            true
+        } else if event.key == self.disable {
+            self.disable();
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Handles every event from `source` on the calling thread, passing the ones that aren't safety keys to `other`
+    pub fn run(&self, source: impl IntoIterator<Item = KeyEvent>, mut other: impl FnMut(KeyEvent)) {
+        for event in source {
+            if !self.handle(&event) {
+                other(event);
+            }
+        }
+    }
+
+    /// E-stops the robot, regardless of the keys pressed
+    pub fn estop(&self) {
+        self.latch.estop();
+    }
+
+    /// Disables the robot, regardless of the keys pressed
+    pub fn disable(&self) {
+        self.latch.disable();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ds::state::SafetyLatch;
+
+    #[test]
+    fn handles_only_safety_key_presses() {
+        let latch = Arc::new(SafetyLatch::new());
+        let keys = SafetyKeys::new(latch.clone());
+
+        let mut others = Vec::new();
+        keys.run(
+            vec![
+                KeyEvent::press(Key::Char('a')),
+                KeyEvent::release(Key::Enter),
+                KeyEvent::press(Key::Enter),
+            ],
+            |event| others.push(event),
+        );
+        assert_eq!(
+            others,
+            vec![
+                KeyEvent::press(Key::Char('a')),
+                KeyEvent::release(Key::Enter)
+            ]
+        );
+        assert_eq!(latch.take(), (true, false));
+
+        let keys = keys.estop_key(Key::Escape);
+        assert!(!keys.handle(&KeyEvent::press(Key::Char(' '))));
+        assert!(keys.handle(&KeyEvent::press(Key::Escape)));
+        assert_eq!(latch.take(), (false, true));
+    }
+
+    #[tokio::test]
+    async fn estops_while_send_state_is_locked() {
+        use crate::testing::MockRoborio;
+        use crate::Control;
+        use futures::{future, StreamExt};
+        use std::time::Duration;
+
+        let mock = MockRoborio::bind_any().await.unwrap();
+        let mut ds = mock.connected_ds().build_async().unwrap();
+        let keys = ds.safety_keys();
+        ds.enable().await;
+        mock.wait_for_control(|control| control.control.contains(Control::ENABLED))
+            .await;
+
+        // The safety keys don't wait for the lock on the send state
+        let send = ds.state.send().lock().await;
+        assert!(keys.handle(&KeyEvent::press(Key::Char(' '))));
+        drop(send);
+
+        let control = mock
+            .wait_for_control(|control| control.control.contains(Control::ESTOP))
+            .await;
+        assert!(!control.control.contains(Control::ENABLED));
+        assert!(ds.estopped().await);
+        let status = ds
+            .status()
+            .skip_while(|status| future::ready(!status.estopped));
+        futures::pin_mut!(status);
+        assert!(!status.next().await.unwrap().enabled);
+        ds.shutdown(Duration::from_secs(1)).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn estops_while_supplier_is_stuck() {
+        use crate::testing::MockRoborio;
+        use crate::Control;
+        use std::time::Duration;
+
+        let mock = MockRoborio::bind_any().await.unwrap();
+        let mut ds = mock.connected_ds().build_async().unwrap();
+        let keys = ds.safety_keys();
+        ds.set_joystick_supplier(|| loop {
+            std::thread::park();
+        })
+        .await;
+        ds.enable().await;
+        let enabled = mock
+            .wait_for_control(|control| control.control.contains(Control::ENABLED))
+            .await;
+
+        // Packets keep being sent, and safety requests still go out
+        mock.wait_for_control(|control| control.seqnum >= enabled.seqnum.wrapping_add(5))
+            .await;
+        assert!(keys.handle(&KeyEvent::press(Key::Char(' '))));
+        mock.wait_for_control(|control| control.control.contains(Control::ESTOP))
+            .await;
+        ds.shutdown(Duration::from_secs(1)).await.unwrap();
+    }
+}
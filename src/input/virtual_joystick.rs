@@ -0,0 +1,282 @@
+use super::{Gamepad, GamepadAxis, GamepadButton, GamepadLayout, Key, KeyEvent};
+use crate::{JoystickDesc, JoystickValue};
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// A gamepad driven by the keyboard, for testing robot code when no controller is at hand
+///
+/// Keys are mapped to buttons, or to a value that an axis moves towards while the key is held. Axes move there at once
+/// by default, or at the rate set by [`ramp`](#method.ramp) to mimic pushing a stick gradually. Holding keys for both
+/// directions of an axis adds their values, so they cancel out.
+///
+/// Events from any source are given to [`handle`](#method.handle), and [`update`](#method.update) is called before
+/// reading the values, usually from the joystick supplier. The joystick is an Xbox controller to robot code, sent on
+/// whichever port it is given by [`descriptor`](#method.descriptor), or by [`Gamepads`](struct.Gamepads.html) when
+/// [`gamepad`](#method.gamepad) is connected there alongside real gamepads.
+///
+/// Terminals only report key presses, repeating them while a key is held. With sources reading from one,
+/// [`release_after`](#method.release_after) releases keys that haven't repeated for a while instead.
+#[derive(Debug, Clone)]
+pub struct VirtualJoystick {
+    gamepad: Gamepad,
+    axis_keys: HashMap<Key, (GamepadAxis, f32)>,
+    button_keys: HashMap<Key, GamepadButton>,
+    /// The keys held down, and when they were last pressed
+    held: HashMap<Key, Instant>,
+    /// How far an axis moves each second, or None to move at once
+    ramp: Option<f32>,
+    release_after: Option<Duration>,
+    last_update: Option<Instant>,
+}
+
+impl VirtualJoystick {
+    /// Creates a virtual joystick without any keys mapped
+    pub fn new() -> VirtualJoystick {
+        VirtualJoystick {
+            gamepad: Gamepad::new("Virtual Joystick", GamepadLayout::Xbox),
+            axis_keys: HashMap::new(),
+            button_keys: HashMap::new(),
+            held: HashMap::new(),
+            ramp: None,
+            release_after: None,
+            last_update: None,
+        }
+    }
+
+    /// Creates a virtual joystick with WASD moving the left stick, IJKL moving the right stick, and U and O pulling
+    /// the left and right triggers
+    ///
+    /// Z, X, C, and V press A, B, X, and Y, and 1 and 2 press the left and right bumpers. None of the keys clash with
+    /// the safety hotkeys of [`SafetyKeys`](struct.SafetyKeys.html).
+    pub fn wasd() -> VirtualJoystick {
+        use GamepadAxis::*;
+
+        let mut joystick = VirtualJoystick::new();
+        let axes = [
+            ('w', LeftStickY, 1.0),
+            ('s', LeftStickY, -1.0),
+            ('a', LeftStickX, -1.0),
+            ('d', LeftStickX, 1.0),
+            ('i', RightStickY, 1.0),
+            ('k', RightStickY, -1.0),
+            ('j', RightStickX, -1.0),
+            ('l', RightStickX, 1.0),
+            ('u', LeftTrigger, 1.0),
+            ('o', RightTrigger, 1.0),
+        ];
+        for &(c, axis, value) in &axes {
+            joystick = joystick.axis_key(Key::Char(c), axis, value);
+        }
+
+        let buttons = [
+            ('z', GamepadButton::South),
+            ('x', GamepadButton::East),
+            ('c', GamepadButton::West),
+            ('v', GamepadButton::North),
+            ('1', GamepadButton::LeftBumper),
+            ('2', GamepadButton::RightBumper),
+        ];
+        for &(c, button) in &buttons {
+            joystick = joystick.button_key(Key::Char(c), button);
+        }
+        joystick
+    }
+
+    /// Renames the joystick, which robot code sees in its descriptor
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.gamepad.name = name.into();
+        self
+    }
+
+    /// Maps `key` to moving `axis` towards `value` while it is held, replacing any other mapping of the key
+    ///
+    /// Stick Y axes are positive when pushed up, as with [`Gamepad`](struct.Gamepad.html).
+    pub fn axis_key(mut self, key: Key, axis: GamepadAxis, value: f32) -> Self {
+        self.button_keys.remove(&key);
+        self.axis_keys.insert(key, (axis, value));
+        self
+    }
+
+    /// Maps `key` to pressing `button` while it is held, replacing any other mapping of the key
+    pub fn button_key(mut self, key: Key, button: GamepadButton) -> Self {
+        self.axis_keys.remove(&key);
+        self.button_keys.insert(key, button);
+        self
+    }
+
+    /// Moves axes towards the value of their keys by at most `per_second` each second, rather than at once
+    ///
+    /// A rate of 2 takes a stick from centred to fully pushed in half a second.
+    pub fn ramp(mut self, per_second: f32) -> Self {
+        self.ramp = Some(per_second);
+        self
+    }
+
+    /// Releases keys that haven't been pressed again for `timeout`, for sources that never report keys being released
+    ///
+    /// The timeout has to be longer than the delay before a held key starts repeating, which is usually 500ms.
+    pub fn release_after(mut self, timeout: Duration) -> Self {
+        self.release_after = Some(timeout);
+        self
+    }
+
+    /// Presses or releases the mapped key of `event`, returning whether the key is mapped
+    ///
+    /// Axes and buttons change on the next call to [`update`](#method.update).
+    pub fn handle(&mut self, event: &KeyEvent, now: Instant) -> bool {
+        if !self.axis_keys.contains_key(&event.key) && !self.button_keys.contains_key(&event.key) {
+            return false;
+        }
+
+        if event.pressed {
+            self.held.insert(event.key, now);
+        } else {
+            self.held.remove(&event.key);
+        }
+        true
+    }
+
+    /// Releases every key
+    pub fn release_all(&mut self) {
+        self.held.clear();
+    }
+
+    /// Releases keys that have timed out, then moves the axes and presses the buttons of the keys being held
+    pub fn update(&mut self, now: Instant) {
+        if let Some(timeout) = self.release_after {
+            self.held
+                .retain(|_, &mut pressed| now.saturating_duration_since(pressed) < timeout);
+        }
+
+        let mut targets = [0.0; 6];
+        for key in self.held.keys() {
+            if let Some(&(axis, value)) = self.axis_keys.get(key) {
+                targets[axis as usize] += value;
+            }
+        }
+
+        let elapsed = self
+            .last_update
+            .map(|last| now.saturating_duration_since(last).as_secs_f32())
+            .unwrap_or(0.0);
+        self.last_update = Some(now);
+
+        for &axis in &super::gamepad::AXES {
+            let target = targets[axis as usize].clamp(-1.0, 1.0);
+            let value = match self.ramp {
+                Some(per_second) => {
+                    let current = self.gamepad.axis(axis);
+                    let step = per_second * elapsed;
+                    current + (target - current).clamp(-step, step)
+                }
+                None => target,
+            };
+            self.gamepad.set_axis(axis, value);
+        }
+
+        // Release every mapped button before pressing the held ones, in case several keys press the same button
+        for &button in self.button_keys.values() {
+            self.gamepad.set_button(button, false);
+        }
+        for key in self.held.keys() {
+            if let Some(&button) = self.button_keys.get(key) {
+                self.gamepad.set_button(button, true);
+            }
+        }
+    }
+
+    /// Returns the gamepad driven by the keys, as of the last update
+    pub fn gamepad(&self) -> &Gamepad {
+        &self.gamepad
+    }
+
+    /// Returns the values of the joystick as of the last update, numbered for robot code
+    pub fn values(&self) -> Vec<JoystickValue> {
+        self.gamepad.values()
+    }
+
+    /// Returns the descriptor of the joystick when it is on `port`
+    pub fn descriptor(&self, port: u8) -> JoystickDesc {
+        self.gamepad.layout.descriptor(port, &self.gamepad.name)
+    }
+}
+
+impl Default for VirtualJoystick {
+    fn default() -> Self {
+        VirtualJoystick::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn maps_keys() {
+        let start = Instant::now();
+        let mut joystick = VirtualJoystick::wasd();
+
+        assert!(joystick.handle(&KeyEvent::press(Key::Char('w')), start));
+        assert!(joystick.handle(&KeyEvent::press(Key::Char('d')), start));
+        assert!(joystick.handle(&KeyEvent::press(Key::Char('a')), start));
+        assert!(joystick.handle(&KeyEvent::press(Key::Char('z')), start));
+        assert!(!joystick.handle(&KeyEvent::press(Key::Char(' ')), start));
+        joystick.update(start);
+
+        let gamepad = joystick.gamepad();
+        assert_eq!(gamepad.axis(GamepadAxis::LeftStickY), 1.0);
+        assert_eq!(gamepad.axis(GamepadAxis::LeftStickX), 0.0);
+        assert!(gamepad.pressed(GamepadButton::South));
+        assert!(joystick.values().contains(&JoystickValue::Button {
+            id: 1,
+            pressed: true
+        }));
+
+        joystick.handle(&KeyEvent::release(Key::Char('z')), start);
+        joystick.handle(&KeyEvent::release(Key::Char('a')), start);
+        joystick.update(start);
+        assert_eq!(joystick.gamepad().axis(GamepadAxis::LeftStickX), 1.0);
+        assert!(!joystick.gamepad().pressed(GamepadButton::South));
+        assert_eq!(joystick.descriptor(2).index, 2);
+    }
+
+    #[test]
+    fn ramps_axes() {
+        let start = Instant::now();
+        let mut joystick = VirtualJoystick::new()
+            .axis_key(Key::Up, GamepadAxis::LeftStickY, 1.0)
+            .ramp(2.0);
+        joystick.update(start);
+
+        joystick.handle(&KeyEvent::press(Key::Up), start);
+        joystick.update(start + Duration::from_millis(250));
+        assert_eq!(joystick.gamepad().axis(GamepadAxis::LeftStickY), 0.5);
+        joystick.update(start + Duration::from_secs(1));
+        assert_eq!(joystick.gamepad().axis(GamepadAxis::LeftStickY), 1.0);
+
+        joystick.handle(&KeyEvent::release(Key::Up), start + Duration::from_secs(1));
+        joystick.update(start + Duration::from_millis(1250));
+        assert_eq!(joystick.gamepad().axis(GamepadAxis::LeftStickY), 0.5);
+    }
+
+    #[test]
+    fn releases_keys_after_timeout() {
+        let start = Instant::now();
+        let mut joystick = VirtualJoystick::wasd().release_after(Duration::from_millis(600));
+
+        joystick.handle(&KeyEvent::press(Key::Char('s')), start);
+        joystick.update(start + Duration::from_millis(500));
+        assert_eq!(joystick.gamepad().axis(GamepadAxis::LeftStickY), -1.0);
+
+        // The terminal repeats the key while it is held
+        joystick.handle(
+            &KeyEvent::press(Key::Char('s')),
+            start + Duration::from_millis(530),
+        );
+        joystick.update(start + Duration::from_millis(1000));
+        assert_eq!(joystick.gamepad().axis(GamepadAxis::LeftStickY), -1.0);
+        joystick.update(start + Duration::from_millis(1200));
+        assert_eq!(joystick.gamepad().axis(GamepadAxis::LeftStickY), 0.0);
+    }
+}
@@ -2,29 +2,48 @@
 //!
 //! `ds` is a library that allows for control of FIRST Robotics Competition robots.
 //! The protocol supported currently is that of the 2018 season, with only the bare minimum
-//! required to control the robot currently consumed. Diagnostic and telemetry information is not decoded and is discarded
+//! required to control the robot currently consumed. CPU, CAN, and power distribution telemetry is decoded, and can be
+//! logged in the NI driver station's format with the [`log`](log/index.html) module.
 //!
 //! The core trait for use of the crate is the [`DriverStation`](struct.DriverStation.html) crate. This crate
 //! provides an API for connecting and controlling to the roboRIO in an FRC robot. It also allows for users to
-//! provide joystick input using arbitrary APIs, and to consume any incoming TCP packets.
+//! provide joystick input using arbitrary APIs, and to consume any incoming TCP packets. The [`input`](input/index.html)
+//! module provides keyboard based input, including the NI driver station's safety hotkeys.
 
 #![doc(html_root_url = "https://docs.rs/ds/1.0.1")]
 
 #[macro_use]
 extern crate bitflags;
-#[macro_use]
 extern crate smallvec;
 
+pub mod capture;
 mod ds;
 mod ext;
+pub mod input;
+pub mod log;
+pub mod metrics;
 mod proto;
+#[cfg(feature = "server")]
+pub mod server;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
 pub(crate) mod util;
 
-pub use self::ds::state::{DsMode, Mode};
-pub use self::ds::{DriverStation, JoystickValue};
+pub use self::ds::state::{DsMode, Mode, RobotStatus};
+pub use self::ds::{
+    AsyncDriverStation, Clock, ConsoleEntry, ConsoleLevel, ConsoleMessage, DiscrepancyKind,
+    DiscrepancyReason, DriverStation, DriverStationBuilder, DsEvent, EventReceiver, JoystickValue,
+    MatchPhase, Ports, PracticeMatch, PracticeTimings, RobotConsole, SimulatedClock,
+    StateDiscrepancy, SystemClock, Watchdog,
+};
 pub use self::proto::tcp::inbound::*;
 pub use self::proto::tcp::outbound::*;
-pub use self::proto::udp::inbound::types::Trace;
+pub use self::proto::udp::inbound::types::tags::{
+    CanMetrics, CoreUsage, CpuInfo, PdpLog, RamInfo, RobotTag,
+};
+pub use self::proto::udp::inbound::types::{Status, Trace};
+pub use self::proto::udp::inbound::UdpResponsePacket;
 pub use self::proto::udp::outbound::types::*;
+pub use self::proto::udp::outbound::UdpControlPacket;
 
 pub type Result<T> = std::result::Result<T, failure::Error>;
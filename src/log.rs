@@ -0,0 +1,405 @@
+//! Reading and writing the log files produced by the NI driver station
+//!
+//! The NI driver station records the state of the link and of the robot every 20ms to a `.dslog` file, and events
+//! such as robot code output to a `.dsevents` file. Both are read by the NI Log File Viewer and by AdvantageScope.
+//! [`DsLogWriter`](struct.DsLogWriter.html) writes the same formats, either directly or through
+//! [`DriverStationBuilder::log_dir`](../struct.DriverStationBuilder.html#method.log_dir), and
+//! [`DsLogReader`](struct.DsLogReader.html) reads them back, including logs written by the NI driver station.
+//!
+//! Both files start with a big endian `i32` format version, currently 4, followed by the LabVIEW timestamp of the
+//! start of the log. `.dslog` files then contain one fixed size record every 20ms, while `.dsevents` files contain
+//! a timestamp and length prefixed string for every event.
+
+use crate::proto::udp::inbound::types::tags::{PdpLog, RobotTag};
+use crate::proto::udp::inbound::UdpResponsePacket;
+use crate::Result;
+
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use chrono::{DateTime, Duration as ChronoDuration, TimeZone, Utc};
+use failure::bail;
+use std::io::Read;
+use std::time::Duration;
+
+mod reader;
+mod writer;
+
+pub use self::reader::{DsLogReader, LogEvent};
+pub use self::writer::DsLogWriter;
+
+/// The version of the log format written by the NI driver station since 2016
+pub(crate) const VERSION: i32 = 4;
+
+/// The time between two records in a `.dslog` file
+pub const RECORD_PERIOD: Duration = Duration::from_millis(20);
+
+/// Seconds between the LabVIEW epoch, 1904-01-01, and the unix epoch
+const LABVIEW_EPOCH_OFFSET: i64 = 2_082_844_800;
+
+/// The power distribution type written for a CTRE PDP, which is also the length of its data
+const PD_TYPE_CTRE: u32 = 25;
+/// The power distribution type written for a REV PDH, which is also the length of its data
+const PD_TYPE_REV: u32 = 33;
+
+/// A single 20ms sample of a `.dslog` file
+///
+/// Fractions are stored between 0 and 1. The file format has limited precision: trip time is stored in half
+/// milliseconds, packet loss in steps of 4%, voltages and bandwidth in 256ths, and utilization in half percents.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DsLogRecord {
+    /// Round trip time of the UDP link
+    pub trip_time: Duration,
+    /// Fraction of control packets that went unanswered
+    pub packet_loss: f32,
+    pub battery_voltage: f32,
+    /// CPU utilization of the roboRIO
+    pub cpu: f32,
+    /// Utilization of the CAN bus
+    pub can_utilization: f32,
+    /// Signal strength of the radio, in dB
+    pub wifi_db: f32,
+    /// Bandwidth used by the link, in megabits per second
+    pub bandwidth_mb: f32,
+    /// Whether the robot reported a brownout
+    pub brownout: bool,
+    /// Whether the driver station watchdog has disabled the robot
+    pub watchdog: bool,
+    /// Whether the driver station was commanding teleop
+    pub ds_teleop: bool,
+    /// Whether the driver station was commanding the robot to be disabled
+    pub ds_disabled: bool,
+    /// Whether the robot code reported running teleop
+    pub robot_teleop: bool,
+    /// Whether the robot code reported running autonomous
+    pub robot_auto: bool,
+    /// Whether the robot code reported being disabled
+    pub robot_disabled: bool,
+    /// Raw data from a CTRE power distribution panel, if one was reported
+    pub pdp: Option<PdpLog>,
+}
+
+impl Default for DsLogRecord {
+    fn default() -> DsLogRecord {
+        DsLogRecord {
+            trip_time: Duration::from_secs(0),
+            packet_loss: 0.0,
+            battery_voltage: 0.0,
+            cpu: 0.0,
+            can_utilization: 0.0,
+            wifi_db: 0.0,
+            bandwidth_mb: 0.0,
+            brownout: false,
+            watchdog: false,
+            ds_teleop: false,
+            ds_disabled: true,
+            robot_teleop: false,
+            robot_auto: false,
+            robot_disabled: false,
+            pdp: None,
+        }
+    }
+}
+
+impl DsLogRecord {
+    /// Creates a record from the robot's half of a status packet
+    ///
+    /// The link statistics and the driver station's state aren't part of the packet, and are left at their defaults.
+    pub fn from_status(packet: &UdpResponsePacket) -> DsLogRecord {
+        let mut record = DsLogRecord {
+            battery_voltage: packet.battery,
+            brownout: packet.status.is_browning_out(),
+            robot_teleop: packet.trace.is_teleop(),
+            robot_auto: packet.trace.is_autonomous(),
+            robot_disabled: packet.trace.is_disabled(),
+            ..DsLogRecord::default()
+        };
+
+        for tag in &packet.tags {
+            match tag {
+                RobotTag::Cpu(cpu) => record.cpu = cpu.utilization(),
+                RobotTag::Can(can) => record.can_utilization = can.utilization,
+                RobotTag::Pdp(pdp) => record.pdp = Some(pdp.clone()),
+                RobotTag::Ram(_) | RobotTag::Other { .. } => {}
+            }
+        }
+
+        record
+    }
+
+    pub(crate) fn encode(&self) -> Vec<u8> {
+        fn scale(value: f32, factor: f32, max: f32) -> f32 {
+            (value * factor).round().clamp(0.0, max)
+        }
+
+        let mut buf = Vec::with_capacity(14 + PD_TYPE_CTRE as usize);
+        buf.push(scale(self.trip_time.as_secs_f32() * 1000.0, 2.0, 255.0) as u8);
+        buf.push(scale(self.packet_loss, 25.0, 127.0) as u8);
+        buf.write_u16::<BigEndian>(scale(self.battery_voltage, 256.0, 65535.0) as u16)
+            .unwrap();
+        buf.push(scale(self.cpu, 200.0, 255.0) as u8);
+
+        // Flags are inverted, a cleared bit means the flag is set
+        let mut mask = 0xff;
+        for (set, bit) in &[
+            (self.brownout, 7),
+            (self.watchdog, 6),
+            (self.ds_teleop, 5),
+            (self.ds_disabled, 3),
+            (self.robot_teleop, 2),
+            (self.robot_auto, 1),
+            (self.robot_disabled, 0),
+        ] {
+            if *set {
+                mask &= !(1 << bit);
+            }
+        }
+        buf.push(mask);
+
+        buf.push(scale(self.can_utilization, 200.0, 255.0) as u8);
+        buf.push(scale(self.wifi_db, 2.0, 255.0) as u8);
+        buf.write_u16::<BigEndian>(scale(self.bandwidth_mb, 256.0, 65535.0) as u16)
+            .unwrap();
+
+        match self.pdp {
+            Some(ref pdp) => {
+                buf.write_u32::<BigEndian>(PD_TYPE_CTRE).unwrap();
+                let mut data = pdp.data.clone();
+                data.resize(PD_TYPE_CTRE as usize, 0);
+                buf.extend(data);
+            }
+            None => buf.write_u32::<BigEndian>(0).unwrap(),
+        }
+
+        buf
+    }
+
+    /// Reads a single record, the inverse of `encode`
+    ///
+    /// REV power distribution data is skipped, as only the CTRE format is understood.
+    pub(crate) fn decode(reader: &mut impl Read) -> Result<DsLogRecord> {
+        let trip_time = Duration::from_micros(u64::from(reader.read_u8()?) * 500);
+        let packet_loss = (f32::from(reader.read_i8()?) / 25.0).max(0.0);
+        let battery_voltage = f32::from(reader.read_u16::<BigEndian>()?) / 256.0;
+        let cpu = f32::from(reader.read_u8()?) / 200.0;
+        let mask = reader.read_u8()?;
+        let flag = |bit: u8| mask & (1 << bit) == 0;
+        let can_utilization = f32::from(reader.read_u8()?) / 200.0;
+        let wifi_db = f32::from(reader.read_u8()?) / 2.0;
+        let bandwidth_mb = f32::from(reader.read_u16::<BigEndian>()?) / 256.0;
+
+        let pdp = match reader.read_u32::<BigEndian>()? {
+            0 => None,
+            PD_TYPE_CTRE => {
+                let mut data = vec![0; PD_TYPE_CTRE as usize];
+                reader.read_exact(&mut data)?;
+                Some(PdpLog { data })
+            }
+            PD_TYPE_REV => {
+                reader.read_exact(&mut [0; PD_TYPE_REV as usize])?;
+                None
+            }
+            other => bail!("Unknown power distribution type {}", other),
+        };
+
+        Ok(DsLogRecord {
+            trip_time,
+            packet_loss,
+            battery_voltage,
+            cpu,
+            can_utilization,
+            wifi_db,
+            bandwidth_mb,
+            brownout: flag(7),
+            watchdog: flag(6),
+            ds_teleop: flag(5),
+            ds_disabled: flag(3),
+            robot_teleop: flag(2),
+            robot_auto: flag(1),
+            robot_disabled: flag(0),
+            pdp,
+        })
+    }
+}
+
+/// Encodes `time` as a LabVIEW timestamp: whole seconds since 1904, followed by a fraction of a second in 2^-64ths
+pub(crate) fn encode_timestamp(time: DateTime<Utc>, buf: &mut Vec<u8>) -> Result<()> {
+    let fraction = (f64::from(time.timestamp_subsec_nanos()) / 1e9 * 2f64.powi(64)) as u64;
+    buf.write_i64::<BigEndian>(time.timestamp() + LABVIEW_EPOCH_OFFSET)?;
+    buf.write_u64::<BigEndian>(fraction)?;
+    Ok(())
+}
+
+/// Reads a LabVIEW timestamp. Precision is limited to nanoseconds
+pub(crate) fn decode_timestamp(reader: &mut impl Read) -> Result<DateTime<Utc>> {
+    let seconds = reader.read_i64::<BigEndian>()? - LABVIEW_EPOCH_OFFSET;
+    let fraction = reader.read_u64::<BigEndian>()?;
+    let nanos = (fraction as f64 / 2f64.powi(64) * 1e9) as i64;
+    match Utc.timestamp_opt(seconds, 0).single() {
+        Some(time) => Ok(time + ChronoDuration::nanoseconds(nanos)),
+        None => bail!("Timestamp {} is out of range", seconds),
+    }
+}
+
+/// The name the NI driver station gives logs started at `start`, without an extension
+///
+/// e.g. `2020_01_04 09_00_00 Sat`
+pub fn file_stem(start: DateTime<Utc>) -> String {
+    start.format("%Y_%m_%d %H_%M_%S %a").to_string()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::proto::udp::inbound::types::tags::CpuInfo;
+    use crate::{CoreUsage, Status, Trace};
+    use chrono::{NaiveDate, TimeZone};
+
+    #[test]
+    fn encodes_record() {
+        let record = DsLogRecord {
+            trip_time: Duration::from_micros(2500),
+            packet_loss: 0.08,
+            battery_voltage: 12.5,
+            cpu: 0.25,
+            can_utilization: 0.5,
+            wifi_db: 0.0,
+            bandwidth_mb: 1.5,
+            brownout: false,
+            watchdog: false,
+            ds_teleop: true,
+            ds_disabled: false,
+            robot_teleop: true,
+            robot_auto: false,
+            robot_disabled: false,
+            pdp: None,
+        };
+
+        assert_eq!(
+            record.encode(),
+            vec![
+                5,
+                2,
+                0x0c,
+                0x80,
+                50,
+                0b1101_1011,
+                100,
+                0,
+                0x01,
+                0x80,
+                0,
+                0,
+                0,
+                0
+            ]
+        );
+
+        let encoded = DsLogRecord {
+            pdp: Some(PdpLog { data: vec![1; 10] }),
+            ..record
+        }
+        .encode();
+        assert_eq!(encoded.len(), 14 + 25);
+        assert_eq!(&encoded[10..14], &[0, 0, 0, 25]);
+        assert_eq!(&encoded[14..24], &[1; 10]);
+    }
+
+    #[test]
+    fn record_from_status() {
+        let packet = UdpResponsePacket {
+            seqnum: 1,
+            status: Status::BROWNOUT | Status::ENABLED | Status::AUTO,
+            trace: Trace::ROBOT_CODE | Trace::IS_ROBORIO | Trace::AUTONOMOUS,
+            battery: 7.5,
+            need_date: false,
+            tags: vec![RobotTag::Cpu(CpuInfo {
+                cores: vec![CoreUsage {
+                    time_critical: 10.0,
+                    above_normal: 0.0,
+                    normal: 20.0,
+                    low: 0.0,
+                }],
+            })],
+        };
+
+        let record = DsLogRecord::from_status(&packet);
+        assert!(record.brownout);
+        assert!(record.robot_auto);
+        assert!(!record.robot_disabled);
+        assert!((record.cpu - 0.3).abs() < f32::EPSILON);
+        assert_eq!(record.battery_voltage, 7.5);
+    }
+
+    #[test]
+    fn timestamp_round_trip() {
+        let time = Utc.from_utc_datetime(
+            &NaiveDate::from_ymd_opt(2020, 1, 4)
+                .unwrap()
+                .and_hms_milli_opt(9, 0, 0, 250)
+                .unwrap(),
+        );
+        let mut buf = Vec::new();
+        encode_timestamp(time, &mut buf).unwrap();
+        assert_eq!(&buf[..8], &(1_578_128_400i64 + 2_082_844_800).to_be_bytes());
+        assert_eq!(&buf[8..], &(1u64 << 62).to_be_bytes());
+        assert_eq!(decode_timestamp(&mut &buf[..]).unwrap(), time);
+
+        assert_eq!(file_stem(time), "2020_01_04 09_00_00 Sat");
+    }
+
+    #[tokio::test]
+    async fn logs_driver_station_session() {
+        use crate::testing::MockRoborio;
+        use futures::StreamExt;
+
+        let dir = std::env::temp_dir().join(format!("ds-log-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mock = MockRoborio::bind_any().await.unwrap();
+        mock.update_response(|r| {
+            r.battery_voltage = 12.5;
+            r.tags = vec![RobotTag::Pdp(PdpLog { data: vec![7; 25] })];
+        });
+        mock.send_stdout(0.5, "Robot program starting");
+        let ds = mock.connected_ds().log_dir(&dir).build_async().unwrap();
+
+        let mut status = ds.status();
+        while !status.next().await.unwrap().connected {}
+        tokio::time::delay_for(Duration::from_millis(200)).await;
+        ds.shutdown(Duration::from_secs(1)).await.unwrap();
+
+        let read = |extension: &str| {
+            let entry = std::fs::read_dir(&dir)
+                .unwrap()
+                .map(|entry| entry.unwrap().path())
+                .find(|path| path.extension().is_some_and(|e| e == extension))
+                .unwrap();
+            std::fs::read(entry).unwrap()
+        };
+        let dslog = read("dslog");
+        let dsevents = read("dsevents");
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(&dslog[..4], &[0, 0, 0, 4]);
+        let records = &dslog[20..];
+        assert!(!records.is_empty());
+        // Records carry PDP data once the robot has reported it, so they have to be walked one at a time
+        let mut offset = 0;
+        let mut connected_records = 0;
+        while offset < records.len() {
+            let record = &records[offset..];
+            if record[2..4] == [12, 0x80] {
+                connected_records += 1;
+                assert_eq!(record[13], 25);
+                assert_eq!(record[14..39], [7; 25]);
+                offset += 39;
+            } else {
+                assert_eq!(record[10..14], [0, 0, 0, 0]);
+                offset += 14;
+            }
+        }
+        assert_eq!(offset, records.len());
+        assert!(connected_records > 0);
+
+        let text = String::from_utf8_lossy(&dsevents[20..]);
+        assert!(text.contains("Robot program starting"));
+    }
+}
@@ -0,0 +1,235 @@
+use super::{decode_timestamp, DsLogRecord, RECORD_PERIOD, VERSION};
+use crate::Result;
+
+use byteorder::{BigEndian, ReadBytesExt};
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use failure::{bail, format_err};
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
+use std::path::Path;
+
+/// An entry of a `.dsevents` file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogEvent {
+    pub timestamp: DateTime<Utc>,
+    /// The text of the event. The NI driver station embeds tags such as `<TagVersion>` and `<message>` in some events
+    pub text: String,
+}
+
+/// Reads a `.dslog` or `.dsevents` file
+///
+/// Both kinds of file share the same header, which is read by the constructor. Records are then read from `.dslog`
+/// files with [`next_record`](#method.next_record) or [`records`](#method.records), and events from `.dsevents` files
+/// with [`next_event`](#method.next_event) or [`events`](#method.events).
+pub struct DsLogReader<R: BufRead> {
+    reader: R,
+    start: DateTime<Utc>,
+    /// Number of records read so far, used to work out the timestamp of the next one
+    index: i32,
+}
+
+impl DsLogReader<BufReader<File>> {
+    /// Opens the log at `path`
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let file = File::open(path)
+            .map_err(|e| format_err!("Failed to open log {}: {}", path.display(), e))?;
+        DsLogReader::new(BufReader::new(file))
+    }
+}
+
+impl<R: BufRead> DsLogReader<R> {
+    /// Reads the header of a log
+    ///
+    /// Returns Err if the header can't be read, or if the log was written in a format version other than 4.
+    pub fn new(mut reader: R) -> Result<Self> {
+        let version = reader.read_i32::<BigEndian>()?;
+        if version != VERSION {
+            bail!("Unsupported log version {}", version);
+        }
+        let start = decode_timestamp(&mut reader)?;
+
+        Ok(DsLogReader {
+            reader,
+            start,
+            index: 0,
+        })
+    }
+
+    /// Returns the time the log was started at
+    pub fn start(&self) -> DateTime<Utc> {
+        self.start
+    }
+
+    /// Reads the next record of a `.dslog` file along with its timestamp, or None at the end of the file
+    pub fn next_record(&mut self) -> Result<Option<(DateTime<Utc>, DsLogRecord)>> {
+        if self.at_end()? {
+            return Ok(None);
+        }
+
+        let record = DsLogRecord::decode(&mut self.reader)?;
+        let period = ChronoDuration::from_std(RECORD_PERIOD).unwrap();
+        let timestamp = self.start + period * self.index;
+        self.index += 1;
+        Ok(Some((timestamp, record)))
+    }
+
+    /// Reads the next event of a `.dsevents` file, or None at the end of the file
+    pub fn next_event(&mut self) -> Result<Option<LogEvent>> {
+        if self.at_end()? {
+            return Ok(None);
+        }
+
+        let timestamp = decode_timestamp(&mut self.reader)?;
+        let len = self.reader.read_i32::<BigEndian>()?;
+        if len < 0 {
+            bail!("Negative event length {}", len);
+        }
+        let mut text = Vec::new();
+        (&mut self.reader).take(len as u64).read_to_end(&mut text)?;
+        if text.len() != len as usize {
+            bail!("Event truncated after {} of {} bytes", text.len(), len);
+        }
+
+        Ok(Some(LogEvent {
+            timestamp,
+            text: String::from_utf8_lossy(&text).into_owned(),
+        }))
+    }
+
+    /// Returns an iterator over the remaining records of a `.dslog` file
+    pub fn records(self) -> impl Iterator<Item = Result<(DateTime<Utc>, DsLogRecord)>> {
+        self.iter(Self::next_record)
+    }
+
+    /// Returns an iterator over the remaining events of a `.dsevents` file
+    pub fn events(self) -> impl Iterator<Item = Result<LogEvent>> {
+        self.iter(Self::next_event)
+    }
+
+    /// Repeatedly calls `next` until it returns None, stopping after the first error
+    fn iter<T>(
+        mut self,
+        mut next: impl FnMut(&mut Self) -> Result<Option<T>>,
+    ) -> impl Iterator<Item = Result<T>> {
+        let mut failed = false;
+        std::iter::from_fn(move || {
+            if failed {
+                return None;
+            }
+            let item = next(&mut self).transpose();
+            failed = matches!(item, Some(Err(_)));
+            item
+        })
+    }
+
+    fn at_end(&mut self) -> Result<bool> {
+        Ok(self.reader.fill_buf()?.is_empty())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::log::DsLogWriter;
+    use crate::PdpLog;
+    use chrono::TimeZone;
+    use std::time::Duration;
+
+    #[test]
+    fn reads_written_logs() {
+        let start = Utc.timestamp_opt(1_578_128_400, 0).unwrap();
+        let mut writer = DsLogWriter::new(Vec::new(), Vec::new(), start).unwrap();
+        let records = vec![
+            DsLogRecord::default(),
+            DsLogRecord {
+                trip_time: Duration::from_micros(1500),
+                packet_loss: 0.12,
+                battery_voltage: 12.25,
+                cpu: 0.5,
+                can_utilization: 0.125,
+                wifi_db: 40.5,
+                bandwidth_mb: 2.5,
+                brownout: true,
+                watchdog: false,
+                ds_teleop: true,
+                ds_disabled: false,
+                robot_teleop: true,
+                robot_auto: false,
+                robot_disabled: false,
+                pdp: Some(PdpLog {
+                    data: (0..25).collect(),
+                }),
+            },
+        ];
+        for record in &records {
+            writer.write_record(record).unwrap();
+        }
+        writer
+            .write_event(
+                start + ChronoDuration::milliseconds(30),
+                "Robot program starting",
+            )
+            .unwrap();
+        writer.write_event(start, "héllo").unwrap();
+        let (dslog, dsevents) = writer.into_inner().unwrap();
+
+        let reader = DsLogReader::new(&dslog[..]).unwrap();
+        assert_eq!(reader.start(), start);
+        let read = reader.records().collect::<Result<Vec<_>>>().unwrap();
+        assert_eq!(read.len(), 2);
+        assert_eq!(read[1].0, start + ChronoDuration::milliseconds(20));
+        let (written, read) = (&records[1], &read[1].1);
+        assert_eq!(read.trip_time, written.trip_time);
+        assert!((read.packet_loss - written.packet_loss).abs() < 0.02);
+        assert_eq!(
+            (read.battery_voltage, read.cpu, read.can_utilization),
+            (12.25, 0.5, 0.125)
+        );
+        assert_eq!((read.wifi_db, read.bandwidth_mb), (40.5, 2.5));
+        assert_eq!(read.pdp, written.pdp);
+        assert!(read.brownout && read.ds_teleop && read.robot_teleop);
+        assert!(!read.watchdog && !read.ds_disabled && !read.robot_auto && !read.robot_disabled);
+
+        let events = DsLogReader::new(&dsevents[..])
+            .unwrap()
+            .events()
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(
+            events,
+            vec![
+                LogEvent {
+                    timestamp: start + ChronoDuration::milliseconds(30),
+                    text: "Robot program starting".to_string(),
+                },
+                LogEvent {
+                    timestamp: start,
+                    text: "héllo".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_bad_logs() {
+        assert!(DsLogReader::new(&[0, 0, 0, 3][..]).is_err());
+
+        let mut header = vec![0, 0, 0, 4];
+        header.extend(&[0; 16]);
+
+        // A record with an unknown power distribution type stops the iterator
+        let mut log = header.clone();
+        log.extend(&[0; 13]);
+        log.push(7);
+        log.extend(&[0; 14]);
+        let mut records = DsLogReader::new(&log[..]).unwrap().records();
+        assert!(records.next().unwrap().is_err());
+        assert!(records.next().is_none());
+
+        let mut truncated = header;
+        truncated.extend(&[0; 5]);
+        let mut reader = DsLogReader::new(&truncated[..]).unwrap();
+        assert!(reader.next_record().is_err());
+    }
+}
@@ -0,0 +1,140 @@
+use super::{encode_timestamp, file_stem, DsLogRecord, VERSION};
+use crate::proto::tcp::inbound::TcpPacket;
+use crate::Result;
+
+use byteorder::{BigEndian, WriteBytesExt};
+use chrono::{DateTime, Utc};
+use failure::format_err;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+/// Writes a pair of `.dslog` and `.dsevents` logs
+///
+/// Records carry no timestamp of their own, each is assumed to be 20ms after the previous one, so
+/// [`write_record`](#method.write_record) should be called every 20ms starting at the time given to the constructor.
+pub struct DsLogWriter<W: Write> {
+    dslog: W,
+    dsevents: W,
+}
+
+impl DsLogWriter<BufWriter<File>> {
+    /// Creates a new pair of logs in `dir`, named after `start` the same way the NI driver station names them
+    pub fn create(dir: impl AsRef<Path>, start: DateTime<Utc>) -> Result<Self> {
+        let base = dir.as_ref().join(file_stem(start));
+        let open = |extension: &str| -> Result<BufWriter<File>> {
+            let path = base.with_extension(extension);
+            let file = File::create(&path)
+                .map_err(|e| format_err!("Failed to create log {}: {}", path.display(), e))?;
+            Ok(BufWriter::new(file))
+        };
+
+        DsLogWriter::new(open("dslog")?, open("dsevents")?, start)
+    }
+}
+
+impl<W: Write> DsLogWriter<W> {
+    /// Writes the headers of both logs, marking them as started at `start`
+    pub fn new(mut dslog: W, mut dsevents: W, start: DateTime<Utc>) -> Result<Self> {
+        let mut header = Vec::with_capacity(20);
+        header.write_i32::<BigEndian>(VERSION)?;
+        encode_timestamp(start, &mut header)?;
+        dslog.write_all(&header)?;
+        dsevents.write_all(&header)?;
+
+        Ok(DsLogWriter { dslog, dsevents })
+    }
+
+    /// Appends a record to the `.dslog` file
+    pub fn write_record(&mut self, record: &DsLogRecord) -> Result<()> {
+        self.dslog.write_all(&record.encode())?;
+        Ok(())
+    }
+
+    /// Appends an event with the given text to the `.dsevents` file
+    pub fn write_event(&mut self, timestamp: DateTime<Utc>, text: &str) -> Result<()> {
+        let mut buf = Vec::with_capacity(20 + text.len());
+        encode_timestamp(timestamp, &mut buf)?;
+        buf.write_i32::<BigEndian>(text.len() as i32)?;
+        buf.extend_from_slice(text.as_bytes());
+        self.dsevents.write_all(&buf)?;
+        Ok(())
+    }
+
+    /// Appends the contents of a TCP packet from the roboRIO to the `.dsevents` file, if it is one that gets logged
+    ///
+    /// Robot code output is logged as is, while errors and warnings are tagged the way the NI driver station tags them.
+    pub fn write_tcp(&mut self, timestamp: DateTime<Utc>, packet: &TcpPacket) -> Result<()> {
+        match packet {
+            TcpPacket::Stdout(stdout) => self.write_event(timestamp, &stdout.message),
+            TcpPacket::ErrorMessage(error) => {
+                let text = format!(
+                    "<TagVersion>1 <time> {:.3} <count> {} <flags> {} <Code> {} <details> {} <location> {} <stack> {}",
+                    error.timestamp,
+                    error.occurrences,
+                    error.is_error as u8,
+                    error.code,
+                    error.details,
+                    error.location,
+                    error.call_stack
+                );
+                self.write_event(timestamp, &text)
+            }
+            TcpPacket::Dummy => Ok(()),
+        }
+    }
+
+    /// Flushes both logs to the underlying writers
+    pub fn flush(&mut self) -> Result<()> {
+        self.dslog.flush()?;
+        self.dsevents.flush()?;
+        Ok(())
+    }
+
+    /// Flushes and returns the underlying `.dslog` and `.dsevents` writers
+    pub fn into_inner(mut self) -> Result<(W, W)> {
+        self.flush()?;
+        Ok((self.dslog, self.dsevents))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Stdout;
+    use chrono::TimeZone;
+
+    #[test]
+    fn writes_logs() {
+        let start = Utc.timestamp_opt(1_578_128_400, 0).unwrap();
+        let mut writer = DsLogWriter::new(Vec::new(), Vec::new(), start).unwrap();
+        writer.write_record(&DsLogRecord::default()).unwrap();
+        writer.write_record(&DsLogRecord::default()).unwrap();
+        let stdout = TcpPacket::Stdout(Stdout {
+            timestamp: 1.0,
+            message: "Robot program starting".to_string(),
+            seqnum: 0,
+            raw: b"Robot program starting".to_vec(),
+        });
+        writer
+            .write_tcp(start + chrono::Duration::seconds(1), &stdout)
+            .unwrap();
+        writer.write_tcp(start, &TcpPacket::Dummy).unwrap();
+        let (dslog, dsevents) = writer.into_inner().unwrap();
+
+        let mut header = vec![0, 0, 0, 4];
+        header.extend(&(1_578_128_400i64 + 2_082_844_800).to_be_bytes());
+        header.extend(&[0; 8]);
+        assert_eq!(&dslog[..20], &header[..]);
+        assert_eq!(dslog.len(), 20 + 2 * 14);
+        assert_eq!(&dslog[20..34], &DsLogRecord::default().encode()[..]);
+
+        assert_eq!(&dsevents[..20], &header[..]);
+        assert_eq!(
+            &dsevents[20..28],
+            &(1_578_128_401i64 + 2_082_844_800).to_be_bytes()
+        );
+        assert_eq!(&dsevents[36..40], &[0, 0, 0, 22]);
+        assert_eq!(&dsevents[40..], b"Robot program starting");
+    }
+}
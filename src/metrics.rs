@@ -0,0 +1,236 @@
+//! Exports robot telemetry in the Prometheus text format
+//!
+//! [`Metrics`](struct.Metrics.html) is a snapshot of the link and of what the roboRIO reports, which formats itself as
+//! the text that Prometheus scrapes. With the `metrics` feature,
+//! [`AsyncDriverStation::serve_metrics`](../struct.AsyncDriverStation.html#method.serve_metrics) and
+//! [`DriverStation::serve_metrics`](../struct.DriverStation.html#method.serve_metrics) serve it at `/metrics`, so that
+//! battery sag and CAN load can be graphed over long runs.
+//!
+//! Values the roboRIO hasn't reported, and everything it reports while it is disconnected, are left out rather than
+//! exported as zero, so graphs show gaps instead of false readings.
+
+#[cfg(feature = "metrics")]
+mod server;
+
+#[cfg(feature = "metrics")]
+pub use self::server::MetricsServer;
+
+use crate::ds::state::DsState;
+use crate::{CanMetrics, CpuInfo, RamInfo};
+
+use std::fmt;
+use std::time::Duration;
+
+/// The content type of the Prometheus text format
+pub const CONTENT_TYPE_TEXT: &str = "text/plain; version=0.0.4";
+
+/// A snapshot of robot telemetry
+#[derive(Debug, Clone, PartialEq)]
+pub struct Metrics {
+    /// Whether packets are being received from the roboRIO
+    pub connected: bool,
+    pub enabled: bool,
+    pub battery_voltage: Option<f32>,
+    pub browning_out: bool,
+    /// Number of times the robot has started browning out since the driver station started
+    pub brownouts: u64,
+    /// CPU utilization of the roboRIO, between 0 and 1
+    pub cpu: Option<f32>,
+    pub ram: Option<RamInfo>,
+    pub can: Option<CanMetrics>,
+    /// Fraction of control packets that went unanswered
+    pub packet_loss: f32,
+    /// Round trip time of the UDP link
+    pub trip_time: Option<Duration>,
+}
+
+impl Metrics {
+    pub(crate) async fn read(state: &DsState) -> Metrics {
+        let send = state.send().lock().await;
+        let recv = state.recv().lock().await;
+        let connected = recv.connected();
+
+        Metrics {
+            connected,
+            enabled: send.enabled(),
+            battery_voltage: Some(recv.battery_voltage()).filter(|_| connected),
+            browning_out: connected && recv.status().is_browning_out(),
+            brownouts: recv.brownouts(),
+            cpu: recv.cpu().map(CpuInfo::utilization).filter(|_| connected),
+            ram: recv.ram().copied().filter(|_| connected),
+            can: recv.can().copied().filter(|_| connected),
+            packet_loss: recv.link().packet_loss(state.clock().instant()),
+            trip_time: recv.link().trip_time().filter(|_| connected),
+        }
+    }
+}
+
+/// Writes the HELP and TYPE lines of a metric, followed by its value
+fn write_metric(
+    f: &mut fmt::Formatter,
+    name: &str,
+    kind: &str,
+    help: &str,
+    value: impl fmt::Display,
+) -> fmt::Result {
+    writeln!(f, "# HELP ds_{} {}", name, help)?;
+    writeln!(f, "# TYPE ds_{} {}", name, kind)?;
+    writeln!(f, "ds_{} {}", name, value)
+}
+
+fn flag(value: bool) -> u8 {
+    value as u8
+}
+
+impl fmt::Display for Metrics {
+    /// Formats the metrics in the Prometheus text format, with every name prefixed with `ds_`
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write_metric(
+            f,
+            "robot_connected",
+            "gauge",
+            "Whether packets are being received from the roboRIO",
+            flag(self.connected),
+        )?;
+        write_metric(
+            f,
+            "robot_enabled",
+            "gauge",
+            "Whether the robot is commanded to be enabled",
+            flag(self.enabled),
+        )?;
+        if let Some(voltage) = self.battery_voltage {
+            write_metric(
+                f,
+                "battery_voltage_volts",
+                "gauge",
+                "Battery voltage reported by the roboRIO",
+                voltage,
+            )?;
+        }
+        write_metric(
+            f,
+            "browning_out",
+            "gauge",
+            "Whether the roboRIO is browning out",
+            flag(self.browning_out),
+        )?;
+        write_metric(
+            f,
+            "brownouts_total",
+            "counter",
+            "Number of brownouts since the driver station started",
+            self.brownouts,
+        )?;
+        if let Some(cpu) = self.cpu {
+            write_metric(
+                f,
+                "cpu_utilization_ratio",
+                "gauge",
+                "CPU utilization of the roboRIO",
+                cpu,
+            )?;
+        }
+        if let Some(ram) = self.ram {
+            write_metric(
+                f,
+                "ram_free_bytes",
+                "gauge",
+                "Free memory on the roboRIO",
+                ram.free,
+            )?;
+            write_metric(
+                f,
+                "ram_largest_free_block_bytes",
+                "gauge",
+                "Largest free block of memory on the roboRIO",
+                ram.block,
+            )?;
+        }
+        if let Some(can) = self.can {
+            write_metric(
+                f,
+                "can_utilization_ratio",
+                "gauge",
+                "Utilization of the CAN bus",
+                can.utilization,
+            )?;
+            write_metric(
+                f,
+                "can_bus_off",
+                "gauge",
+                "CAN bus off count reported by the roboRIO",
+                can.bus_off,
+            )?;
+            write_metric(
+                f,
+                "can_tx_full",
+                "gauge",
+                "CAN transmit buffer full count reported by the roboRIO",
+                can.tx_full,
+            )?;
+            write_metric(
+                f,
+                "can_rx_errors",
+                "gauge",
+                "CAN receive error count reported by the roboRIO",
+                can.rx_errors,
+            )?;
+            write_metric(
+                f,
+                "can_tx_errors",
+                "gauge",
+                "CAN transmit error count reported by the roboRIO",
+                can.tx_errors,
+            )?;
+        }
+        write_metric(
+            f,
+            "packet_loss_ratio",
+            "gauge",
+            "Fraction of control packets that went unanswered",
+            self.packet_loss,
+        )?;
+        if let Some(trip_time) = self.trip_time {
+            write_metric(
+                f,
+                "trip_time_seconds",
+                "gauge",
+                "Round trip time of the UDP link",
+                trip_time.as_secs_f64(),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn formats_text() {
+        let metrics = Metrics {
+            connected: true,
+            enabled: false,
+            battery_voltage: Some(12.5),
+            browning_out: false,
+            brownouts: 2,
+            cpu: None,
+            ram: Some(RamInfo {
+                block: 1024,
+                free: 4096,
+            }),
+            can: None,
+            packet_loss: 0.25,
+            trip_time: None,
+        };
+        let text = metrics.to_string();
+        assert!(text.contains("# TYPE ds_brownouts_total counter\nds_brownouts_total 2\n"));
+        assert!(text.contains("\nds_battery_voltage_volts 12.5\n"));
+        assert!(text.contains("\nds_ram_free_bytes 4096\n"));
+        assert!(text.contains("\nds_packet_loss_ratio 0.25\n"));
+        assert!(!text.contains("cpu"));
+        assert!(!text.contains("trip_time"));
+    }
+}
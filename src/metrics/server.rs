@@ -0,0 +1,153 @@
+use super::{Metrics, CONTENT_TYPE_TEXT};
+use crate::ds::state::DsState;
+use crate::Result;
+
+use failure::format_err;
+use hyper::header::CONTENT_TYPE;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use log::*;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::{Arc, Weak};
+use tokio::runtime::Handle;
+use tokio::sync::oneshot;
+
+/// Handle to a metrics endpoint serving in the background
+///
+/// The endpoint stops when [`stop`](#method.stop) is called or this handle is dropped. Once the driver station has
+/// been dropped, requests are answered with 503 Service Unavailable.
+pub struct MetricsServer {
+    addr: SocketAddr,
+    stop: Option<oneshot::Sender<()>>,
+}
+
+impl MetricsServer {
+    pub(crate) fn start(
+        handle: &Handle,
+        state: Weak<DsState>,
+        addr: SocketAddr,
+    ) -> Result<MetricsServer> {
+        let service = make_service_fn(move |_| {
+            let state = state.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request| {
+                    let state = state.clone();
+                    async move { Ok::<_, Infallible>(handle_request(state.upgrade(), request).await) }
+                }))
+            }
+        });
+
+        // Binding registers the listener with the runtime's reactor
+        let server = handle
+            .enter(|| Server::try_bind(&addr))
+            .map_err(|e| format_err!("Failed to listen on {}: {}", addr, e))?
+            .serve(service);
+        let addr = server.local_addr();
+        info!("Serving metrics on {}", addr);
+
+        let (stop_tx, stop_rx) = oneshot::channel();
+        handle.spawn(async move {
+            let server = server.with_graceful_shutdown(async {
+                let _ = stop_rx.await;
+            });
+            if let Err(e) = server.await {
+                error!("Metrics server failed: {}", e);
+            }
+        });
+
+        Ok(MetricsServer {
+            addr,
+            stop: Some(stop_tx),
+        })
+    }
+
+    /// Returns the address the endpoint is listening on, such as to find the port chosen when binding to port 0
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Stops serving metrics
+    pub fn stop(&mut self) {
+        if let Some(stop) = self.stop.take() {
+            let _ = stop.send(());
+        }
+    }
+}
+
+impl Drop for MetricsServer {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+async fn handle_request(state: Option<Arc<DsState>>, request: Request<Body>) -> Response<Body> {
+    let state = match state {
+        Some(state) => state,
+        None => {
+            return Response::builder()
+                .status(StatusCode::SERVICE_UNAVAILABLE)
+                .body(Body::from("The driver station has shut down\n"))
+                .unwrap()
+        }
+    };
+
+    match (request.method(), request.uri().path()) {
+        (&Method::GET, "/metrics") => Response::builder()
+            .header(CONTENT_TYPE, CONTENT_TYPE_TEXT)
+            .body(Body::from(Metrics::read(&state).await.to_string()))
+            .unwrap(),
+        _ => Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .body(Body::from("Metrics are served at /metrics\n"))
+            .unwrap(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::{MockResponse, MockRoborio};
+    use crate::{RamInfo, RobotTag, Status};
+    use hyper::Client;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn serves_metrics() {
+        let mock = MockRoborio::bind_any().await.unwrap();
+        mock.set_response(MockResponse {
+            status: Status::BROWNOUT,
+            tags: vec![RobotTag::Ram(RamInfo {
+                block: 1024,
+                free: 4096,
+            })],
+            ..MockResponse::default()
+        });
+        let mut ds = mock.connected_ds().build_async().unwrap();
+
+        while ds.metrics().await.ram.is_none() {
+            tokio::time::delay_for(Duration::from_millis(10)).await;
+        }
+        mock.update_response(|response| response.status = Status::empty());
+        while ds.metrics().await.browning_out {
+            tokio::time::delay_for(Duration::from_millis(10)).await;
+        }
+        mock.update_response(|response| response.status = Status::BROWNOUT);
+        while ds.metrics().await.brownouts < 2 {
+            tokio::time::delay_for(Duration::from_millis(10)).await;
+        }
+
+        let server = ds.serve_metrics("127.0.0.1:0".parse().unwrap()).unwrap();
+        let url = format!("http://{}/metrics", server.local_addr());
+        let response = Client::new().get(url.parse().unwrap()).await.unwrap();
+        assert_eq!(response.headers()[CONTENT_TYPE], CONTENT_TYPE_TEXT);
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let text = String::from_utf8(body.to_vec()).unwrap();
+        assert!(text.contains("\nds_robot_connected 1\n"));
+        assert!(text.contains("\nds_brownouts_total 2\n"));
+        assert!(text.contains("\nds_ram_free_bytes 4096\n"));
+
+        drop(server);
+        ds.shutdown(Duration::from_secs(1)).await.unwrap();
+    }
+}
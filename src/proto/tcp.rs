@@ -1,9 +1,11 @@
-// use log::*;
+use log::*;
 
 use crate::ext::BufExt;
 use crate::proto::tcp::outbound::{OutgoingTcpTag, TcpTag};
-use crate::{Stdout, TcpPacket};
+use crate::{ErrorMessage, Stdout, TcpPacket};
+use byteorder::{BigEndian, ByteOrder};
 use bytes::{Buf, BytesMut};
+use failure::bail;
 use std::io;
 use tokio_util::codec::{Decoder, Encoder};
 
@@ -33,11 +35,16 @@ impl Decoder for DsTcpCodec {
     type Item = TcpPacket;
     type Error = failure::Error;
 
+    /// Decodes the next frame from `src`
+    ///
+    /// Frames that fail to decode are logged and skipped, every frame is length prefixed so the next one can always
+    /// be found.
     fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
-        let mut buf = src.clone().freeze();
-
         fn inner(buf: &mut impl Buf) -> crate::Result<(TcpPacket, usize)> {
             let len = buf.read_u16_be()?;
+            if len == 0 {
+                bail!("Empty TCP frame");
+            }
 
             let id = buf.read_u8()?;
 
@@ -47,6 +54,21 @@ impl Decoder for DsTcpCodec {
                     TcpPacket::Stdout(Stdout::decode(buf, len as usize - 1)?),
                     len as usize + 2,
                 )),
+                0x0b => {
+                    if buf.remaining() < len as usize - 1 {
+                        return Err(io::Error::new(
+                            io::ErrorKind::UnexpectedEof,
+                            "Not enough data",
+                        )
+                        .into());
+                    }
+                    // Decode from the frame alone, so that a malformed message can't read into the next one
+                    let message = ErrorMessage::decode(&mut bytes::buf::BufExt::take(
+                        &mut *buf,
+                        len as usize - 1,
+                    ))?;
+                    Ok((TcpPacket::ErrorMessage(message), len as usize + 2))
+                }
                 _ => {
                     for _ in 0..(len - 1) {
                         let _ = buf.read_u8()?;
@@ -56,22 +78,90 @@ impl Decoder for DsTcpCodec {
             }
         }
 
-        use failure::bail;
-        match inner(&mut buf) {
-            Ok((packet, n)) => {
-                src.advance(n);
-                Ok(Some(packet))
-            }
-            Err(e) => match e.downcast::<io::Error>() {
-                Ok(e) => {
-                    if e.kind() == io::ErrorKind::UnexpectedEof {
-                        Ok(None)
-                    } else {
-                        Err(e.into())
-                    }
+        loop {
+            let mut buf = src.clone().freeze();
+            let err = match inner(&mut buf) {
+                Ok((packet, n)) => {
+                    src.advance(n);
+                    return Ok(Some(packet));
                 }
-                _ => bail!("Failed to decode TCP packet"),
-            },
+                Err(e) => e,
+            };
+
+            // Running out of data only means the frame hasn't been fully received yet, unless all of it is here
+            let frame_len = match src.get(..2) {
+                Some(prefix) => BigEndian::read_u16(prefix) as usize + 2,
+                None => return Ok(None),
+            };
+            if src.len() < frame_len {
+                return Ok(None);
+            }
+
+            warn!(
+                "Skipping malformed TCP frame {}: {}",
+                hex::encode(&src[..frame_len]),
+                err
+            );
+            src.advance(frame_len);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn frame(id: u8, data: &[u8]) -> Vec<u8> {
+        let mut frame = (data.len() as u16 + 1).to_be_bytes().to_vec();
+        frame.push(id);
+        frame.extend_from_slice(data);
+        frame
+    }
+
+    fn stdout(seqnum: u16, message: &[u8]) -> Vec<u8> {
+        let mut data = 1.5f32.to_be_bytes().to_vec();
+        data.extend(&seqnum.to_be_bytes());
+        data.extend_from_slice(message);
+        frame(0x0c, &data)
+    }
+
+    #[test]
+    fn decodes_invalid_utf8_lossily() {
+        let mut src = BytesMut::from(&stdout(3, b"caf\xe9")[..]);
+        match DsTcpCodec.decode(&mut src).unwrap() {
+            Some(TcpPacket::Stdout(stdout)) => {
+                assert_eq!(stdout.message, "caf\u{fffd}");
+                assert_eq!(stdout.raw, b"caf\xe9");
+                assert_eq!(stdout.seqnum, 3);
+            }
+            other => panic!("Expected stdout, got {:?}", other),
+        }
+        assert!(src.is_empty());
+    }
+
+    #[test]
+    fn skips_malformed_frames() {
+        let mut bytes = Vec::new();
+        // Too short to hold a stdout header
+        bytes.extend(frame(0x0c, &[0, 0]));
+        // An error message whose details claim to be longer than the frame
+        bytes.extend(frame(
+            0x0b,
+            &[0, 0, 0, 0, 0, 1, 0, 1, 0, 0, 0, 0, 1, 0xff, 0xff],
+        ));
+        // Empty frame
+        bytes.extend(&[0, 0]);
+        bytes.extend(stdout(1, b"still here"));
+        // A partial frame is left for later
+        let partial = stdout(2, b"not yet");
+        bytes.extend(&partial[..5]);
+
+        let mut src = BytesMut::from(&bytes[..]);
+        match DsTcpCodec.decode(&mut src).unwrap() {
+            Some(TcpPacket::Stdout(stdout)) => assert_eq!(stdout.message, "still here"),
+            other => panic!("Expected stdout, got {:?}", other),
         }
+        assert!(DsTcpCodec.decode(&mut src).unwrap().is_none());
+        assert_eq!(&src[..], &partial[..5]);
     }
 }
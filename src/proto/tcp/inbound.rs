@@ -1,44 +1,89 @@
 use crate::ext::BufExt;
 use crate::Result as CResult;
 use bytes::Buf;
+use failure::bail;
 use std::io::{Error, ErrorKind};
-use std::str;
 
 /// Enum containing possible incoming TCP packets from the roboRIO
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum TcpPacket {
     /// Contains a message from the robot code's standard output
     Stdout(Stdout),
+    /// Contains an error or warning reported by the robot code, such as through `DriverStation.reportError`
+    ErrorMessage(ErrorMessage),
     Dummy,
 }
 
-pub(crate) trait IncomingTcpPacket: Sized {
-    fn decode(buf: &mut impl Buf) -> CResult<Self>;
-}
-
 /// Contains data outputted to standard output from robot code. Can be consumed by API users to
 /// display code logs
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Stdout {
     pub timestamp: f32,
+    /// The printed text. Invalid UTF-8 is replaced with U+FFFD, see `raw` for the bytes as they were received
     pub message: String,
     pub seqnum: u16,
+    /// The printed bytes, exactly as sent by the roboRIO
+    pub raw: Vec<u8>,
 }
 
 impl Stdout {
     pub fn decode(buf: &mut impl Buf, len: usize) -> CResult<Self> {
+        if len < 6 {
+            bail!("Stdout frame of {} bytes is too short", len);
+        }
         let timestamp = buf.read_f32_be()?;
         let seqnum = buf.read_u16_be()?;
-        let mut v = vec![0; len - 6];
-        if buf.remaining() < v.len() {
+        let mut raw = vec![0; len - 6];
+        if buf.remaining() < raw.len() {
             return Err(Error::new(ErrorKind::UnexpectedEof, "Not enough data").into());
         }
-        buf.copy_to_slice(&mut v[..]);
-        let message = str::from_utf8(&v[..])?;
+        buf.copy_to_slice(&mut raw[..]);
         Ok(Stdout {
             timestamp,
-            message: message.to_string(),
+            message: String::from_utf8_lossy(&raw).into_owned(),
             seqnum,
+            raw,
+        })
+    }
+}
+
+/// Contains an error or warning reported by robot code
+#[derive(Debug, Clone)]
+pub struct ErrorMessage {
+    pub timestamp: f32,
+    /// Sequence number, shared with stdout messages
+    pub seqnum: u16,
+    /// How many times the error occurred since it was last sent
+    pub occurrences: u16,
+    pub code: i32,
+    /// Whether this is an error rather than a warning
+    pub is_error: bool,
+    pub details: String,
+    pub location: String,
+    pub call_stack: String,
+}
+
+impl ErrorMessage {
+    pub fn decode(buf: &mut impl Buf) -> CResult<Self> {
+        fn read_string(buf: &mut impl Buf) -> CResult<String> {
+            let len = buf.read_u16_be()? as usize;
+            if buf.remaining() < len {
+                return Err(Error::new(ErrorKind::UnexpectedEof, "Not enough data").into());
+            }
+            let mut v = vec![0; len];
+            buf.copy_to_slice(&mut v[..]);
+            Ok(String::from_utf8_lossy(&v).into_owned())
+        }
+
+        Ok(ErrorMessage {
+            timestamp: buf.read_f32_be()?,
+            seqnum: buf.read_u16_be()?,
+            occurrences: buf.read_u16_be()?,
+            code: buf.read_i32_be()?,
+            is_error: buf.read_u8()? & 1 != 0,
+            details: read_string(buf)?,
+            location: read_string(buf)?,
+            call_stack: read_string(buf)?,
         })
     }
 }
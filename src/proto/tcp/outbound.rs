@@ -74,8 +74,51 @@ pub enum MatchType {
     Eliminations = 3,
 }
 
-#[derive(Debug, Clone)]
-pub struct JoystickDesc;
+/// The kind of a joystick, as reported to robot code by `GenericHID.getType()`
+#[repr(u8)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum JoystickType {
+    /// No joystick is plugged in to the port
+    Unknown = 255,
+    XInputUnknown = 0,
+    XInputGamepad = 1,
+    HidJoystick = 20,
+    HidGamepad = 21,
+    HidDriving = 22,
+    HidFlight = 23,
+}
+
+/// Describes the joystick on a port, so that robot code can tell what kind of controller it is
+///
+/// Descriptors are sent whenever the TCP connection is established, see
+/// [`DriverStation::set_joystick_descriptors`](struct.DriverStation.html#method.set_joystick_descriptors).
+#[derive(Debug, Clone, PartialEq)]
+pub struct JoystickDesc {
+    /// The port of the joystick, from 0 to 5
+    pub index: u8,
+    pub is_xbox: bool,
+    pub joystick_type: JoystickType,
+    pub name: String,
+    /// The HID usage of each axis, 0 for X, 1 for Y, 2 for Z, 3 for twist, and 4 for throttle
+    pub axis_types: Vec<u8>,
+    pub button_count: u8,
+    pub pov_count: u8,
+}
+
+impl Default for JoystickDesc {
+    /// A generic gamepad with 6 axes, 10 buttons, and a POV on port 0
+    fn default() -> Self {
+        JoystickDesc {
+            index: 0,
+            is_xbox: false,
+            joystick_type: JoystickType::HidGamepad,
+            name: "PS4".to_string(),
+            axis_types: vec![0, 1, 2, 3, 4, 5],
+            button_count: 10,
+            pov_count: 1,
+        }
+    }
+}
 
 impl OutgoingTcpTag for JoystickDesc {
     fn id(&self) -> u8 {
@@ -84,8 +127,45 @@ impl OutgoingTcpTag for JoystickDesc {
 
     fn data(&self) -> Vec<u8> {
         let mut buf = Vec::new();
-        buf.extend_from_slice(&[0, 0, 21, 3, b'P', b'S', b'4', 6, 0, 1, 2, 3, 4, 5, 10, 1]);
+        buf.push(self.index);
+        buf.push(self.is_xbox as u8);
+        buf.push(self.joystick_type as u8);
+        // Names are cut short rather than overflowing their length byte
+        let name = &self.name.as_bytes()[..self.name.len().min(u8::MAX as usize)];
+        buf.push(name.len() as u8);
+        buf.extend_from_slice(name);
+        buf.push(self.axis_types.len() as u8);
+        buf.extend_from_slice(&self.axis_types);
+        buf.push(self.button_count);
+        buf.push(self.pov_count);
 
         buf
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn encodes_joystick_descriptor() {
+        assert_eq!(
+            JoystickDesc::default().data(),
+            vec![0, 0, 21, 3, b'P', b'S', b'4', 6, 0, 1, 2, 3, 4, 5, 10, 1]
+        );
+
+        let desc = JoystickDesc {
+            index: 2,
+            is_xbox: true,
+            joystick_type: JoystickType::XInputGamepad,
+            name: "Xbox".to_string(),
+            axis_types: vec![0, 1],
+            button_count: 10,
+            pov_count: 1,
+        };
+        assert_eq!(
+            desc.construct(),
+            vec![0, 14, 0x02, 2, 1, 1, 4, b'X', b'b', b'o', b'x', 2, 0, 1, 10, 1]
+        );
+    }
+}
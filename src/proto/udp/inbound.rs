@@ -7,19 +7,41 @@ use self::types::*;
 use crate::ext::BufExt;
 use crate::Result;
 
+use byteorder::{BigEndian, WriteBytesExt};
 use bytes::Buf;
 
 /// Response packet sent by the RIO over UDP every ~20ms.
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct UdpResponsePacket {
     pub seqnum: u16,
     pub status: Status,
     pub trace: Trace,
     pub battery: f32,
     pub need_date: bool,
+    pub tags: Vec<tags::RobotTag>,
 }
 
 impl UdpResponsePacket {
+    /// Encodes this packet as the roboRIO would send it
+    ///
+    /// The battery voltage is sent as a whole number of volts and a number of 256ths, anything finer is truncated.
+    pub fn encode(&self) -> Vec<u8> {
+        let battery = self.battery.clamp(0.0, 255.0 + 255.0 / 256.0);
+        let mut buf = Vec::new();
+        buf.write_u16::<BigEndian>(self.seqnum).unwrap();
+        buf.push(0x01); // comm version
+        buf.push(self.status.bits());
+        buf.push(self.trace.bits());
+        buf.push(battery.trunc() as u8);
+        buf.push((battery.fract() * 256.0) as u8);
+        buf.push(self.need_date as u8);
+        for tag in &self.tags {
+            buf.extend(tag.encode());
+        }
+
+        buf
+    }
+
     /// Attempts to decode a valid response packet from the given buffer
     /// Will return Err() if any of the reads fail.
     pub fn decode(buf: &mut (impl Buf + Clone)) -> Result<(UdpResponsePacket, usize)> {
@@ -29,20 +51,16 @@ impl UdpResponsePacket {
         // debug!("decode: {} {}", text.len() / 2, text);
 
         let res = (|| {
-            let mut len = 0;
             let seqnum = buf.read_u16_be()?;
             step = 1;
-            len += 2;
 
             buf.read_u8()?; // Get rid of comm version
             step = 2;
-            len += 1;
 
-            let status = Status::from_bits(buf.read_u8()?).unwrap();
+            let status = Status::from_bits_truncate(buf.read_u8()?);
             step = 3;
-            let trace = Trace::from_bits(buf.read_u8()?).unwrap();
+            let trace = Trace::from_bits_truncate(buf.read_u8()?);
             step = 4;
-            len += 2;
 
             let battery = {
                 let high = buf.read_u8()?;
@@ -51,62 +69,17 @@ impl UdpResponsePacket {
                 step = 6;
                 f32::from(high) + f32::from(low) / 256f32
             };
-            len += 2;
 
             let need_date = buf.read_u8()? == 1;
             step = 7;
-            len += 1;
-
-            if let Ok(_tag_len) = buf.read_u8() {
-                step += 8;
-                // debug!("tag data {}", tag_len);
-
-                use crate::util::InboundTag;
-                while let Ok(tag_id) = buf.read_u8() {
-                    len += 1;
-                    match tag_id {
-                        0x01 => {
-                            types::tags::JoystickOutput::chomp(buf)?;
-                            len += 8;
-                        }
-                        0x04 => {
-                            types::tags::DiskInfo::chomp(buf)?;
-                            len += 4;
-                        }
-                        0x05 => {
-                            // debug!("chomp CPU {len} {}", hex::encode(buf.clone().bytes()));
-                            types::tags::CPUInfo::chomp(buf)?;
-                            // debug!("chomped");
-                            len += 1 + 4 * 4 * 2; // cpu count plus 4 32-bit words per cpu
-                        }
-                        0x06 => {
-                            types::tags::RAMInfo::chomp(buf)?;
-                            len += 8;
-                        }
-                        0x08 => {
-                            types::tags::PDPLog::chomp(buf)?;
-                            len += 25;
-                        }
-                        0x09 => {
-                            types::tags::Unknown::chomp(buf)?;
-                            len += 9;
-                        }
-                        0x0e => {
-                            types::tags::CANMetrics::chomp(buf)?;
-                            len += 14;
-                        }
-                        _ => {}
-                    }
-
-                    // if !buf.has_remaining() {
-                    //     debug!("no more bytes");
-                    //     break;
-                    // }
-                    // debug!("looping");
-                }
-
-                // debug!("done while loop");
+
+            // Each tag is prefixed with its own length, which includes the id
+            let mut tags = Vec::new();
+            while buf.has_remaining() {
+                tags.push(tags::RobotTag::decode(buf)?);
+                step += 1;
             }
+            let len = before.remaining() - buf.remaining();
 
             Ok((
                 UdpResponsePacket {
@@ -115,40 +88,128 @@ impl UdpResponsePacket {
                     trace,
                     battery,
                     need_date,
+                    tags,
                 },
                 len,
             ))
         })();
 
-        match res {
-            Err(ref err) => {
-                // error!("decode: {:?} in {}", err, hex::encode(""));
-                // 0177 sequence
-                // 01   version (always 1 for now)
-                // 02   status
-                // 31   trace
-                // 0bdc battery 0xb + 0xdc/256 = 11.859V
-                // 00   request date = no
-                // 22   tag length
-                //  05  id, 5=cpu
-                //  02  num cpus
-                //  41bd6a05 cpu0 time critical %
-                //  00000000 cpu0 above normal %
-                //  00000000 cpu0 normal %
-                //  4070c0d2 cpu0 low %
-                //  4150f3d6 cpu1 time critical %
-                //  00000000 cpu1 above normal %
-                //  00000000 cpu1 normal %
-                //  40680005 cpu1 low %
-
-                // 0ac5
-                // 0102310bd700
-                // 220502 41a96d2b0000000000000000405f728841535a860000000000000000405cef45
-                error!("decode: {err:?} at {step} in {}", hex::encode(before.bytes()));
-            }
-            _ => {}
+        if let Err(ref err) = res {
+            // error!("decode: {:?} in {}", err, hex::encode(""));
+            // 0177 sequence
+            // 01   version (always 1 for now)
+            // 02   status
+            // 31   trace
+            // 0bdc battery 0xb + 0xdc/256 = 11.859V
+            // 00   request date = no
+            // 22   tag length
+            //  05  id, 5=cpu
+            //  02  num cpus
+            //  41bd6a05 cpu0 time critical %
+            //  00000000 cpu0 above normal %
+            //  00000000 cpu0 normal %
+            //  4070c0d2 cpu0 low %
+            //  4150f3d6 cpu1 time critical %
+            //  00000000 cpu1 above normal %
+            //  00000000 cpu1 normal %
+            //  40680005 cpu1 low %
+
+            // 0ac5
+            // 0102310bd700
+            // 220502 41a96d2b0000000000000000405f728841535a860000000000000000405cef45
+            error!(
+                "decode: {err:?} at {step} in {}",
+                hex::encode(before.bytes())
+            );
         }
 
         res
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::tags::*;
+    use super::*;
+    use bytes::Bytes;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    fn random_tag(rng: &mut StdRng) -> RobotTag {
+        match rng.gen_range(0, 5) {
+            0 => RobotTag::Cpu(CpuInfo {
+                cores: (0..rng.gen_range(0, 4))
+                    .map(|_| CoreUsage {
+                        time_critical: rng.gen_range(0.0, 100.0),
+                        above_normal: rng.gen_range(0.0, 100.0),
+                        normal: rng.gen_range(0.0, 100.0),
+                        low: rng.gen_range(0.0, 100.0),
+                    })
+                    .collect(),
+            }),
+            1 => RobotTag::Can(CanMetrics {
+                utilization: rng.gen(),
+                bus_off: rng.gen(),
+                tx_full: rng.gen(),
+                rx_errors: rng.gen(),
+                tx_errors: rng.gen(),
+            }),
+            2 => RobotTag::Pdp(PdpLog {
+                data: (0..25).map(|_| rng.gen()).collect(),
+            }),
+            3 => RobotTag::Ram(RamInfo {
+                block: rng.gen(),
+                free: rng.gen(),
+            }),
+            _ => RobotTag::Other {
+                id: rng.gen_range(0x10, 0xff),
+                data: (0..rng.gen_range(0, 16)).map(|_| rng.gen()).collect(),
+            },
+        }
+    }
+
+    #[test]
+    fn decodes_every_tag() {
+        // Captured from a roboRIO: CPU usage of both cores, followed by an empty joystick output tag
+        let mut packet = hex::decode(
+            "0ac501023107d700220502\
+             41a96d2b0000000000000000405f728841535a860000000000000000405cef45",
+        )
+        .unwrap();
+        packet.extend(&[9, 0x01, 0, 0, 0, 0, 0, 0, 0, 0]);
+
+        let (decoded, len) = UdpResponsePacket::decode(&mut Bytes::from(packet.clone())).unwrap();
+        assert_eq!(len, packet.len());
+        assert_eq!(decoded.tags.len(), 2);
+        match decoded.tags[0] {
+            RobotTag::Cpu(ref cpu) => {
+                assert_eq!(cpu.cores.len(), 2);
+                assert!((cpu.utilization() - 0.2067).abs() < 0.0001);
+            }
+            ref other => panic!("Expected CPU info, got {:?}", other),
+        }
+        assert_eq!(decoded.tags[1].id(), 0x01);
+    }
+
+    #[test]
+    fn response_packet_round_trip() {
+        let mut rng = StdRng::seed_from_u64(0x1150);
+        for _ in 0..1000 {
+            let packet = UdpResponsePacket {
+                seqnum: rng.gen(),
+                status: Status::from_bits_truncate(rng.gen()),
+                trace: Trace::from_bits_truncate(rng.gen()),
+                // Only voltages that are a whole number of 256ths survive encoding
+                battery: f32::from(rng.gen::<u8>()) + f32::from(rng.gen::<u8>()) / 256.0,
+                need_date: rng.gen(),
+                tags: (0..rng.gen_range(0, 4))
+                    .map(|_| random_tag(&mut rng))
+                    .collect(),
+            };
+
+            let encoded = packet.encode();
+            let (decoded, _) = UdpResponsePacket::decode(&mut Bytes::from(encoded)).unwrap();
+            assert_eq!(decoded, packet);
+        }
+    }
+}
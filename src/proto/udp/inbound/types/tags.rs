@@ -1,36 +1,193 @@
+//! This module contains the tags that the roboRIO can attach to its status packets
+//! Only the tags needed for logging are decoded, the rest are kept as raw bytes
+
+use byteorder::{BigEndian, WriteBytesExt};
+use bytes::Buf;
+use failure::bail;
+
 use crate::ext::BufExt;
-use crate::util::InboundTag;
 use crate::Result;
-use bytes::Buf;
 
-macro_rules! gen_stub_tags {
-    ($($struct_name:ident : $num_bytes:expr),*) => {
-        $(
-        pub(crate) struct $struct_name {
-            _data: [u8; $num_bytes]
+/// Enum wrapping the tags that can be received from the roboRIO
+#[derive(Debug, Clone, PartialEq)]
+pub enum RobotTag {
+    /// CPU usage of the roboRIO
+    Cpu(CpuInfo),
+    /// Health of the CAN bus
+    Can(CanMetrics),
+    /// Currents and voltage reported by the power distribution panel
+    Pdp(PdpLog),
+    /// Memory usage of the roboRIO
+    Ram(RamInfo),
+    /// Any other tag, such as joystick outputs or disk usage
+    Other { id: u8, data: Vec<u8> },
+}
+
+impl RobotTag {
+    pub fn id(&self) -> u8 {
+        match self {
+            RobotTag::Cpu(_) => 0x05,
+            RobotTag::Can(_) => 0x0e,
+            RobotTag::Pdp(_) => 0x08,
+            RobotTag::Ram(_) => 0x06,
+            RobotTag::Other { id, .. } => *id,
+        }
+    }
+
+    /// Encodes this tag, prefixed with its length as it appears in a status packet
+    pub fn encode(&self) -> Vec<u8> {
+        let data = match self {
+            RobotTag::Cpu(cpu) => cpu.encode(),
+            RobotTag::Can(can) => can.encode(),
+            RobotTag::Pdp(pdp) => pdp.data.clone(),
+            RobotTag::Ram(ram) => ram.encode(),
+            RobotTag::Other { data, .. } => data.clone(),
+        };
+
+        let mut buf = vec![data.len() as u8 + 1, self.id()];
+        buf.extend(data);
+        buf
+    }
+
+    /// Decodes a single length prefixed tag from `buf`
+    pub fn decode(buf: &mut impl Buf) -> Result<RobotTag> {
+        let len = buf.read_u8()? as usize;
+        if len == 0 || buf.remaining() < len {
+            bail!("Tag length {} doesn't fit in the packet", len);
         }
+        let id = buf.read_u8()?;
+        let mut data = vec![0; len - 1];
+        buf.copy_to_slice(&mut data[..]);
+
+        let tag = match id {
+            0x05 => RobotTag::Cpu(CpuInfo::decode(&mut &data[..])?),
+            0x0e => RobotTag::Can(CanMetrics::decode(&mut &data[..])?),
+            0x08 => RobotTag::Pdp(PdpLog { data }),
+            0x06 => RobotTag::Ram(RamInfo::decode(&mut &data[..])?),
+            _ => RobotTag::Other { id, data },
+        };
+        Ok(tag)
+    }
+}
 
-        impl InboundTag for $struct_name {
-            fn chomp(buf: &mut impl Buf) -> Result<Self> {
-                let mut _data = [0; $num_bytes];
+/// Percentage of time a single core spent at each thread priority
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct CoreUsage {
+    pub time_critical: f32,
+    pub above_normal: f32,
+    pub normal: f32,
+    pub low: f32,
+}
 
-                for i in 0..$num_bytes {
-                    _data[i] = buf.read_u8()?;
-                }
+/// Tag containing the CPU usage of the roboRIO
+#[derive(Debug, Clone, PartialEq)]
+pub struct CpuInfo {
+    pub cores: Vec<CoreUsage>,
+}
 
-                Ok($struct_name { _data })
+impl CpuInfo {
+    /// Returns the average usage across all cores, between 0 and 1
+    pub fn utilization(&self) -> f32 {
+        if self.cores.is_empty() {
+            return 0.0;
+        }
+
+        let total: f32 = self
+            .cores
+            .iter()
+            .map(|c| c.time_critical + c.above_normal + c.normal + c.low)
+            .sum();
+        (total / self.cores.len() as f32 / 100.0).clamp(0.0, 1.0)
+    }
+
+    fn decode(buf: &mut impl Buf) -> Result<CpuInfo> {
+        let count = buf.read_u8()?;
+        let mut cores = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            cores.push(CoreUsage {
+                time_critical: buf.read_f32_be()?,
+                above_normal: buf.read_f32_be()?,
+                normal: buf.read_f32_be()?,
+                low: buf.read_f32_be()?,
+            });
+        }
+        Ok(CpuInfo { cores })
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut buf = vec![self.cores.len() as u8];
+        for core in &self.cores {
+            for value in &[core.time_critical, core.above_normal, core.normal, core.low] {
+                buf.write_f32::<BigEndian>(*value).unwrap();
             }
         }
-        )*
+        buf
     }
 }
 
-// UDP tags should be eaten to ensure the pipe doesn't get clogged, but for now proper structs aren't implemented.
-gen_stub_tags!(PDPLog : 25,
-    JoystickOutput : 8,
-    DiskInfo : 8,
-    CPUInfo : 1 + 4 * 4 * 2,
-    RAMInfo : 8,
-    Unknown : 9,
-    CANMetrics : 14
-);
+/// Tag containing the utilization and error counts of the CAN bus
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct CanMetrics {
+    /// Bus utilization, between 0 and 1
+    pub utilization: f32,
+    pub bus_off: u32,
+    pub tx_full: u32,
+    pub rx_errors: u8,
+    pub tx_errors: u8,
+}
+
+impl CanMetrics {
+    fn decode(buf: &mut impl Buf) -> Result<CanMetrics> {
+        Ok(CanMetrics {
+            utilization: buf.read_f32_be()?,
+            bus_off: buf.read_u32_be()?,
+            tx_full: buf.read_u32_be()?,
+            rx_errors: buf.read_u8()?,
+            tx_errors: buf.read_u8()?,
+        })
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(14);
+        buf.write_f32::<BigEndian>(self.utilization).unwrap();
+        buf.write_u32::<BigEndian>(self.bus_off).unwrap();
+        buf.write_u32::<BigEndian>(self.tx_full).unwrap();
+        buf.push(self.rx_errors);
+        buf.push(self.tx_errors);
+        buf
+    }
+}
+
+/// Tag containing the memory usage of the roboRIO
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct RamInfo {
+    /// Size of the largest free block, in bytes
+    pub block: u32,
+    /// Free memory, in bytes
+    pub free: u32,
+}
+
+impl RamInfo {
+    fn decode(buf: &mut impl Buf) -> Result<RamInfo> {
+        Ok(RamInfo {
+            block: buf.read_u32_be()?,
+            free: buf.read_u32_be()?,
+        })
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(8);
+        buf.write_u32::<BigEndian>(self.block).unwrap();
+        buf.write_u32::<BigEndian>(self.free).unwrap();
+        buf
+    }
+}
+
+/// Tag containing the state of a CTRE power distribution panel
+///
+/// The packed channel currents aren't decoded, the contents are copied as is into `.dslog` files, which is what the
+/// NI driver station does.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PdpLog {
+    pub data: Vec<u8>,
+}
@@ -2,15 +2,19 @@ pub mod types;
 
 use self::types::tags::*;
 use self::types::*;
+use crate::ext::BufExt;
+use crate::Result;
 use byteorder::{BigEndian, WriteBytesExt};
+use bytes::Buf;
 
 /// UDP control packet to send to the roboRIO
+#[derive(Debug, Clone, PartialEq)]
 pub struct UdpControlPacket {
     pub(crate) seqnum: u16,
     pub(crate) control: Control,
     pub(crate) request: Option<Request>,
     pub(crate) alliance: Alliance,
-    pub(crate) tags: Vec<Box<dyn Tag>>,
+    pub(crate) tags: Vec<UdpTag>,
 }
 
 impl UdpControlPacket {
@@ -28,9 +32,133 @@ impl UdpControlPacket {
         buf.push(self.alliance.0);
 
         for tag in &self.tags {
-            buf.extend(tag.construct());
+            buf.extend(tag.encode());
         }
 
         buf
     }
+
+    /// Decodes a control packet, as sent by the driver station, from the given buffer
+    ///
+    /// Tags of unknown kinds are skipped. Returns Err if the packet is truncated, or if a known tag is malformed.
+    pub fn decode(buf: &mut impl Buf) -> Result<UdpControlPacket> {
+        let seqnum = buf.read_u16_be()?;
+        buf.read_u8()?; // comm version
+        let control = Control::from_bits_truncate(buf.read_u8()?);
+        let request = Request::from_bits_truncate(buf.read_u8()?);
+        let alliance = Alliance(buf.read_u8()?);
+
+        let mut tags = Vec::new();
+        while buf.has_remaining() {
+            if let Some(tag) = UdpTag::decode(buf)? {
+                tags.push(tag);
+            }
+        }
+
+        Ok(UdpControlPacket {
+            seqnum,
+            control,
+            request: if request.is_empty() {
+                None
+            } else {
+                Some(request)
+            },
+            alliance,
+            tags,
+        })
+    }
+
+    pub fn seqnum(&self) -> u16 {
+        self.seqnum
+    }
+
+    pub fn control(&self) -> Control {
+        self.control
+    }
+
+    pub fn request(&self) -> Option<Request> {
+        self.request
+    }
+
+    pub fn alliance(&self) -> Alliance {
+        self.alliance
+    }
+
+    pub fn tags(&self) -> &[UdpTag] {
+        &self.tags
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    fn random_tag(rng: &mut StdRng) -> UdpTag {
+        match rng.gen_range(0, 4) {
+            0 => UdpTag::Countdown(Countdown::new(rng.gen_range(0.0, 150.0))),
+            1 => {
+                let axes = (0..rng.gen_range(0, 12)).map(|_| rng.gen()).collect();
+                let buttons = (0..rng.gen_range(0, 32)).map(|_| rng.gen()).collect();
+                let povs = (0..rng.gen_range(0, 4)).map(|_| rng.gen()).collect();
+                UdpTag::Joysticks(Joysticks::new(axes, buttons, povs))
+            }
+            2 => UdpTag::DateTime(DateTime::new(
+                rng.gen_range(0, 1_000_000),
+                rng.gen_range(0, 60),
+                rng.gen_range(0, 60),
+                rng.gen_range(0, 24),
+                rng.gen_range(1, 32),
+                rng.gen_range(0, 12),
+                rng.gen(),
+            )),
+            _ => {
+                let len = rng.gen_range(0, 64);
+                let tz: String = (0..len)
+                    .map(|_| rng.gen_range(b'!', b'~') as char)
+                    .collect();
+                UdpTag::Timezone(Timezone::new(&tz))
+            }
+        }
+    }
+
+    #[test]
+    fn control_packet_round_trip() {
+        let mut rng = StdRng::seed_from_u64(0x1110);
+        for _ in 0..1000 {
+            let packet = UdpControlPacket {
+                seqnum: rng.gen(),
+                control: Control::from_bits_truncate(rng.gen()),
+                request: Some(Request::from_bits_truncate(rng.gen())).filter(|r| !r.is_empty()),
+                alliance: Alliance(rng.gen_range(0, 6)),
+                tags: (0..rng.gen_range(0, 5))
+                    .map(|_| random_tag(&mut rng))
+                    .collect(),
+            };
+
+            let encoded = packet.encode();
+            let decoded = UdpControlPacket::decode(&mut &encoded[..]).unwrap();
+            assert_eq!(decoded, packet);
+        }
+    }
+
+    #[test]
+    fn control_packet_skips_unknown_tags() {
+        let mut encoded = vec![0, 1, 1, 0, 0, 0];
+        encoded.extend(&[3, 0x42, 0xde, 0xad]);
+        encoded.extend(UdpTag::Countdown(Countdown::new(2.0)).encode());
+
+        let decoded = UdpControlPacket::decode(&mut &encoded[..]).unwrap();
+        assert_eq!(decoded.tags(), &[UdpTag::Countdown(Countdown::new(2.0))]);
+    }
+
+    #[test]
+    fn control_packet_rejects_truncated_tags() {
+        let mut encoded = vec![0, 1, 1, 0, 0, 0];
+        encoded.extend(UdpTag::Timezone(Timezone::new("UTC")).encode());
+        encoded.pop();
+
+        assert!(UdpControlPacket::decode(&mut &encoded[..]).is_err());
+    }
 }
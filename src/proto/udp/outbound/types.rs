@@ -23,7 +23,7 @@ bitflags! {
 }
 
 /// Struct abstracting the byte value for alliance colour and position
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub struct Alliance(pub u8);
 
 impl Alliance {
@@ -2,11 +2,17 @@
 //! The `Tag` trait contains the core logic, and is inherited by structs with specific roles
 
 use byteorder::{BigEndian, WriteBytesExt};
+use bytes::Buf;
+use chrono::{Datelike, Timelike, Utc};
+use failure::bail;
+use std::convert::TryFrom;
 
-use crate::util::to_u8_vec;
+use crate::ext::BufExt;
+use crate::util::{from_u8_vec, to_u8_vec};
+use crate::Result;
 
 /// Enum wrapping possible outgoing UDP tags
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum UdpTag {
     /// Tag sent to inform user code of the time left in the current mode
     Countdown(Countdown),
@@ -18,6 +24,48 @@ pub enum UdpTag {
     Timezone(Timezone),
 }
 
+impl UdpTag {
+    /// Encodes this tag, prefixed with its length as it appears in a control packet
+    pub fn encode(&self) -> Vec<u8> {
+        match self {
+            UdpTag::Countdown(tag) => tag.construct(),
+            UdpTag::Joysticks(tag) => tag.construct(),
+            UdpTag::DateTime(tag) => tag.construct(),
+            UdpTag::Timezone(tag) => tag.construct(),
+        }
+    }
+
+    /// Decodes a single length prefixed tag from `buf`
+    ///
+    /// Returns Ok(None) if the tag is well formed but of a kind that isn't known, the tag is skipped in that case.
+    pub fn decode(buf: &mut impl Buf) -> Result<Option<UdpTag>> {
+        let len = buf.read_u8()? as usize;
+        if len == 0 || buf.remaining() < len {
+            bail!("Tag length {} doesn't fit in the packet", len);
+        }
+        let id = buf.read_u8()?;
+        let mut body = vec![0; len - 1];
+        buf.copy_to_slice(&mut body[..]);
+        let mut data = &body[..];
+        let tag = match id {
+            0x07 => Some(UdpTag::Countdown(Countdown::decode(&mut data)?)),
+            0x0c => Some(UdpTag::Joysticks(Joysticks::decode(&mut data)?)),
+            0x0f => Some(UdpTag::DateTime(DateTime::decode(&mut data)?)),
+            0x10 => Some(UdpTag::Timezone(Timezone::decode(&mut data)?)),
+            _ => None,
+        };
+        if tag.is_some() && data.has_remaining() {
+            bail!(
+                "Tag 0x{:02x} has {} unexpected trailing bytes",
+                id,
+                data.len()
+            );
+        }
+
+        Ok(tag)
+    }
+}
+
 /// Represents an outgoing UDP tag
 pub(crate) trait Tag: Send {
     fn id(&self) -> usize;
@@ -35,7 +83,7 @@ pub(crate) trait Tag: Send {
 }
 
 /// Tag containing the time remaining in the current mode
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct Countdown {
     seconds_remaining: f32,
 }
@@ -46,6 +94,15 @@ impl Countdown {
             seconds_remaining: seconds,
         }
     }
+
+    /// Decodes the body of a countdown tag
+    pub fn decode(buf: &mut impl Buf) -> Result<Countdown> {
+        Ok(Countdown::new(buf.read_f32_be()?))
+    }
+
+    pub fn seconds_remaining(&self) -> f32 {
+        self.seconds_remaining
+    }
 }
 
 impl Tag for Countdown {
@@ -61,8 +118,11 @@ impl Tag for Countdown {
     }
 }
 
+/// The most buttons a joystick can send to robot code, numbered from 1
+pub const MAX_BUTTONS: u8 = 32;
+
 /// Tag containing values from joysticks
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Joysticks {
     axes: Vec<i8>,
     buttons: Vec<bool>,
@@ -77,6 +137,46 @@ impl Joysticks {
             povs,
         }
     }
+
+    /// Decodes the body of a joystick tag
+    pub fn decode(buf: &mut impl Buf) -> Result<Joysticks> {
+        let axis_count = buf.read_u8()?;
+        let mut axes = Vec::with_capacity(axis_count as usize);
+        for _ in 0..axis_count {
+            axes.push(buf.read_i8()?);
+        }
+
+        let button_count = buf.read_u8()? as usize;
+        let mut button_bytes = vec![0; button_count.div_ceil(8)];
+        for byte in button_bytes.iter_mut() {
+            *byte = buf.read_u8()?;
+        }
+        let buttons = from_u8_vec(&button_bytes, button_count);
+
+        let pov_count = buf.read_u8()?;
+        let mut povs = Vec::with_capacity(pov_count as usize);
+        for _ in 0..pov_count {
+            povs.push(buf.read_i16_be()?);
+        }
+
+        Ok(Joysticks {
+            axes,
+            buttons,
+            povs,
+        })
+    }
+
+    pub fn axes(&self) -> &[i8] {
+        &self.axes
+    }
+
+    pub fn buttons(&self) -> &[bool] {
+        &self.buttons
+    }
+
+    pub fn povs(&self) -> &[i16] {
+        &self.povs
+    }
 }
 
 impl Tag for Joysticks {
@@ -93,7 +193,7 @@ impl Tag for Joysticks {
 
         let buttons = to_u8_vec(&self.buttons);
 
-        buf.push(10);
+        buf.push(self.buttons.len() as u8);
         buf.extend(buttons);
 
         buf.push(self.povs.len() as u8);
@@ -107,7 +207,7 @@ impl Tag for Joysticks {
 }
 
 /// Tag containing the current date and time in UTC
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct DateTime {
     micros: u32,
     second: u8,
@@ -138,6 +238,38 @@ impl DateTime {
             year,
         }
     }
+
+    /// Creates a tag for the given instant, in the format expected by the roboRIO
+    ///
+    /// The month is zero based, and the year is counted from 1900. Years that don't fit are clamped, and leap seconds
+    /// are folded into the last microsecond of the preceding second.
+    pub fn from_utc(now: &chrono::DateTime<Utc>) -> DateTime {
+        let micros = now.timestamp_subsec_micros().min(999_999);
+        let year = u8::try_from((now.year() - 1900).max(0)).unwrap_or(u8::MAX);
+
+        DateTime {
+            micros,
+            second: now.second() as u8,
+            minute: now.minute() as u8,
+            hour: now.hour() as u8,
+            day: now.day() as u8,
+            month: now.month0() as u8,
+            year,
+        }
+    }
+
+    /// Decodes the body of a date tag
+    pub fn decode(buf: &mut impl Buf) -> Result<DateTime> {
+        Ok(DateTime {
+            micros: buf.read_u32_be()?,
+            second: buf.read_u8()?,
+            minute: buf.read_u8()?,
+            hour: buf.read_u8()?,
+            day: buf.read_u8()?,
+            month: buf.read_u8()?,
+            year: buf.read_u8()?,
+        })
+    }
 }
 
 impl Tag for DateTime {
@@ -160,7 +292,7 @@ impl Tag for DateTime {
 }
 
 /// Tag containing the current timezone of the RIO
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Timezone {
     tz: String,
 }
@@ -169,6 +301,19 @@ impl Timezone {
     pub fn new(tz: &str) -> Timezone {
         Timezone { tz: tz.to_string() }
     }
+
+    /// Decodes the body of a timezone tag, which takes up the rest of `buf`
+    pub fn decode(buf: &mut impl Buf) -> Result<Timezone> {
+        let mut data = vec![0; buf.remaining()];
+        buf.copy_to_slice(&mut data[..]);
+        Ok(Timezone {
+            tz: String::from_utf8(data)?,
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.tz
+    }
 }
 
 impl Tag for Timezone {
@@ -187,6 +332,11 @@ impl Tag for Timezone {
 #[cfg(test)]
 mod test {
     use super::*;
+    use chrono::{NaiveDate, TimeZone};
+
+    fn utc(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
 
     #[test]
     fn verify_format() {
@@ -197,4 +347,29 @@ mod test {
 
         assert_eq!(buf, &[0x05, 0x07, 0x040, 0x0, 0x0, 0x0]);
     }
+
+    #[test]
+    fn date_time_from_utc() {
+        let now = utc(2020, 1, 31)
+            .and_hms_micro_opt(13, 4, 5, 250_000)
+            .unwrap();
+        let now = Utc.from_utc_datetime(&now);
+        let tag = DateTime::from_utc(&now);
+        assert_eq!(
+            tag.construct(),
+            &[0x0b, 0x0f, 0x00, 0x03, 0xd0, 0x90, 5, 4, 13, 31, 0, 120]
+        );
+
+        // Leap seconds are represented by chrono as an extra second's worth of nanoseconds
+        let leap = utc(2016, 12, 31)
+            .and_hms_micro_opt(23, 59, 59, 1_500_000)
+            .unwrap();
+        let leap = Utc.from_utc_datetime(&leap);
+        let tag = DateTime::from_utc(&leap);
+        assert_eq!(tag.micros, 999_999);
+        assert_eq!(tag.second, 59);
+
+        let far = Utc.from_utc_datetime(&utc(2200, 6, 1).and_hms_opt(0, 0, 0).unwrap());
+        assert_eq!(DateTime::from_utc(&far).year, u8::MAX);
+    }
 }
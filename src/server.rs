@@ -0,0 +1,592 @@
+//! An HTTP and WebSocket API for a driver station, built with the `server` feature
+//!
+//! [`DsServer`](struct.DsServer.html) wraps an [`AsyncDriverStation`](../struct.AsyncDriverStation.html) so that
+//! dashboards in a browser, or written in any other language, can drive a robot without linking this crate. Anyone who
+//! can reach the server can read the robot's status, so it should only listen on addresses the team trusts. Controlling
+//! the robot needs the server's token, sent as `Authorization: Bearer <token>`, or as the percent-encoded `token` query
+//! parameter when opening a WebSocket. Tokens shorter than [`MIN_TOKEN_LEN`](constant.MIN_TOKEN_LEN.html) are refused.
+//!
+//! | Request | Response |
+//! |---|---|
+//! | `GET /status` | A status message, as below |
+//! | `GET /console` | The console messages of robot code received so far, oldest first |
+//! | `GET /metrics` | Robot telemetry in the Prometheus text format, as described in [`metrics`](../metrics/index.html) |
+//! | `POST /enable`, `/disable`, `/estop`, `/restart_code` | Controls the robot |
+//! | `POST /mode` | Changes the mode, with a body such as `{"mode": "auto"}`. Modes are `auto`, `teleop`, and `test` |
+//! | `POST /joysticks` | Replaces the values of every joystick port, as below |
+//! | `GET /ws` | A WebSocket streaming status and console messages |
+//!
+//! Status messages look like `{"type": "status", "connected": true, "enabled": false, "mode": "teleop",
+//! "battery_voltage": 12.5, "trace": {...}, "telemetry": {...}, ...}`, and console messages like `{"type": "console",
+//! "level": "print", "timestamp": 10.2, "text": "..."}`. A WebSocket is sent a status message when it opens, whenever
+//! the status changes, and every second with new telemetry, along with every console message as it arrives.
+//!
+//! WebSockets opened with the token can also send commands, named by their `command` field, such as
+//! `{"command": "enable"}` or `{"command": "mode", "mode": "auto"}`. Joysticks are sent as
+//! `{"command": "joysticks", "joysticks": [[{"axis": 1, "value": -0.5}, {"button": 1, "pressed": true},
+//! {"pov": 0, "angle": 90}]]}`, with a list of values for each of up to six ports. They have to be sent at least every
+//! half second, or they are dropped, so that a dashboard that crashes doesn't keep driving the robot.
+
+mod json;
+
+use self::json::Command;
+use crate::input::SafetyKeys;
+use crate::metrics::CONTENT_TYPE_TEXT;
+use crate::{AsyncDriverStation, ConsoleLevel, JoystickValue, Result, RobotConsole};
+
+use failure::format_err;
+use futures::future::{self, Future};
+use futures::sink::SinkExt;
+use futures::stream::{self, StreamExt};
+use hyper::body::HttpBody;
+use hyper::header::{AUTHORIZATION, CONTENT_LENGTH, CONTENT_TYPE};
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use log::*;
+use percent_encoding::percent_decode_str;
+use serde_json::Value;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex as StdMutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{broadcast, Mutex};
+use tokio_tungstenite::tungstenite::handshake::server::create_response;
+use tokio_tungstenite::tungstenite::protocol::Role;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::WebSocketStream;
+
+/// How long joystick values are sent for after they were last updated
+const JOYSTICK_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// How often WebSockets are sent a status message with new telemetry
+const TELEMETRY_PERIOD: Duration = Duration::from_secs(1);
+
+/// Number of console entries kept for `GET /console`
+const CONSOLE_CAPACITY: usize = 1000;
+
+/// Largest request body accepted, which a few joysticks fit well within
+const MAX_BODY: usize = 64 * 1024;
+
+/// The shortest token a server accepts, so that an empty or guessable one can't authorize control of the robot
+pub const MIN_TOKEN_LEN: usize = 16;
+
+const COMMANDS: [&str; 6] = [
+    "enable",
+    "disable",
+    "estop",
+    "restart_code",
+    "mode",
+    "joysticks",
+];
+
+/// The joystick values last sent by a client
+struct Joysticks {
+    values: Vec<Vec<JoystickValue>>,
+    updated: Option<Instant>,
+}
+
+impl Joysticks {
+    fn current(&self) -> Vec<Vec<JoystickValue>> {
+        match self.updated {
+            Some(updated) if updated.elapsed() < JOYSTICK_TIMEOUT => self.values.clone(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+struct Shared {
+    ds: Mutex<AsyncDriverStation>,
+    token: String,
+    /// Disables and E-stops the robot without waiting on any locks
+    safety: SafetyKeys,
+    console: StdMutex<RobotConsole>,
+    /// Console messages, broadcast to every WebSocket
+    console_tx: broadcast::Sender<Value>,
+    joysticks: Arc<StdMutex<Joysticks>>,
+}
+
+impl Shared {
+    /// Compares `token` with the server's token, taking the same time wherever they differ
+    fn authorized(&self, token: Option<&str>) -> bool {
+        let token = match token {
+            Some(token) => token.as_bytes(),
+            None => return false,
+        };
+        let expected = self.token.as_bytes();
+        token.len() == expected.len()
+            && token
+                .iter()
+                .zip(expected)
+                .fold(0, |diff, (a, b)| diff | (a ^ b))
+                == 0
+    }
+
+    async fn status(&self) -> Value {
+        let ds = self.ds.lock().await;
+        json::status(&ds.latest_status(), &ds.telemetry().await)
+    }
+
+    fn console(&self) -> Value {
+        let console = self.console.lock().unwrap();
+        let messages = console
+            .messages(ConsoleLevel::Print)
+            .map(|message| {
+                serde_json::json!({
+                    "level": format!("{:?}", message.level).to_lowercase(),
+                    "timestamp": message.timestamp,
+                    "text": message.text,
+                    "code": message.code,
+                    "location": message.location,
+                    "call_stack": message.call_stack,
+                })
+            })
+            .collect();
+        Value::Array(messages)
+    }
+
+    async fn run(&self, command: Command) {
+        match command {
+            Command::Enable => self.ds.lock().await.enable().await,
+            Command::Disable => self.safety.disable(),
+            Command::Estop => self.safety.estop(),
+            Command::RestartCode => self.ds.lock().await.restart_code().await,
+            Command::Mode { mode } => self.ds.lock().await.set_mode(mode.into()).await,
+            Command::Joysticks { joysticks } => {
+                let values = joysticks
+                    .into_iter()
+                    .map(|port| port.into_iter().map(JoystickValue::from).collect())
+                    .collect();
+                *self.joysticks.lock().unwrap() = Joysticks {
+                    values,
+                    updated: Some(Instant::now()),
+                };
+            }
+        }
+    }
+}
+
+/// Serves the HTTP and WebSocket API of a driver station, as described in the [module documentation](index.html)
+pub struct DsServer {
+    shared: Arc<Shared>,
+}
+
+impl DsServer {
+    /// Wraps `ds`, whose joystick supplier and TCP consumer are replaced by those of the server
+    ///
+    /// Requests that control the robot have to carry `token`, which should be long and random. Returns an error if it
+    /// is shorter than [`MIN_TOKEN_LEN`](constant.MIN_TOKEN_LEN.html), in which case `ds` is dropped and shuts down.
+    pub async fn new(mut ds: AsyncDriverStation, token: impl Into<String>) -> Result<DsServer> {
+        let token = token.into();
+        if token.chars().count() < MIN_TOKEN_LEN {
+            return Err(format_err!(
+                "The token must be at least {} characters long",
+                MIN_TOKEN_LEN
+            ));
+        }
+
+        let joysticks = Arc::new(StdMutex::new(Joysticks {
+            values: Vec::new(),
+            updated: None,
+        }));
+        let supplier = joysticks.clone();
+        ds.set_joystick_supplier(move || supplier.lock().unwrap().current())
+            .await;
+
+        let (console_tx, _) = broadcast::channel(64);
+        let shared = Arc::new(Shared {
+            safety: ds.safety_keys(),
+            ds: Mutex::new(ds),
+            token,
+            console: StdMutex::new(RobotConsole::new(CONSOLE_CAPACITY)),
+            console_tx,
+            joysticks,
+        });
+
+        let consumer = Arc::downgrade(&shared);
+        shared
+            .ds
+            .lock()
+            .await
+            .set_tcp_consumer(move |packet| {
+                if let Some(shared) = consumer.upgrade() {
+                    shared.console.lock().unwrap().push(&packet);
+                    if let Some(message) = json::console(&packet) {
+                        let _ = shared.console_tx.send(message);
+                    }
+                }
+            })
+            .await;
+
+        Ok(DsServer { shared })
+    }
+
+    /// Serves requests on `addr` until `shutdown` completes, then shuts down the driver station
+    pub async fn serve(self, addr: SocketAddr, shutdown: impl Future<Output = ()>) -> Result<()> {
+        let shared = self.shared;
+        let service = {
+            let shared = shared.clone();
+            make_service_fn(move |_| {
+                let shared = shared.clone();
+                async move {
+                    Ok::<_, Infallible>(service_fn(move |request| {
+                        let shared = shared.clone();
+                        async move { Ok::<_, Infallible>(handle(shared, request).await) }
+                    }))
+                }
+            })
+        };
+
+        let server = Server::try_bind(&addr)
+            .map_err(|e| format_err!("Failed to listen on {}: {}", addr, e))?
+            .serve(service);
+        info!("Listening on {}", server.local_addr());
+        server
+            .with_graceful_shutdown(shutdown)
+            .await
+            .map_err(|e| format_err!("Server failed: {}", e))?;
+
+        let mut ds = shared.ds.lock().await;
+        ds.signal_shutdown();
+        ds.join(Duration::from_secs(1)).await
+    }
+}
+
+fn json_response(status: StatusCode, body: &Value) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header(CONTENT_TYPE, "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap()
+}
+
+fn error_response(status: StatusCode, message: &str) -> Response<Body> {
+    json_response(status, &json::error(message))
+}
+
+/// Returns the percent-decoded value of the `token` query parameter
+fn query_token(request: &Request<Body>) -> Option<String> {
+    let token = request
+        .uri()
+        .query()?
+        .split('&')
+        .find_map(|pair| pair.strip_prefix("token="))?;
+    percent_decode_str(token)
+        .decode_utf8()
+        .ok()
+        .map(String::from)
+}
+
+/// Returns the token of an `Authorization: Bearer <token>` header
+fn bearer_token(request: &Request<Body>) -> Option<&str> {
+    request
+        .headers()
+        .get(AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Bearer ")
+}
+
+async fn handle(shared: Arc<Shared>, request: Request<Body>) -> Response<Body> {
+    let path = request.uri().path().trim_start_matches('/').to_string();
+    match (request.method(), path.as_str()) {
+        (&Method::GET, "status") => json_response(StatusCode::OK, &shared.status().await),
+        (&Method::GET, "console") => json_response(StatusCode::OK, &shared.console()),
+        (&Method::GET, "metrics") => {
+            let metrics = shared.ds.lock().await.metrics().await;
+            Response::builder()
+                .header(CONTENT_TYPE, CONTENT_TYPE_TEXT)
+                .body(Body::from(metrics.to_string()))
+                .unwrap()
+        }
+        (&Method::GET, "ws") => upgrade(shared, request),
+        (&Method::POST, command) if COMMANDS.contains(&command) => {
+            if !shared.authorized(bearer_token(&request)) {
+                return error_response(StatusCode::UNAUTHORIZED, "Missing or incorrect token");
+            }
+            let length = request
+                .headers()
+                .get(CONTENT_LENGTH)
+                .and_then(|length| length.to_str().ok()?.parse::<u64>().ok());
+            if length.is_some_and(|length| length > MAX_BODY as u64) {
+                return error_response(StatusCode::PAYLOAD_TOO_LARGE, "Request body is too large");
+            }
+
+            let body = match read_body(request.into_body()).await {
+                Ok(body) => body,
+                Err(response) => return response,
+            };
+            match Command::from_http(command, &body) {
+                Ok(command) => {
+                    shared.run(command).await;
+                    Response::builder()
+                        .status(StatusCode::NO_CONTENT)
+                        .body(Body::empty())
+                        .unwrap()
+                }
+                Err(e) => error_response(StatusCode::BAD_REQUEST, &e.to_string()),
+            }
+        }
+        _ => error_response(StatusCode::NOT_FOUND, "Not found"),
+    }
+}
+
+/// Reads a request body, stopping as soon as it grows past `MAX_BODY`
+///
+/// Chunked bodies don't have a `Content-Length` to check up front, so the limit is applied to what has been read.
+async fn read_body(mut body: Body) -> std::result::Result<Vec<u8>, Response<Body>> {
+    let mut buf = Vec::new();
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk.map_err(|e| error_response(StatusCode::BAD_REQUEST, &e.to_string()))?;
+        if buf.len() + chunk.len() > MAX_BODY {
+            return Err(error_response(
+                StatusCode::PAYLOAD_TOO_LARGE,
+                "Request body is too large",
+            ));
+        }
+        buf.extend_from_slice(&chunk);
+    }
+    Ok(buf)
+}
+
+/// Completes the WebSocket handshake of `request`, then streams messages over it once the connection is upgraded
+fn upgrade(shared: Arc<Shared>, request: Request<Body>) -> Response<Body> {
+    let authorized = shared.authorized(query_token(&request).as_deref());
+
+    let mut handshake = Request::builder()
+        .method(request.method())
+        .uri(request.uri())
+        .version(request.version());
+    for (name, value) in request.headers() {
+        handshake = handshake.header(name, value);
+    }
+    let response = match create_response(&handshake.body(()).unwrap()) {
+        Ok(response) => response,
+        Err(e) => return error_response(StatusCode::BAD_REQUEST, &e.to_string()),
+    };
+
+    let upgraded = request.into_body().on_upgrade();
+    tokio::spawn(async move {
+        match upgraded.await {
+            Ok(upgraded) => {
+                let ws = WebSocketStream::from_raw_socket(upgraded, Role::Server, None).await;
+                stream(shared, ws, authorized).await;
+            }
+            Err(e) => debug!("WebSocket upgrade failed: {}", e),
+        }
+    });
+
+    let (parts, ()) = response.into_parts();
+    Response::from_parts(parts, Body::empty())
+}
+
+enum Event {
+    Status,
+    Console(Value),
+    Message(Message),
+    Closed,
+}
+
+/// Sends status and console messages over a WebSocket, and runs the commands it sends if it is `authorized`
+async fn stream<S>(shared: Arc<Shared>, ws: WebSocketStream<S>, authorized: bool)
+where
+    S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin,
+{
+    let (mut sink, incoming) = ws.split();
+    let status = shared.ds.lock().await.status();
+    let console = shared
+        .console_tx
+        .subscribe()
+        .filter_map(|message| future::ready(message.ok()));
+    let incoming = incoming
+        .take_while(|message| future::ready(message.is_ok()))
+        .filter_map(|message| future::ready(message.ok()))
+        .map(Event::Message)
+        .chain(stream::once(future::ready(Event::Closed)));
+
+    let mut events = stream::select(
+        stream::select(
+            status.map(|_| Event::Status),
+            tokio::time::interval(TELEMETRY_PERIOD).map(|_| Event::Status),
+        ),
+        stream::select(Box::pin(console.map(Event::Console)), Box::pin(incoming)),
+    );
+
+    while let Some(event) = events.next().await {
+        let reply = match event {
+            Event::Status => Some(shared.status().await),
+            Event::Console(message) => Some(message),
+            Event::Message(Message::Text(text)) if authorized => {
+                match serde_json::from_str::<Command>(&text) {
+                    Ok(command) => {
+                        shared.run(command).await;
+                        None
+                    }
+                    Err(e) => Some(json::error(e)),
+                }
+            }
+            Event::Message(Message::Text(_)) => Some(json::error("Missing or incorrect token")),
+            Event::Message(Message::Close(_)) | Event::Closed => break,
+            Event::Message(_) => None,
+        };
+
+        if let Some(reply) = reply {
+            if sink.send(Message::Text(reply.to_string())).await.is_err() {
+                break;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::proto::udp::outbound::types::tags::UdpTag;
+    use crate::testing::MockRoborio;
+    use crate::Control;
+    use hyper::Client;
+    use tokio::sync::oneshot;
+
+    const TOKEN: &str = "correct-horse-battery";
+
+    #[tokio::test]
+    async fn controls_driver_station() {
+        let mock = MockRoborio::bind_any().await.unwrap();
+        let ds = mock.connected_ds().build_async().unwrap();
+
+        // A port the OS found free, which the server binds again once the listener is dropped
+        let addr = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let server = DsServer::new(ds, TOKEN).await.unwrap();
+        let (shutdown_tx, shutdown) = oneshot::channel::<()>();
+        let served = tokio::spawn(server.serve(addr, async {
+            let _ = shutdown.await;
+        }));
+
+        let client = Client::new();
+        let post = |path: &str, token: Option<&str>, body: &'static str| {
+            let mut request = Request::post(format!("http://{}/{}", addr, path));
+            if let Some(token) = token {
+                request = request.header(AUTHORIZATION, format!("Bearer {}", token));
+            }
+            client.request(request.body(Body::from(body)).unwrap())
+        };
+
+        // Retry until the server is listening
+        let response = loop {
+            match post("enable", Some("wrong"), "").await {
+                Ok(response) => break response,
+                Err(_) => tokio::time::delay_for(Duration::from_millis(10)).await,
+            }
+        };
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let response = post("enable", None, "").await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        // Bodies without a Content-Length are limited as they are read
+        let chunks = vec![
+            Ok::<_, std::io::Error>(vec![b' '; MAX_BODY]),
+            Ok(vec![b' ']),
+        ];
+        let request = Request::post(format!("http://{}/mode", addr))
+            .header(AUTHORIZATION, format!("Bearer {}", TOKEN))
+            .body(Body::wrap_stream(stream::iter(chunks)))
+            .unwrap();
+        let response = client.request(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+        let response = post("launch", Some(TOKEN), "").await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let response = post("mode", Some(TOKEN), r#"{"mode": "fast"}"#)
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let response = post(
+            "joysticks",
+            Some(TOKEN),
+            r#"{"joysticks": [[{"axis": 1, "value": 0.5}]]}"#,
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        let response = post("enable", Some(TOKEN), "").await.unwrap();
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        mock.wait_for_control(|control| {
+            control.control.contains(Control::ENABLED)
+                && control.tags.iter().any(|tag| match tag {
+                    UdpTag::Joysticks(joysticks) => joysticks.axes()[1] == 64,
+                    _ => false,
+                })
+        })
+        .await;
+
+        let response = client
+            .get(format!("http://{}/status", addr).parse().unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let status: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(status["type"], "status");
+        assert_eq!(status["connected"], true);
+
+        let response = post("disable", Some(TOKEN), "").await.unwrap();
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        mock.wait_for_control(|control| !control.control.contains(Control::ENABLED))
+            .await;
+
+        // WebSockets are sent the status when they open, and can only send commands with the token
+        let url = format!("ws://{}/ws", addr);
+        let (mut ws, _) = tokio_tungstenite::connect_async(url.as_str())
+            .await
+            .unwrap();
+        let message = ws.next().await.unwrap().unwrap();
+        let status: Value = serde_json::from_str(message.to_text().unwrap()).unwrap();
+        assert_eq!(status["type"], "status");
+        ws.send(Message::Text(r#"{"command": "enable"}"#.to_string()))
+            .await
+            .unwrap();
+        let error = loop {
+            let message = ws.next().await.unwrap().unwrap();
+            let message: Value = serde_json::from_str(message.to_text().unwrap()).unwrap();
+            if message["type"] == "error" {
+                break message;
+            }
+        };
+        assert_eq!(error["error"], "Missing or incorrect token");
+
+        let url = format!("ws://{}/ws?token={}", addr, TOKEN);
+        let (mut ws, _) = tokio_tungstenite::connect_async(url.as_str())
+            .await
+            .unwrap();
+        ws.send(Message::Text(r#"{"command": "enable"}"#.to_string()))
+            .await
+            .unwrap();
+        mock.wait_for_control(|control| control.control.contains(Control::ENABLED))
+            .await;
+        drop(ws);
+
+        shutdown_tx.send(()).unwrap();
+        served.await.unwrap().unwrap();
+    }
+
+    #[test]
+    fn decodes_query_token() {
+        let request = Request::get("/ws?mode=view&token=a%2Bb%26c%3D+d")
+            .body(Body::empty())
+            .unwrap();
+        assert_eq!(query_token(&request).as_deref(), Some("a+b&c=+d"));
+        let request = Request::get("/ws?token=%FF").body(Body::empty()).unwrap();
+        assert_eq!(query_token(&request), None);
+    }
+
+    #[tokio::test]
+    async fn rejects_short_tokens() {
+        for token in &["", "short"] {
+            let mock = MockRoborio::bind_any().await.unwrap();
+            let ds = mock.connected_ds().build_async().unwrap();
+            assert!(DsServer::new(ds, *token).await.is_err());
+        }
+    }
+}
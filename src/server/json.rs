@@ -0,0 +1,221 @@
+use crate::input::MAX_PORTS;
+use crate::log::DsLogRecord;
+use crate::{DsMode, JoystickValue, Mode, RobotStatus, TcpPacket};
+
+use serde::de::{Deserializer, Error as _};
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+
+/// A request to control the driver station, sent as the body of a `POST` or as a WebSocket message
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "command", rename_all = "snake_case", deny_unknown_fields)]
+pub enum Command {
+    Enable,
+    Disable,
+    Estop,
+    RestartCode,
+    Mode {
+        mode: ModeName,
+    },
+    /// Replaces the values of every joystick port
+    Joysticks {
+        #[serde(deserialize_with = "joystick_ports")]
+        joysticks: Vec<Vec<JsonJoystickValue>>,
+    },
+}
+
+/// Deserializes the values of each joystick port, refusing more ports than the driver station has
+fn joystick_ports<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<Vec<JsonJoystickValue>>, D::Error> {
+    let ports = Vec::<Vec<JsonJoystickValue>>::deserialize(deserializer)?;
+    if ports.len() > MAX_PORTS {
+        return Err(D::Error::custom(format!(
+            "At most {} joystick ports can be sent",
+            MAX_PORTS
+        )));
+    }
+    Ok(ports)
+}
+
+impl Command {
+    /// Parses the body of a `POST` to `/<command>`, which holds the command's fields without its name
+    pub fn from_http(command: &str, body: &[u8]) -> serde_json::Result<Command> {
+        let mut fields = if body.iter().all(u8::is_ascii_whitespace) {
+            Map::new()
+        } else {
+            serde_json::from_slice(body)?
+        };
+        fields.insert("command".to_string(), Value::from(command));
+        serde_json::from_value(Value::Object(fields))
+    }
+}
+
+/// A mode, named as on the command line
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ModeName {
+    Auto,
+    Teleop,
+    Test,
+}
+
+impl From<ModeName> for Mode {
+    fn from(mode: ModeName) -> Mode {
+        match mode {
+            ModeName::Auto => Mode::Autonomous,
+            ModeName::Teleop => Mode::Teleoperated,
+            ModeName::Test => Mode::Test,
+        }
+    }
+}
+
+fn mode_name(mode: Mode) -> &'static str {
+    match mode {
+        Mode::Autonomous => "auto",
+        Mode::Teleoperated => "teleop",
+        Mode::Test => "test",
+    }
+}
+
+/// A joystick value, such as `{"axis": 1, "value": -0.5}`, `{"button": 1, "pressed": true}`, or
+/// `{"pov": 0, "angle": 90}`
+#[derive(Debug, Copy, Clone, PartialEq, Deserialize)]
+#[serde(untagged, deny_unknown_fields)]
+pub enum JsonJoystickValue {
+    Axis { axis: u8, value: f32 },
+    Button { button: u8, pressed: bool },
+    Pov { pov: u8, angle: i16 },
+}
+
+impl From<JsonJoystickValue> for JoystickValue {
+    fn from(value: JsonJoystickValue) -> JoystickValue {
+        match value {
+            JsonJoystickValue::Axis { axis, value } => JoystickValue::Axis {
+                id: axis,
+                value: value.clamp(-1.0, 1.0),
+            },
+            JsonJoystickValue::Button { button, pressed } => JoystickValue::Button {
+                id: button,
+                pressed,
+            },
+            JsonJoystickValue::Pov { pov, angle } => JoystickValue::POV { id: pov, angle },
+        }
+    }
+}
+
+/// Builds the message describing the state of the robot and the link to it
+pub fn status(status: &RobotStatus, telemetry: &DsLogRecord) -> Value {
+    let trace = status.trace;
+    json!({
+        "type": "status",
+        "connected": status.connected,
+        "enabled": status.enabled,
+        "estopped": status.estopped,
+        "mode": mode_name(status.mode),
+        "simulation": status.ds_mode == DsMode::Simulation,
+        "battery_voltage": status.battery_voltage,
+        "discrepancy": status.discrepancy.map(|discrepancy| format!("{:?}", discrepancy.kind)),
+        "trace": {
+            "roborio": trace.is_connected(),
+            "robot_code": trace.is_code_started(),
+            "disabled": trace.is_disabled(),
+            "autonomous": trace.is_autonomous(),
+            "teleop": trace.is_teleop(),
+            "test": trace.is_test(),
+        },
+        "telemetry": {
+            "trip_time_ms": telemetry.trip_time.as_secs_f64() * 1000.0,
+            "packet_loss": telemetry.packet_loss,
+            "cpu": telemetry.cpu,
+            "can_utilization": telemetry.can_utilization,
+            "brownout": telemetry.brownout,
+            "watchdog": telemetry.watchdog,
+        },
+    })
+}
+
+/// Builds the message for output printed or reported by robot code, if the packet has any
+pub fn console(packet: &TcpPacket) -> Option<Value> {
+    match packet {
+        TcpPacket::Stdout(stdout) => Some(json!({
+            "type": "console",
+            "level": "print",
+            "timestamp": stdout.timestamp,
+            "text": stdout.message,
+        })),
+        TcpPacket::ErrorMessage(error) => Some(json!({
+            "type": "console",
+            "level": if error.is_error { "error" } else { "warning" },
+            "timestamp": error.timestamp,
+            "text": error.details,
+            "code": error.code,
+            "location": error.location,
+            "call_stack": error.call_stack,
+        })),
+        TcpPacket::Dummy => None,
+    }
+}
+
+/// Builds the body of an error response, or the message sent over a WebSocket when a command is rejected
+pub fn error(message: impl std::fmt::Display) -> Value {
+    json!({
+        "type": "error",
+        "error": message.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parses_commands() {
+        assert_eq!(Command::from_http("enable", b"").unwrap(), Command::Enable);
+        assert_eq!(
+            Command::from_http("mode", br#"{"mode": "auto"}"#).unwrap(),
+            Command::Mode {
+                mode: ModeName::Auto
+            }
+        );
+        assert!(Command::from_http("mode", br#"{"mode": "autonomous"}"#).is_err());
+        assert!(Command::from_http("launch", b"").is_err());
+
+        let command: Command = serde_json::from_str(
+            r#"{"command": "joysticks", "joysticks": [[{"axis": 1, "value": 2.0}, {"button": 3, "pressed": true}], [{"pov": 0, "angle": 90}]]}"#,
+        )
+        .unwrap();
+        let joysticks = match command {
+            Command::Joysticks { joysticks } => joysticks
+                .into_iter()
+                .map(|port| port.into_iter().map(JoystickValue::from).collect())
+                .collect::<Vec<Vec<_>>>(),
+            _ => panic!("Expected joysticks, got {:?}", command),
+        };
+        assert_eq!(
+            joysticks,
+            vec![
+                vec![
+                    JoystickValue::Axis { id: 1, value: 1.0 },
+                    JoystickValue::Button {
+                        id: 3,
+                        pressed: true
+                    }
+                ],
+                vec![JoystickValue::POV { id: 0, angle: 90 }]
+            ]
+        );
+
+        assert!(
+            Command::from_http("joysticks", br#"{"joysticks": [[], [], [], [], [], []]}"#).is_ok()
+        );
+        let error = Command::from_http(
+            "joysticks",
+            br#"{"joysticks": [[], [], [], [], [], [], []]}"#,
+        )
+        .unwrap_err();
+        assert!(error
+            .to_string()
+            .starts_with("At most 6 joystick ports can be sent"));
+    }
+}
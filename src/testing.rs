@@ -0,0 +1,525 @@
+//! Tools for testing code that uses the driver station without a real robot
+//!
+//! [`MockRoborio`](struct.MockRoborio.html) binds the roboRIO's side of the protocol on localhost. Point a driver
+//! station at it with [`DriverStationBuilder::target`](../struct.DriverStationBuilder.html#method.target) and the same
+//! [`Ports`](../struct.Ports.html), or with [`connected_ds`](struct.MockRoborio.html#method.connected_ds), and it will
+//! answer control packets and record everything the driver station sends.
+//!
+//! This module is only built with the `testing` feature, so add the crate with it to your `[dev-dependencies]`.
+
+use crate::proto::udp::inbound::types::tags::RobotTag;
+use crate::proto::udp::inbound::types::{Status, Trace};
+use crate::proto::udp::outbound::types::Control;
+use crate::{
+    DriverStation, DriverStationBuilder, Ports, Result, UdpControlPacket, UdpResponsePacket,
+};
+
+use byteorder::{BigEndian, WriteBytesExt};
+use failure::format_err;
+use futures::future::{abortable, AbortHandle, Either};
+use futures::stream::{self, Stream, StreamExt};
+use futures_channel::mpsc::{unbounded, UnboundedReceiver, UnboundedSender};
+use log::*;
+use std::sync::atomic::{AtomicU16, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::io::AsyncReadExt;
+use tokio::net::{TcpListener, TcpStream, UdpSocket};
+use tokio::sync::broadcast;
+
+/// Finds a UDP port on localhost that is free at the time of the call
+pub(crate) fn free_port() -> Result<u16> {
+    let socket = std::net::UdpSocket::bind("127.0.0.1:0")
+        .map_err(|e| format_err!("Failed to find a free port: {}", e))?;
+    Ok(socket.local_addr()?.port())
+}
+
+/// A TCP frame received from the driver station
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReceivedTcp {
+    pub id: u8,
+    pub data: Vec<u8>,
+}
+
+/// The contents of the status packets that the mock answers with
+#[derive(Debug, Clone)]
+pub struct MockResponse {
+    pub battery_voltage: f32,
+    /// The trace to report, or None to report running robot code in the commanded mode
+    pub trace: Option<Trace>,
+    /// Extra status bits, such as `Status::BROWNOUT`, added to the commanded mode and enabled state
+    pub status: Status,
+    /// Whether to ask the driver station for the date
+    pub need_date: bool,
+    /// Tags appended to each response, such as CPU usage or CAN metrics
+    pub tags: Vec<RobotTag>,
+    /// Whether to answer control packets at all. Turning this off looks like a lost connection
+    pub respond: bool,
+}
+
+impl Default for MockResponse {
+    fn default() -> MockResponse {
+        MockResponse {
+            battery_voltage: 12.0,
+            trace: None,
+            status: Status::empty(),
+            need_date: false,
+            tags: Vec::new(),
+            respond: true,
+        }
+    }
+}
+
+struct Shared {
+    response: MockResponse,
+    controls: Vec<UdpControlPacket>,
+    tcp: Vec<ReceivedTcp>,
+}
+
+/// An in-process stand in for a roboRIO
+///
+/// The mock listens for control packets on `ports.udp_tx` and TCP connections on `ports.tcp`, and answers each
+/// control packet with a status packet sent to `ports.udp_rx`. The status mirrors the commanded mode and enabled
+/// state, as real robot code would. The tasks run on the tokio runtime the mock was bound on, and stop when it's dropped.
+pub struct MockRoborio {
+    shared: Arc<Mutex<Shared>>,
+    ports: Ports,
+    controls_tx: broadcast::Sender<UdpControlPacket>,
+    tcp_tx: UnboundedSender<Vec<u8>>,
+    tcp_seqnum: AtomicU16,
+    tasks: Vec<AbortHandle>,
+}
+
+impl MockRoborio {
+    /// Binds the roboRIO side of `ports` on localhost and starts answering the driver station
+    pub async fn bind(ports: Ports) -> Result<MockRoborio> {
+        let udp = UdpSocket::bind(("127.0.0.1", ports.udp_tx))
+            .await
+            .map_err(|e| format_err!("Failed to bind UDP port {}: {}", ports.udp_tx, e))?;
+        let tcp = TcpListener::bind(("127.0.0.1", ports.tcp))
+            .await
+            .map_err(|e| format_err!("Failed to bind TCP port {}: {}", ports.tcp, e))?;
+        Ok(MockRoborio::start(udp, tcp, ports))
+    }
+
+    /// Binds the roboRIO side on ports chosen by the OS, so that tests running at the same time never collide
+    ///
+    /// The driver station's own ports are found by briefly binding them, so another process could take them before
+    /// the driver station does, though that is unlikely. [`connected_ds`](#method.connected_ds) returns a builder
+    /// using the ports.
+    pub async fn bind_any() -> Result<MockRoborio> {
+        let udp = UdpSocket::bind("127.0.0.1:0")
+            .await
+            .map_err(|e| format_err!("Failed to bind UDP: {}", e))?;
+        let tcp = TcpListener::bind("127.0.0.1:0")
+            .await
+            .map_err(|e| format_err!("Failed to bind TCP: {}", e))?;
+        let ports = Ports {
+            udp_tx: udp.local_addr()?.port(),
+            udp_rx: free_port()?,
+            tcp: tcp.local_addr()?.port(),
+            sim: free_port()?,
+        };
+        Ok(MockRoborio::start(udp, tcp, ports))
+    }
+
+    fn start(udp: UdpSocket, tcp: TcpListener, ports: Ports) -> MockRoborio {
+        let shared = Arc::new(Mutex::new(Shared {
+            response: MockResponse::default(),
+            controls: Vec::new(),
+            tcp: Vec::new(),
+        }));
+        let (controls_tx, _) = broadcast::channel(256);
+        let (tcp_tx, tcp_rx) = unbounded();
+
+        let (udp_task, udp_handle) = abortable(udp_loop(
+            udp,
+            ports.udp_rx,
+            shared.clone(),
+            controls_tx.clone(),
+        ));
+        let (tcp_task, tcp_handle) = abortable(tcp_loop(tcp, shared.clone(), tcp_rx));
+        tokio::spawn(udp_task);
+        tokio::spawn(tcp_task);
+
+        MockRoborio {
+            shared,
+            ports,
+            controls_tx,
+            tcp_tx,
+            tcp_seqnum: AtomicU16::new(0),
+            tasks: vec![udp_handle, tcp_handle],
+        }
+    }
+
+    /// Returns the ports of the mock, which a driver station has to be given to talk to it
+    pub fn ports(&self) -> Ports {
+        self.ports
+    }
+
+    /// Returns a builder for a driver station talking to the mock, with simulator detection off
+    pub fn connected_ds(&self) -> DriverStationBuilder {
+        DriverStation::builder()
+            .target("127.0.0.1")
+            .ports(self.ports)
+            .simulation_detection(false)
+    }
+
+    /// Returns the response currently being sent
+    pub fn response(&self) -> MockResponse {
+        self.shared.lock().unwrap().response.clone()
+    }
+
+    /// Changes the contents of every status packet sent from now on
+    pub fn set_response(&self, response: MockResponse) {
+        self.shared.lock().unwrap().response = response;
+    }
+
+    /// Modifies the response in place, e.g. `mock.update_response(|r| r.battery_voltage = 6.5)`
+    pub fn update_response(&self, f: impl FnOnce(&mut MockResponse)) {
+        f(&mut self.shared.lock().unwrap().response);
+    }
+
+    /// Returns every control packet received so far
+    pub fn controls(&self) -> Vec<UdpControlPacket> {
+        self.shared.lock().unwrap().controls.clone()
+    }
+
+    /// Returns the most recently received control packet
+    pub fn last_control(&self) -> Option<UdpControlPacket> {
+        self.shared.lock().unwrap().controls.last().cloned()
+    }
+
+    /// Returns a stream of control packets received after this is called
+    pub fn control_packets(&self) -> impl Stream<Item = UdpControlPacket> {
+        stream::unfold(self.controls_tx.subscribe(), |mut rx| async move {
+            loop {
+                match rx.recv().await {
+                    Ok(control) => return Some((control, rx)),
+                    Err(broadcast::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::RecvError::Closed) => return None,
+                }
+            }
+        })
+    }
+
+    /// Waits for a control packet matching `predicate`
+    pub async fn wait_for_control(
+        &self,
+        predicate: impl Fn(&UdpControlPacket) -> bool,
+    ) -> UdpControlPacket {
+        let packets = self.control_packets();
+        futures::pin_mut!(packets);
+        while let Some(control) = packets.next().await {
+            if predicate(&control) {
+                return control;
+            }
+        }
+        unreachable!("The mock's control channel closed while it was still alive")
+    }
+
+    /// Returns every TCP frame received so far
+    pub fn tcp_frames(&self) -> Vec<ReceivedTcp> {
+        self.shared.lock().unwrap().tcp.clone()
+    }
+
+    /// Sends a line of robot code output, as if printed to standard output
+    pub fn send_stdout(&self, timestamp: f32, message: &str) {
+        let mut buf = Vec::new();
+        buf.write_f32::<BigEndian>(timestamp).unwrap();
+        buf.write_u16::<BigEndian>(self.next_seqnum()).unwrap();
+        buf.extend_from_slice(message.as_bytes());
+        self.send_tcp(0x0c, buf);
+    }
+
+    /// Sends an error or warning reported by robot code
+    pub fn send_error(
+        &self,
+        timestamp: f32,
+        code: i32,
+        is_error: bool,
+        details: &str,
+        location: &str,
+        call_stack: &str,
+    ) {
+        let mut buf = Vec::new();
+        buf.write_f32::<BigEndian>(timestamp).unwrap();
+        buf.write_u16::<BigEndian>(self.next_seqnum()).unwrap();
+        buf.write_u16::<BigEndian>(1).unwrap(); // number of occurrences
+        buf.write_i32::<BigEndian>(code).unwrap();
+        buf.push(is_error as u8);
+        for s in &[details, location, call_stack] {
+            buf.write_u16::<BigEndian>(s.len() as u16).unwrap();
+            buf.extend_from_slice(s.as_bytes());
+        }
+        self.send_tcp(0x0b, buf);
+    }
+
+    /// Sends the version of a piece of software or hardware on the robot
+    pub fn send_version(&self, name: &str, version: &str) {
+        let mut buf = vec![0, 0, 0, 0]; // device type, unused, device id
+        buf.push(name.len() as u8);
+        buf.extend_from_slice(name.as_bytes());
+        buf.push(version.len() as u8);
+        buf.extend_from_slice(version.as_bytes());
+        self.send_tcp(0x0a, buf);
+    }
+
+    /// Returns the sequence number for the next stdout or error message
+    fn next_seqnum(&self) -> u16 {
+        self.tcp_seqnum.fetch_add(1, Ordering::Relaxed)
+    }
+
+    /// Sends an arbitrary TCP frame, which is held until the driver station connects
+    pub fn send_tcp(&self, id: u8, data: Vec<u8>) {
+        let mut frame = Vec::new();
+        frame.write_u16::<BigEndian>(data.len() as u16 + 1).unwrap();
+        frame.push(id);
+        frame.extend(data);
+        let _ = self.tcp_tx.unbounded_send(frame);
+    }
+}
+
+impl Drop for MockRoborio {
+    fn drop(&mut self) {
+        for task in &self.tasks {
+            task.abort();
+        }
+    }
+}
+
+/// Encodes a status packet answering `control`
+fn encode_response(control: &UdpControlPacket, response: &MockResponse) -> Vec<u8> {
+    let mode = control.control.bits() & 0b11;
+    let mut status = Status::from_bits_truncate(mode) | response.status;
+    if control.control.contains(Control::ENABLED) {
+        status |= Status::ENABLED;
+    }
+    if control.control.contains(Control::ESTOP) {
+        status |= Status::ESTOP;
+    }
+
+    let trace = response.trace.unwrap_or_else(|| {
+        let running = if !control.control.contains(Control::ENABLED) {
+            Trace::DISABLED
+        } else if mode == Control::AUTO.bits() {
+            Trace::AUTONOMOUS
+        } else if mode == Control::TEST.bits() {
+            Trace::TEST_MODE
+        } else {
+            Trace::TELEOP
+        };
+        Trace::ROBOT_CODE | Trace::IS_ROBORIO | running
+    });
+
+    UdpResponsePacket {
+        seqnum: control.seqnum,
+        status,
+        trace,
+        battery: response.battery_voltage,
+        need_date: response.need_date,
+        tags: response.tags.clone(),
+    }
+    .encode()
+}
+
+async fn udp_loop(
+    mut sock: UdpSocket,
+    reply_port: u16,
+    shared: Arc<Mutex<Shared>>,
+    controls_tx: broadcast::Sender<UdpControlPacket>,
+) {
+    let mut buf = [0; 1500];
+    loop {
+        let (len, from) = match sock.recv_from(&mut buf[..]).await {
+            Ok(received) => received,
+            Err(e) => {
+                debug!("Mock roboRIO failed to receive: {}", e);
+                continue;
+            }
+        };
+
+        let control = match UdpControlPacket::decode(&mut &buf[..len]) {
+            Ok(control) => control,
+            Err(e) => {
+                warn!("Mock roboRIO received an invalid control packet: {}", e);
+                continue;
+            }
+        };
+
+        let reply = {
+            let mut shared = shared.lock().unwrap();
+            shared.controls.push(control.clone());
+            if shared.response.respond {
+                Some(encode_response(&control, &shared.response))
+            } else {
+                None
+            }
+        };
+        let _ = controls_tx.send(control);
+
+        if let Some(reply) = reply {
+            if let Err(e) = sock.send_to(&reply[..], (from.ip(), reply_port)).await {
+                debug!("Mock roboRIO failed to reply: {}", e);
+            }
+        }
+    }
+}
+
+async fn tcp_loop(
+    mut listener: TcpListener,
+    shared: Arc<Mutex<Shared>>,
+    mut outgoing: UnboundedReceiver<Vec<u8>>,
+) {
+    loop {
+        let conn = match listener.accept().await {
+            Ok((conn, _)) => conn,
+            Err(e) => {
+                debug!("Mock roboRIO failed to accept: {}", e);
+                continue;
+            }
+        };
+
+        if let Err(e) = serve_tcp(conn, &shared, &mut outgoing).await {
+            debug!("Mock roboRIO TCP connection closed: {}", e);
+        }
+    }
+}
+
+/// Records frames from the driver station and forwards outgoing frames until the connection closes
+async fn serve_tcp(
+    conn: TcpStream,
+    shared: &Mutex<Shared>,
+    outgoing: &mut UnboundedReceiver<Vec<u8>>,
+) -> Result<()> {
+    use tokio::io::AsyncWriteExt;
+
+    let (read, mut write) = tokio::io::split(conn);
+
+    let frames = stream::unfold(read, |mut read| async move {
+        let frame = async {
+            let len = read.read_u16().await?;
+            let mut buf = vec![0; len as usize];
+            read.read_exact(&mut buf[..]).await?;
+            Ok::<_, std::io::Error>(buf)
+        }
+        .await;
+        frame.ok().map(|frame| (frame, read))
+    });
+    let mut stream = stream::select(
+        Box::pin(frames).map(Either::Left),
+        outgoing.map(Either::Right),
+    );
+
+    while let Some(item) = stream.next().await {
+        match item {
+            Either::Left(frame) => {
+                if let Some((&id, data)) = frame.split_first() {
+                    shared.lock().unwrap().tcp.push(ReceivedTcp {
+                        id,
+                        data: data.to_vec(),
+                    });
+                }
+            }
+            Either::Right(frame) => write.write_all(&frame[..]).await?,
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::tags::{DateTime, Timezone, UdpTag};
+    use crate::{Clock, SystemClock, TcpPacket};
+    use chrono::{NaiveDate, TimeZone, Utc};
+    use futures::future::BoxFuture;
+    use std::time::{Duration, Instant};
+
+    /// Clock stuck on a single date, so the date sent to the robot is known exactly
+    struct FixedClock(chrono::DateTime<Utc>);
+
+    impl Clock for FixedClock {
+        fn now(&self) -> chrono::DateTime<Utc> {
+            self.0
+        }
+
+        fn instant(&self) -> Instant {
+            SystemClock.instant()
+        }
+
+        fn delay_until(&self, deadline: Instant) -> BoxFuture<'static, ()> {
+            SystemClock.delay_until(deadline)
+        }
+    }
+
+    #[tokio::test]
+    async fn talks_to_driver_station() {
+        let mock = MockRoborio::bind_any().await.unwrap();
+        mock.update_response(|r| {
+            r.battery_voltage = 12.5;
+            r.need_date = true;
+        });
+
+        let start = Utc.from_utc_datetime(
+            &NaiveDate::from_ymd_opt(2020, 1, 4)
+                .unwrap()
+                .and_hms_opt(9, 0, 0)
+                .unwrap(),
+        );
+        let mut ds = mock
+            .connected_ds()
+            .timezone("America/Toronto")
+            .clock(FixedClock(start))
+            .build_async()
+            .unwrap();
+        let (stdout_tx, mut stdout_rx) = unbounded();
+        ds.set_tcp_consumer(move |packet| {
+            if let TcpPacket::Stdout(stdout) = packet {
+                let _ = stdout_tx.unbounded_send(stdout.message);
+            }
+        })
+        .await;
+
+        // The date is answered with the simulated clock, followed by the timezone
+        let control = mock
+            .wait_for_control(|c| c.tags.iter().any(|t| matches!(t, UdpTag::DateTime(_))))
+            .await;
+        assert_eq!(
+            control.tags,
+            vec![
+                UdpTag::DateTime(DateTime::from_utc(&start)),
+                UdpTag::Timezone(Timezone::new("America/Toronto"))
+            ]
+        );
+        mock.update_response(|r| r.need_date = false);
+
+        let mut status = ds.status();
+        while !status.next().await.unwrap().connected {}
+        assert_eq!(ds.battery_voltage().await, 12.5);
+
+        ds.enable().await;
+        let control = mock
+            .wait_for_control(|c| c.control.contains(Control::ENABLED))
+            .await;
+        assert_eq!(control.control.bits() & 0b11, Control::AUTO.bits());
+        while !status.next().await.unwrap().trace.is_autonomous() {}
+
+        mock.send_stdout(1.0, "Robot program starting");
+        assert_eq!(stdout_rx.next().await.unwrap(), "Robot program starting");
+
+        ds.set_game_specific_message("LRL").await.unwrap();
+        tokio::time::delay_for(Duration::from_millis(100)).await;
+        assert!(mock.tcp_frames().contains(&ReceivedTcp {
+            id: 0x0e,
+            data: b"LRL".to_vec()
+        }));
+
+        // The last packet sent on the way out disables the robot
+        let (_, res) = futures::join!(
+            mock.wait_for_control(|c| !c.control.contains(Control::ENABLED)),
+            ds.shutdown(Duration::from_secs(1))
+        );
+        res.unwrap();
+    }
+}
@@ -1,5 +1,3 @@
-use bytes::Buf;
-
 /// Function to translate boolean button values into the bytes that the roboRIO expects
 /// Buttons are encoded LSB 0 on the wire. This algorithm was MSB 0 originally, and I didn't feel like translating it properly
 pub(crate) fn to_u8_vec(vec_in: &[bool]) -> Vec<u8> {
@@ -9,10 +7,7 @@ pub(crate) fn to_u8_vec(vec_in: &[bool]) -> Vec<u8> {
         let mut num: u8 = 0;
         for j in i..i + 8 {
             num <<= 1;
-            num |= *match vec_in.get(j) {
-                Some(a) => a,
-                None => &false,
-            } as u8;
+            num |= *vec_in.get(j).unwrap_or(&false) as u8;
         }
         vec.push(num.reverse_bits());
     }
@@ -20,6 +15,16 @@ pub(crate) fn to_u8_vec(vec_in: &[bool]) -> Vec<u8> {
     vec.into_iter().rev().collect()
 }
 
+/// Inverse of `to_u8_vec`, reading `count` buttons from the given bytes
+pub(crate) fn from_u8_vec(bytes: &[u8], count: usize) -> Vec<bool> {
+    (0..count)
+        .map(|i| {
+            // The last byte holds the first 8 buttons
+            let byte = bytes[bytes.len() - 1 - i / 8];
+            byte & (1 << (i % 8)) != 0
+        })
+        .collect()
+}
 
 /// Converts the given team number into a String containing the IP of the roboRIO
 /// Assumes the roboRIO will exist at 10.TE.AM.2
@@ -34,8 +39,7 @@ pub(crate) fn ip_from_team_number(team: u32) -> String {
     }
 }
 
-pub(crate) trait InboundTag {
-    fn chomp(buf: &mut impl Buf) -> crate::Result<Self>
-    where
-        Self: Sized;
+/// Returns the IANA name of the host's timezone, falling back to UTC if it can't be determined
+pub(crate) fn host_timezone() -> String {
+    iana_time_zone::get_timezone().unwrap_or_else(|_| "UTC".to_string())
 }